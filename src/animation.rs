@@ -0,0 +1,162 @@
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, DynamicImage, Frame, RgbImage,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use crate::{error::Error, film::extension};
+
+// whether the path is of an animated gif or png, or a video, made of the frames of all the
+// configs with it
+pub fn is_animated(path: &str) -> bool {
+    matches!(
+        extension(path).as_deref(),
+        Some("gif") | Some("apng") | Some("mp4") | Some("webm") | Some("mkv")
+    )
+}
+
+enum Output {
+    // written at once by finish
+    Frames(Vec<RgbImage>),
+    // streamed into ffmpeg, started by the first frame
+    Video(Option<Child>),
+}
+
+// the frames in order, looping forever in a gif or png
+pub struct Animation {
+    pub path: String,
+    fps: f64,
+    output: Output,
+    count: usize,
+}
+
+#[allow(dead_code)]
+impl Animation {
+    pub fn new(path: &str, fps: f64) -> Self {
+        let output = match extension(path).as_deref() {
+            Some("gif") | Some("apng") => Output::Frames(Vec::new()),
+            _ => Output::Video(None),
+        };
+        Self {
+            path: path.to_string(),
+            fps,
+            output,
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, frame: RgbImage) -> Result<(), Error> {
+        self.count += 1;
+        let result = match &mut self.output {
+            Output::Frames(frames) => {
+                frames.push(frame);
+                Ok(())
+            }
+            Output::Video(child) => {
+                if child.is_none() {
+                    match ffmpeg(&self.path, frame.dimensions(), self.fps) {
+                        Ok(c) => *child = Some(c),
+                        Err(e) => {
+                            let e = format!("ffmpeg: {}", e);
+                            return Err(Error::Animation(self.path.clone(), e));
+                        }
+                    }
+                }
+                let stdin = child.as_mut().unwrap().stdin.as_mut().unwrap();
+                stdin.write_all(frame.as_raw()).map_err(|e| e.to_string())
+            }
+        };
+        result.map_err(|e| Error::Animation(self.path.clone(), e))
+    }
+
+    // what was saved, to be printed by the caller
+    pub fn finish(self) -> Result<String, Error> {
+        let message = format!("save the animation: {}, {} frames", self.path, self.count);
+        let result = match self.output {
+            Output::Frames(frames) if frames.is_empty() => Ok(()),
+            Output::Frames(frames) => match extension(&self.path).as_deref() {
+                Some("gif") => save_gif(&self.path, frames, self.fps),
+                _ => save_apng(&self.path, frames, self.fps),
+            },
+            Output::Video(None) => Ok(()),
+            Output::Video(Some(mut child)) => {
+                // closes the input
+                drop(child.stdin.take());
+                match child.wait() {
+                    Ok(status) if status.success() => Ok(()),
+                    Ok(status) => Err(format!("ffmpeg {}", status)),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+        };
+        result
+            .map(|_| message)
+            .map_err(|e| Error::Animation(self.path, e))
+    }
+}
+
+// the raw frames from stdin, the codec chosen by ffmpeg for the extension
+fn ffmpeg(path: &str, (width, height): (u32, u32), fps: f64) -> std::io::Result<Child> {
+    Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+        .args(["-s", &format!("{}x{}", width, height)])
+        .args(["-r", &fps.to_string()])
+        .args(["-i", "-"])
+        // yuv420p needs an even size
+        .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+}
+
+fn save_gif(path: &str, frames: Vec<RgbImage>, fps: f64) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = GifEncoder::new(BufWriter::new(file));
+    encoder
+        .set_repeat(Repeat::Infinite)
+        .map_err(|e| e.to_string())?;
+    let delay = Duration::try_from_secs_f64(1.0 / fps).map_err(|e| e.to_string())?;
+    let delay = Delay::from_saturating_duration(delay);
+    encoder
+        .encode_frames(
+            frames.into_iter().map(|img| {
+                Frame::from_parts(DynamicImage::ImageRgb8(img).into_rgba8(), 0, 0, delay)
+            }),
+        )
+        .map_err(|e| e.to_string())
+}
+
+fn save_apng(path: &str, frames: Vec<RgbImage>, fps: f64) -> Result<(), String> {
+    let (width, height) = frames[0].dimensions();
+    if frames.iter().any(|img| img.dimensions() != (width, height)) {
+        return Err("the frames are not of the same size".to_string());
+    }
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(frames.len() as u32, 0)
+        .map_err(|e| e.to_string())?;
+    // in milliseconds
+    encoder
+        .set_frame_delay(
+            (1000.0 / fps).round().clamp(1.0, u16::MAX as f64) as u16,
+            1000,
+        )
+        .map_err(|e| e.to_string())?;
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    for img in frames {
+        writer
+            .write_image_data(img.as_raw())
+            .map_err(|e| e.to_string())?;
+    }
+    writer.finish().map_err(|e| e.to_string())
+}
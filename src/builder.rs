@@ -0,0 +1,247 @@
+use crate::{
+    element::Color,
+    error::Error,
+    light::Light,
+    material::{Emission, Falloff, Material, Profile},
+    medium::Medium,
+    render::{Aa, Engine, Entity, Fresnel, Integrator, Mode, Sampler, Scene, Visibility},
+    shapes::Shape,
+};
+
+// an entity made without a config, like
+// Entity::builder().shape(Circle::new(0.5, 0.5, 0.1)).emissive(Color::grey(2.0)).build()
+// the materials given are mixed
+pub struct EntityBuilder {
+    shape: Option<Box<dyn Shape + Sync>>,
+    materials: Vec<Material>,
+    profile: Profile,
+    falloff: Falloff,
+    visibility: Visibility,
+    layer: Option<String>,
+    back: Option<Material>,
+    intensity: f64,
+}
+
+impl Entity {
+    pub fn builder() -> EntityBuilder {
+        EntityBuilder {
+            shape: None,
+            materials: Vec::new(),
+            profile: Profile::Uniform,
+            falloff: Falloff::None,
+            visibility: Visibility::default(),
+            layer: None,
+            back: None,
+            intensity: 1.0,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl EntityBuilder {
+    pub fn shape<S: Shape + Sync + 'static>(mut self, shape: S) -> Self {
+        self.shape = Some(Box::new(shape));
+        self
+    }
+
+    pub fn material(mut self, material: Material) -> Self {
+        self.materials.push(material);
+        self
+    }
+
+    pub fn emissive(self, color: Color) -> Self {
+        self.emission(Emission::Uniform(color))
+    }
+
+    pub fn emission(self, emission: Emission) -> Self {
+        self.material(Material::Emitter(emission))
+    }
+
+    pub fn diffuse(self, albedo: Color) -> Self {
+        self.material(Material::Diffuse(albedo))
+    }
+
+    pub fn mirror(self, reflectivity: f64, roughness: f64) -> Self {
+        self.material(Material::Mirror {
+            reflectivity,
+            roughness,
+        })
+    }
+
+    // no dispersion, roughness, absorption or gradient, Entity::material for them
+    pub fn dielectric(self, eta: f64) -> Self {
+        self.material(Material::Dielectric {
+            eta,
+            dispersion: 0.0,
+            roughness: 0.0,
+            absorption: Color::black(),
+            grin: None,
+        })
+    }
+
+    pub fn absorber(self, absorption: Color) -> Self {
+        self.material(Material::Absorber(absorption))
+    }
+
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn falloff(mut self, falloff: Falloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    pub fn layer(mut self, layer: &str) -> Self {
+        self.layer = Some(layer.to_string());
+        self
+    }
+
+    // of the emission of the materials, the ones before and after it
+    pub fn intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    // of the back of an open shape, not mixed with the others
+    pub fn back(mut self, material: Material) -> Self {
+        self.back = Some(material);
+        self
+    }
+
+    pub fn build(mut self) -> Result<Entity, Error> {
+        let shape = self
+            .shape
+            .ok_or_else(|| Error::Invalid("an entity needs a shape".to_string()))?;
+        let material = if self.materials.len() == 1 {
+            self.materials.remove(0)
+        } else {
+            Material::Mix(self.materials)
+        };
+        let material = material.with_intensity(self.intensity);
+        Ok(Entity {
+            aabb: shape.aabb(),
+            shape,
+            material,
+            profile: self.profile,
+            falloff: self.falloff,
+            visibility: self.visibility,
+            layer: self.layer,
+            back: self.back.map(|m| m.with_intensity(self.intensity)),
+        })
+    }
+}
+
+// a scene made without a config, the settings not given are the defaults of Scene::new
+pub struct SceneBuilder {
+    entities: Vec<Entity>,
+    lights: Vec<Box<dyn Light + Sync>>,
+    engine: Engine,
+    medium: Option<Medium>,
+    mode: Mode,
+    fresnel: Fresnel,
+    sampler: Sampler,
+    aa: Aa,
+    nee: bool,
+    integrator: Integrator,
+    seed: Option<u64>,
+}
+
+impl Scene {
+    pub fn builder() -> SceneBuilder {
+        SceneBuilder {
+            entities: Vec::new(),
+            lights: Vec::new(),
+            engine: Engine::default(),
+            medium: None,
+            mode: Mode::default(),
+            fresnel: Fresnel::default(),
+            sampler: Sampler::default(),
+            aa: Aa::default(),
+            nee: false,
+            integrator: Integrator::default(),
+            seed: None,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl SceneBuilder {
+    pub fn entity(mut self, entity: Entity) -> Self {
+        self.entities.push(entity);
+        self
+    }
+
+    pub fn light<L: Light + Sync + 'static>(mut self, light: L) -> Self {
+        self.lights.push(Box::new(light));
+        self
+    }
+
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    pub fn medium(mut self, medium: Medium) -> Self {
+        self.medium = Some(medium);
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn fresnel(mut self, fresnel: Fresnel) -> Self {
+        self.fresnel = fresnel;
+        self
+    }
+
+    pub fn sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    pub fn aa(mut self, aa: Aa) -> Self {
+        self.aa = aa;
+        self
+    }
+
+    pub fn nee(mut self, nee: bool) -> Self {
+        self.nee = nee;
+        self
+    }
+
+    // the photon map is built by the renderer
+    pub fn integrator(mut self, integrator: Integrator) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn build(self) -> Scene {
+        Scene {
+            lights: self.lights,
+            engine: self.engine,
+            medium: self.medium,
+            mode: self.mode,
+            fresnel: self.fresnel,
+            sampler: self.sampler,
+            aa: self.aa,
+            nee: self.nee,
+            integrator: self.integrator,
+            seed: self.seed,
+            ..Scene::new(self.entities)
+        }
+    }
+}
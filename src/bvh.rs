@@ -0,0 +1,254 @@
+use crate::shapes::Aabb;
+
+// the most items in a leaf
+const LEAF_SIZE: usize = 2;
+
+enum Node {
+    Leaf {
+        aabb: Aabb,
+        items: Vec<usize>,
+    },
+    Inner {
+        aabb: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl Node {
+    fn aabb(&self) -> &Aabb {
+        match self {
+            Node::Leaf { aabb, .. } | Node::Inner { aabb, .. } => aabb,
+        }
+    }
+}
+
+// a bounding volume hierarchy over the boxes of items, the items without a box
+// are tested by every ray
+pub struct Bvh {
+    nodes: Vec<Node>,
+    unbounded: Vec<usize>,
+}
+
+#[allow(dead_code)]
+impl Bvh {
+    pub fn new(boxes: &[Option<Aabb>]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            unbounded: Vec::new(),
+        };
+        let mut bounded: Vec<(usize, Aabb)> = Vec::new();
+        for (i, b) in boxes.iter().enumerate() {
+            match b {
+                Some(b) => bounded.push((i, *b)),
+                None => bvh.unbounded.push(i),
+            }
+        }
+        if !bounded.is_empty() {
+            bvh.build(bounded);
+        }
+        bvh
+    }
+
+    // split at the median of the centers along the longer side, returns the index of the node
+    fn build(&mut self, mut items: Vec<(usize, Aabb)>) -> usize {
+        let aabb = items
+            .iter()
+            .map(|(_, b)| *b)
+            .reduce(|a, b| a.union(&b))
+            .unwrap();
+        if items.len() <= LEAF_SIZE {
+            self.nodes.push(Node::Leaf {
+                aabb,
+                items: items.into_iter().map(|(i, _)| i).collect(),
+            });
+            return self.nodes.len() - 1;
+        }
+        let center = |b: &Aabb, x_axis: bool| {
+            if x_axis {
+                b.min.0 + b.max.0
+            } else {
+                b.min.1 + b.max.1
+            }
+        };
+        let x_axis = aabb.max.0 - aabb.min.0 > aabb.max.1 - aabb.min.1;
+        items.sort_by(|(_, a), (_, b)| center(a, x_axis).total_cmp(&center(b, x_axis)));
+        let right = items.split_off(items.len() / 2);
+        let left = self.build(items);
+        let right = self.build(right);
+        self.nodes.push(Node::Inner { aabb, left, right });
+        self.nodes.len() - 1
+    }
+
+    // the nearest of the hits found by hit(item, t_max), which returns the hit with its ray
+    // parameter if it's before t_max. boxes beyond the nearest hit so far are skipped
+    pub fn nearest<T, F>(
+        &self,
+        p: (f64, f64),
+        d: (f64, f64),
+        t_max: f64,
+        mut hit: F,
+    ) -> Option<(T, f64)>
+    where
+        F: FnMut(usize, f64) -> Option<(T, f64)>,
+    {
+        let mut best: Option<(T, f64)> = None;
+        let mut test = |i: usize, best: &mut Option<(T, f64)>| {
+            let t_max = best.as_ref().map_or(t_max, |(_, t)| *t);
+            if let Some((item, t)) = hit(i, t_max) {
+                if t < t_max {
+                    *best = Some((item, t));
+                }
+            }
+        };
+        for &i in &self.unbounded {
+            test(i, &mut best);
+        }
+        let mut stack: Vec<usize> = Vec::new();
+        if !self.nodes.is_empty() {
+            // the root is built last
+            stack.push(self.nodes.len() - 1);
+        }
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            let entry = match node.aabb().entry(p, d) {
+                Some(t) => t,
+                None => continue,
+            };
+            if entry > best.as_ref().map_or(t_max, |(_, t)| *t) {
+                continue;
+            }
+            match node {
+                Node::Leaf { items, .. } => {
+                    for &i in items {
+                        test(i, &mut best);
+                    }
+                }
+                Node::Inner { left, right, .. } => {
+                    stack.push(*right);
+                    stack.push(*left);
+                }
+            }
+        }
+        best
+    }
+
+    // nearest for 4 rays from p, hit(item, t_max) tests the item against all of them at once.
+    // a box is visited when any ray enters it before its own nearest hit
+    pub fn nearest4<T, F>(
+        &self,
+        p: (f64, f64),
+        d: [(f64, f64); 4],
+        t_max: f64,
+        mut hit: F,
+    ) -> [Option<(T, f64)>; 4]
+    where
+        F: FnMut(usize, [f64; 4]) -> [Option<(T, f64)>; 4],
+    {
+        let mut best: [Option<(T, f64)>; 4] = std::array::from_fn(|_| None);
+        let bound = |best: &[Option<(T, f64)>; 4]| {
+            std::array::from_fn(|j| best[j].as_ref().map_or(t_max, |(_, t)| *t))
+        };
+        let mut test = |i: usize, best: &mut [Option<(T, f64)>; 4]| {
+            let t_max: [f64; 4] = bound(best);
+            for (j, h) in hit(i, t_max).into_iter().enumerate() {
+                if let Some((item, t)) = h {
+                    if t < t_max[j] {
+                        best[j] = Some((item, t));
+                    }
+                }
+            }
+        };
+        for &i in &self.unbounded {
+            test(i, &mut best);
+        }
+        let mut stack: Vec<usize> = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(self.nodes.len() - 1);
+        }
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            let t_max: [f64; 4] = bound(&best);
+            let visit = (0..4).any(|j| node.aabb().entry(p, d[j]).is_some_and(|t| t <= t_max[j]));
+            if !visit {
+                continue;
+            }
+            match node {
+                Node::Leaf { items, .. } => {
+                    for &i in items {
+                        test(i, &mut best);
+                    }
+                }
+                Node::Inner { left, right, .. } => {
+                    stack.push(*right);
+                    stack.push(*left);
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::{Circle, Plane, Shape};
+
+    // a grid of circles, with a plane without a box below them
+    fn shapes() -> Vec<Box<dyn Shape + Sync>> {
+        let mut shapes: Vec<Box<dyn Shape + Sync>> = (0..25)
+            .map(|i| {
+                let (x, y) = ((i % 5) as f64 * 0.2 + 0.1, (i / 5) as f64 * 0.2 + 0.1);
+                Box::new(Circle::new(x, y, 0.03 + 0.01 * (i % 4) as f64)) as Box<dyn Shape + Sync>
+            })
+            .collect();
+        shapes.push(Box::new(Plane {
+            px: 0.0,
+            py: 1.2,
+            nx: 0.0,
+            ny: -1.0,
+        }));
+        shapes
+    }
+
+    fn linear(shapes: &[Box<dyn Shape + Sync>], p: (f64, f64), d: (f64, f64)) -> Option<usize> {
+        shapes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, shape)| Some((i, shape.nearest(p, d, f64::INFINITY)?.1)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    #[test]
+    fn nearest_is_the_one_of_a_linear_scan() {
+        let shapes = shapes();
+        let bvh = Bvh::new(&shapes.iter().map(|shape| shape.aabb()).collect::<Vec<_>>());
+        for k in 0..200 {
+            let a = k as f64 * 0.731;
+            let p = ((k % 7) as f64 * 0.15, (k % 11) as f64 * 0.09);
+            let d = (a.cos(), a.sin());
+            let hit = bvh.nearest(p, d, f64::INFINITY, |i, t_max| {
+                let (_, t) = shapes[i].nearest(p, d, t_max)?;
+                Some((i, t))
+            });
+            assert_eq!(hit.map(|(i, _)| i), linear(&shapes, p, d), "ray {}", k);
+            let ds = [0.0, 0.4, 1.9, 3.1].map(|b| ((a + b).cos(), (a + b).sin()));
+            let hits = bvh.nearest4(p, ds, f64::INFINITY, |i, t_max| {
+                std::array::from_fn(|j| {
+                    let (_, t) = shapes[i].nearest(p, ds[j], t_max[j])?;
+                    Some((i, t))
+                })
+            });
+            for j in 0..4 {
+                assert_eq!(
+                    hits[j].as_ref().map(|(i, _)| *i),
+                    linear(&shapes, p, ds[j]),
+                    "ray {} of {}",
+                    j,
+                    k
+                );
+            }
+        }
+    }
+}
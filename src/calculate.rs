@@ -2,4 +2,19 @@ pub fn distance((p1x, p1y): (f64, f64), (p2x, p2y): (f64, f64)) -> f64 {
     let dx = p1x - p2x;
     let dy = p1y - p2y;
     (dx * dx + dy * dy).sqrt()
-}
\ No newline at end of file
+}
+
+pub fn closest_on_segment(
+    (px, py): (f64, f64),
+    (ax, ay): (f64, f64),
+    (bx, by): (f64, f64),
+) -> (f64, f64) {
+    let abx = bx - ax;
+    let aby = by - ay;
+    let len2 = abx * abx + aby * aby;
+    if len2 == 0.0 {
+        return (ax, ay);
+    }
+    let h = (((px - ax) * abx + (py - ay) * aby) / len2).clamp(0.0, 1.0);
+    (ax + abx * h, ay + aby * h)
+}
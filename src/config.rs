@@ -1,35 +1,1387 @@
-use crate::{element::Color, render::Entity, shapes::*};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Read,
+    mem,
+    path::Path,
+};
+
+use crate::{
+    animation::is_animated,
+    debug::DebugShapes,
+    element::Color,
+    error::Error,
+    expr,
+    film::{extension, Dither, ToneMap},
+    light::{Light, PointLight, SpotLight},
+    mask::ImageMask,
+    material::{Background, Emission, Falloff, Grin, Material, Profile},
+    medium::{Density, Medium},
+    noise::Perlin,
+    post::{Bloom, Denoise, Finish},
+    render::{
+        Aa, Adaptive, Ao, Camera, Engine, Entity, Fit, Fresnel, Integrator, Mode, Progressive,
+        RenderSettings, Sampler, Scene, Visibility, World,
+    },
+    ron,
+    shapes::*,
+    text,
+    validate::validate,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
+    // to pick the config by, with --only and --skip
+    #[serde(default)]
+    pub name: Option<String>,
     pub enable: bool,
     pub out: String,
+    // the image of out rendered again when it is there already, skipped without
+    #[serde(default = "default_overwrite")]
+    pub overwrite: bool,
+    // the heatmap of the samples of every pixel and of the noise left in it, side by side
+    #[serde(default)]
+    pub out_stats: Option<String>,
+    // the rays, the depth of the paths, the total internal reflections and the tests of every
+    // kind of shape of the render, printed after it. of the renders at the same time together
+    #[serde(default)]
+    pub stats: bool,
+    // smaller images of the same render, resized from it
+    #[serde(default)]
+    pub pyramid: Vec<Level>,
+    // the aovs of render::aovs: the normal, the distance and the entity of the nearest surface
+    #[serde(default)]
+    pub out_normal: Option<String>,
+    #[serde(default)]
+    pub out_depth: Option<String>,
+    #[serde(default)]
+    pub out_id: Option<String>,
+    // the ambient occlusion, when there is ao
+    #[serde(default)]
+    pub out_ao: Option<String>,
+    #[serde(default)]
+    pub ao: Option<Ao>,
+    // an image for every layer of the entities, with the name of the layer in place of {layer}
+    // or before the extension. out is then the sum of them, see render::Scene::layer
+    #[serde(default)]
+    pub out_layers: Option<String>,
     pub width: u32,
     pub height: u32,
+    // of the world in the image, instead of the shorter edge of it being 1 long. see render::World
+    #[serde(default)]
+    pub world_width: Option<f64>,
+    #[serde(default)]
+    pub world_height: Option<f64>,
+    // of the world in an image of another aspect
+    #[serde(default)]
+    pub fit: Fit,
     pub stratification: u32,
     pub max_depth: u32,
+    #[serde(default = "default_tile")]
+    pub tile: u32,
+    #[serde(default)]
+    pub engine: Engine,
+    #[serde(default)]
+    pub mode: Mode,
+    // the outlines of the shapes, their normals and the emitters, see debug::draw
+    #[serde(default)]
+    pub debug_shapes: Option<DebugShapes>,
+    // where the image is in the world, see render::Camera
+    #[serde(default)]
+    pub camera: Option<Camera>,
+    #[serde(default)]
+    pub fresnel: Fresnel,
+    #[serde(default)]
+    pub sampler: Sampler,
+    #[serde(default)]
+    pub aa: Aa,
+    // next event estimation, for small emitters
+    #[serde(default)]
+    pub nee: bool,
+    #[serde(default)]
+    pub integrator: Integrator,
+    // emitted for the photon map
+    #[serde(default = "default_photons")]
+    pub photons: u32,
+    // of the photons gathered around a point
+    #[serde(default = "default_photon_radius")]
+    pub photon_radius: f64,
     pub scenes: Vec<EntityJson>,
+    #[serde(default)]
+    pub lights: Vec<LightJson>,
+    #[serde(default)]
+    pub medium: Option<MediumJson>,
+    // the light of the rays hitting nothing, black without. seen along the paths, not by the
+    // photon map
+    #[serde(default)]
+    pub background: Option<BackgroundJson>,
+    // render in passes, saving the image after each
+    #[serde(default)]
+    pub progressive: Option<Progressive>,
+    // of the render, then the samples taken are saved. in passes like progressive, not adaptive
+    #[serde(default)]
+    pub max_seconds: Option<f64>,
+    // more samples for the noisy pixels, instead of the stratification for all. not progressive
+    #[serde(default)]
+    pub adaptive: Option<Adaptive>,
+    // the same image for every render, random without
+    #[serde(default)]
+    pub seed: Option<u64>,
+    // the factor of the colors before they are clamped into the image
+    #[serde(default = "default_exposure")]
+    pub exposure: f64,
+    // of the noise of the render, before the bloom
+    #[serde(default)]
+    pub denoise: Option<Denoise>,
+    // glow around the bright pixels, before the exposure
+    #[serde(default)]
+    pub bloom: Option<Bloom>,
+    // after the exposure
+    #[serde(default)]
+    pub tone_map: ToneMap,
+    // aberration, vignette and grain, after the tone map
+    #[serde(default)]
+    pub finish: Option<Finish>,
+    // of the encoding, 1 for the linear values. the srgb curve without
+    #[serde(default)]
+    pub gamma: Option<f64>,
+    // of the channels, 8 or 16. not for the float images
+    #[serde(default = "default_bit_depth")]
+    pub bit_depth: u8,
+    // of the 8 bit images, against the banding of the smooth gradients
+    #[serde(default)]
+    pub dither: Dither,
+    // of the animated gif or png, or the video, the configs with the same out are its frames
+    #[serde(default = "default_fps")]
+    pub fps: f64,
+    // expanded into a config for every frame by expand
+    #[serde(default)]
+    pub animation: Option<AnimationJson>,
+    // numbers, or expressions of the others, used as $name in the expressions. taken by expand
+    #[serde(default)]
+    pub vars: serde_json::Map<String, Value>,
+    // files of entities added to the scenes, merged by include
+    #[serde(default)]
+    pub include: Option<Value>,
+    // entities or shapes, used by {"use": name} in their place. taken by expand
+    #[serde(default)]
+    pub definitions: serde_json::Map<String, Value>,
+}
+
+#[allow(dead_code)]
+impl Config {
+    pub fn settings(&self) -> RenderSettings {
+        RenderSettings {
+            width: self.width,
+            height: self.height,
+            stratification: self.stratification,
+            max_depth: self.max_depth,
+            tile: self.tile,
+            adaptive: self.adaptive,
+            camera: self.camera,
+            world: match (self.world_width, self.world_height) {
+                (None, None) => None,
+                (width, height) => Some(World {
+                    width,
+                    height,
+                    fit: self.fit,
+                }),
+            },
+        }
+    }
+
+    // {name} in the paths of the images by the name
+    pub fn name_outs(&mut self, name: &str) {
+        self.out = name_out(&self.out, name);
+        for out in self.other_outs_mut() {
+            *out = name_out(out, name);
+        }
+    }
+
+    // the paths of the images besides out, the ones that are set
+    pub fn other_outs_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        [
+            &mut self.out_stats,
+            &mut self.out_normal,
+            &mut self.out_depth,
+            &mut self.out_id,
+            &mut self.out_ao,
+            &mut self.out_layers,
+        ]
+        .into_iter()
+        .filter_map(|out| out.as_mut())
+        .chain(self.pyramid.iter_mut().map(|level| &mut level.out))
+    }
+
+    // the exposure and the tone map the film is saved by, already applied with a finish
+    pub fn look(&self) -> (f64, ToneMap) {
+        match self.finish {
+            Some(_) => (1.0, ToneMap::Clamp),
+            None => (self.exposure, self.tone_map),
+        }
+    }
+
+    // the passes of the render, with max_seconds too: the time of progressive is cut to it
+    pub fn passes(&self) -> Option<Progressive> {
+        let max = match self.max_seconds {
+            Some(max) => max,
+            None => return self.progressive,
+        };
+        let seconds = self.progressive.and_then(|p| p.seconds);
+        Some(Progressive {
+            seconds: Some(seconds.map_or(max, |s| s.min(max))),
+        })
+    }
+
+    // the scene of the config, its entities and lights taken
+    pub fn scene(&mut self, seed: Option<u64>) -> Result<Scene, Error> {
+        let entities = mem::take(&mut self.scenes)
+            .into_iter()
+            .map(|e| e.get_entity())
+            .collect::<Result<_, _>>()?;
+        Ok(Scene {
+            lights: mem::take(&mut self.lights)
+                .into_iter()
+                .map(|l| l.get_light())
+                .collect::<Result<_, _>>()?,
+            engine: self.engine,
+            medium: self.medium.take().map(|m| m.get_medium()),
+            background: self.background.take().map(get_background).transpose()?,
+            mode: self.mode,
+            fresnel: self.fresnel,
+            sampler: self.sampler,
+            aa: self.aa,
+            nee: self.nee,
+            integrator: self.integrator,
+            seed: seed.or(self.seed),
+            ..Scene::new(entities)
+        })
+    }
+}
+
+fn default_tile() -> u32 {
+    32
+}
+
+fn default_overwrite() -> bool {
+    true
+}
+
+fn default_exposure() -> f64 {
+    1.0
+}
+
+fn default_bit_depth() -> u8 {
+    8
+}
+
+fn default_fps() -> f64 {
+    24.0
+}
+
+fn default_photons() -> u32 {
+    100_000
+}
+
+fn default_photon_radius() -> f64 {
+    0.01
+}
+
+// the configs in the file as json values
+pub fn read(path: &Path) -> Result<Vec<Value>, Error> {
+    serde_json::from_value(parse(path)?)
+        .map_err(|e| Error::Parse(path.display().to_string(), e.to_string()))
+}
+
+// the configs into the file, in the format of its extension like read. the comments are lost
+pub fn write(path: &Path, values: &[Value]) -> Result<(), Error> {
+    let name = path.display().to_string();
+    let list = Value::Array(values.to_vec());
+    let text = match extension(&name).as_deref() {
+        Some("ron") => ron::to_string(&list),
+        _ => {
+            serde_json::to_string_pretty(&list)
+                .map_err(|e| Error::Parse(name.clone(), e.to_string()))?
+                + "\n"
+        }
+    };
+    fs::write(path, text).map_err(|e| Error::Io(name, e))
+}
+
+// the first frame of the config in the json text, or of the first in a list of them. without a
+// file, entities can't be included
+pub fn from_str(json: &str) -> Result<Config, Error> {
+    let value = match serde_json::from_str(&strip_json(json)) {
+        Ok(Value::Array(mut list)) if !list.is_empty() => list.remove(0),
+        Ok(value) => value,
+        Err(e) => return Err(Error::Parse("the config".to_string(), e.to_string())),
+    };
+    expand(value)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Invalid("the config has no frames".to_string()))
+}
+
+// the file as a json value, by the extension: ron, or json with comments and trailing commas
+fn parse(path: &Path) -> Result<Value, Error> {
+    let name = path.display().to_string();
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| Error::Io(name.clone(), e))?;
+    match extension(&name).as_deref() {
+        Some("ron") => ron::parse(&text),
+        _ => serde_json::from_str(&strip_json(&text)).map_err(|e| e.to_string()),
+    }
+    .map_err(|e| Error::Parse(name, e))
+}
+
+// the json without // and /* */ comments and the commas before ] and }, out of the strings.
+// the comments become spaces, so that the errors keep their lines and columns
+fn strip_json(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('"', _) => {
+                result.push('"');
+                i += 1;
+                while i < chars.len() {
+                    result.push(chars[i]);
+                    i += 1;
+                    match chars[i - 1] {
+                        '\\' if i < chars.len() => {
+                            result.push(chars[i]);
+                            i += 1;
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            ('/', Some('/')) => {
+                while i < chars.len() && chars[i] != '\n' {
+                    result.push(' ');
+                    i += 1;
+                }
+            }
+            ('/', Some('*')) => {
+                let end = (i + 2..chars.len())
+                    .find(|&j| chars[j] == '*' && chars.get(j + 1) == Some(&'/'))
+                    .map_or(chars.len(), |j| j + 2);
+                for c in &chars[i..end] {
+                    result.push(if *c == '\n' { '\n' } else { ' ' });
+                }
+                i = end;
+            }
+            (',', _) => {
+                // the next character that is not a space or in a comment
+                let mut j = i + 1;
+                loop {
+                    match (chars.get(j), chars.get(j + 1)) {
+                        (Some(c), _) if c.is_whitespace() => j += 1,
+                        (Some('/'), Some('/')) => {
+                            while j < chars.len() && chars[j] != '\n' {
+                                j += 1;
+                            }
+                        }
+                        (Some('/'), Some('*')) => {
+                            j = (j + 2..chars.len())
+                                .find(|&k| chars[k] == '*' && chars.get(k + 1) == Some(&'/'))
+                                .map_or(chars.len(), |k| k + 2);
+                        }
+                        _ => break,
+                    }
+                }
+                let trailing = matches!(chars.get(j), Some(']') | Some('}'));
+                result.push(if trailing { ' ' } else { ',' });
+                i += 1;
+            }
+            (c, _) => {
+                result.push(c);
+                i += 1;
+            }
+        }
+    }
+    result
+}
+
+// the deepest include, deeper ones are taken as a cycle
+const MAX_INCLUDE_DEPTH: u32 = 32;
+
+// the entities of the files in "include", a path or a list of them, relative to the directory
+// of the config, appended to the scenes. an entity of the scenes can be an include too
+pub fn include(mut value: Value, dir: &Path) -> Result<Value, Error> {
+    let object = match value.as_object_mut() {
+        Some(object) => object,
+        None => return Ok(value),
+    };
+    let mut scenes = match object.remove("scenes") {
+        Some(Value::Array(list)) => list,
+        Some(_) => {
+            return Err(Error::Config(vec![
+                "/scenes: should be a list of entities".to_string()
+            ]))
+        }
+        None => Vec::new(),
+    };
+    if let Some(files) = object.remove("include").filter(|f| !f.is_null()) {
+        scenes.push(serde_json::json!({ "include": files }));
+    }
+    object.insert(
+        "scenes".to_string(),
+        Value::Array(include_entities(scenes, dir, 0)?),
+    );
+    Ok(value)
+}
+
+fn include_entities(list: Vec<Value>, dir: &Path, depth: u32) -> Result<Vec<Value>, Error> {
+    let mut result = Vec::new();
+    for item in list {
+        let files = match item.get("include") {
+            Some(Value::String(file)) => vec![file.clone()],
+            Some(Value::Array(files)) => files
+                .iter()
+                .map(|f| match f {
+                    Value::String(file) => Ok(file.clone()),
+                    _ => Err(Error::Config(vec![format!(
+                        "/include: {} should be a path",
+                        f
+                    )])),
+                })
+                .collect::<Result<_, _>>()?,
+            Some(files) => {
+                return Err(Error::Config(vec![format!(
+                    "/include: {} should be a path or a list of them",
+                    files
+                )]))
+            }
+            None => {
+                result.push(item);
+                continue;
+            }
+        };
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(Error::Config(vec![format!(
+                "/include: deeper than {}, in a cycle?",
+                MAX_INCLUDE_DEPTH
+            )]));
+        }
+        for file in files {
+            let path = dir.join(&file);
+            let value = parse(&path)?;
+            // a list of entities, or one
+            let list = match value {
+                Value::Array(list) => list,
+                value => vec![value],
+            };
+            let dir = path.parent().unwrap_or(dir);
+            result.extend(include_entities(list, dir, depth + 1)?);
+        }
+    }
+    Ok(result)
+}
+
+// the configs of a json value, one for every frame when it has an animation. the strings that
+// are expressions, of the normalized time t, the frame and the vars, are replaced by their values.
+// the errors are checked by validate, each with the json pointer of the value
+pub fn expand(value: Value) -> Result<Vec<Config>, Error> {
+    frames(value).map_err(Error::Config)
+}
+
+fn frames(mut value: Value) -> Result<Vec<Config>, Vec<String>> {
+    let animation: Option<AnimationJson> = match value
+        .as_object_mut()
+        .and_then(|o| o.remove("animation"))
+        .filter(|a| !a.is_null())
+    {
+        Some(a) => Some(serde_json::from_value(a).map_err(|e| vec![format!("/animation: {}", e)])?),
+        None => None,
+    };
+    if animation
+        .as_ref()
+        .is_some_and(|a| a.fps <= 0.0 || !a.fps.is_finite())
+    {
+        return Err(vec![
+            "/animation/fps: should be above 0, the frames have no duration".to_string(),
+        ]);
+    }
+    if let Some(definitions) = value.as_object_mut().and_then(|o| o.remove("definitions")) {
+        match definitions {
+            Value::Object(definitions) => {
+                instantiate(&mut value, &definitions, 0).map_err(|e| vec![e])?
+            }
+            _ => return Err(vec!["/definitions: should be an object".to_string()]),
+        }
+    }
+    let defined = match value.as_object_mut().and_then(|o| o.remove("vars")) {
+        Some(Value::Object(map)) => map,
+        Some(_) => return Err(vec!["/vars: should be an object".to_string()]),
+        None => serde_json::Map::new(),
+    };
+    let animation = match animation {
+        Some(animation) => animation,
+        None => {
+            let errors = evaluate(&mut value, &vars(&defined, 0, 1).map_err(|e| vec![e])?);
+            if !errors.is_empty() {
+                return Err(errors);
+            }
+            return Ok(vec![config(value)?]);
+        }
+    };
+    (0..animation.frames)
+        .map(|frame| {
+            let in_frame = |e: String| vec![format!("{} (frame {})", e, frame)];
+            let mut value = value.clone();
+            for track in &animation.tracks {
+                let target = value.pointer_mut(&track.path).ok_or_else(|| {
+                    in_frame(format!("/animation: no {} in the config", track.path))
+                })?;
+                *target = track.at(frame as f64).map_err(in_frame)?;
+            }
+            let vars = vars(&defined, frame, animation.frames).map_err(in_frame)?;
+            let errors = evaluate(&mut value, &vars);
+            if !errors.is_empty() {
+                return Err(errors.into_iter().flat_map(in_frame).collect());
+            }
+            let mut config = config(value)
+                .map_err(|errors| errors.into_iter().flat_map(in_frame).collect::<Vec<_>>())?;
+            config.fps = animation.fps;
+            config.out = frame_out(&config.out, frame);
+            Ok(config)
+        })
+        .collect()
+}
+
+fn config(value: Value) -> Result<Config, Vec<String>> {
+    let errors = validate(&value);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    serde_json::from_value(value).map_err(|e| vec![format!(": {}", e)])
+}
+
+// t from 0 up to 1 at the frame after the last, so that periodic motions loop, then the
+// defined ones in any order of their dependencies
+fn vars(
+    defined: &serde_json::Map<String, Value>,
+    frame: u32,
+    frames: u32,
+) -> Result<HashMap<String, f64>, String> {
+    let mut vars = HashMap::from([
+        ("t".to_string(), frame as f64 / frames.max(1) as f64),
+        ("frame".to_string(), frame as f64),
+    ]);
+    let mut left: Vec<(&String, &Value)> = defined.iter().collect();
+    while !left.is_empty() {
+        let mut errors = Vec::new();
+        let count = left.len();
+        left.retain(|(name, value)| {
+            let result = match value {
+                Value::Number(n) => Ok(n.as_f64().unwrap()),
+                Value::String(s) => expr::eval(s, &vars),
+                _ => Err("should be a number or an expression".to_string()),
+            };
+            match result {
+                Ok(v) => {
+                    vars.insert(format!("${}", name.trim_start_matches('$')), v);
+                    false
+                }
+                Err(e) => {
+                    errors.push(format!("{}: {}", name, e));
+                    true
+                }
+            }
+        });
+        if left.len() == count {
+            return Err(format!("/vars: {}", errors.join(", ")));
+        }
+    }
+    Ok(vars)
+}
+
+// the keys of a use for the transform of the shape, as in the transform shape
+const TRANSFORM_KEYS: [&str; 3] = ["translate", "rotate", "scale"];
+
+// every {"use": name} replaced by the definition, its other keys overriding the ones of the
+// definition. with the transform keys, the shape of an entity, or the shape, is transformed
+fn instantiate(
+    value: &mut Value,
+    definitions: &serde_json::Map<String, Value>,
+    depth: u32,
+) -> Result<(), String> {
+    if let Some(Value::String(name)) = value.get("use") {
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(format!(
+                "/definitions: deeper than {}, in a cycle?",
+                MAX_INCLUDE_DEPTH
+            ));
+        }
+        let mut instance = definitions
+            .get(name)
+            .ok_or_else(|| format!("/definitions: no {}", name))?
+            .clone();
+        let mut overrides = value.as_object().unwrap().clone();
+        overrides.remove("use");
+        let mut transform = serde_json::Map::new();
+        for key in TRANSFORM_KEYS {
+            if let Some(v) = overrides.remove(key) {
+                transform.insert(key.to_string(), v);
+            }
+        }
+        instantiate(&mut instance, definitions, depth + 1)?;
+        if let Some(object) = instance.as_object_mut() {
+            for (k, v) in overrides {
+                object.insert(k, v);
+            }
+        }
+        if !transform.is_empty() {
+            let shape = match instance.get_mut("shape") {
+                Some(shape) => shape,
+                None => &mut instance,
+            };
+            transform.insert("shape".to_string(), shape.take());
+            *shape = serde_json::json!({ "transform": transform });
+        }
+        *value = instance;
+    }
+    match value {
+        Value::Array(list) => {
+            for v in list {
+                instantiate(v, definitions, depth)?;
+            }
+        }
+        Value::Object(map) => {
+            for v in map.values_mut() {
+                instantiate(v, definitions, depth)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// the fields that are strings, never expressions
+const STRING_KEYS: [&str; 13] = [
+    "name",
+    "out",
+    "out_stats",
+    "out_normal",
+    "out_depth",
+    "out_id",
+    "out_ao",
+    "out_layers",
+    "path",
+    "font",
+    "text",
+    "hex",
+    "layer",
+];
+
+// the strings that aren't expressions, like the names of the variants, are left as they are.
+// the errors of the ones that can't be a name, each with the json pointer of the string
+fn evaluate(value: &mut Value, vars: &HashMap<String, f64>) -> Vec<String> {
+    let mut errors = Vec::new();
+    evaluate_at(value, vars, "", &mut errors);
+    errors
+}
+
+fn evaluate_at(
+    value: &mut Value,
+    vars: &HashMap<String, f64>,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    match value {
+        Value::String(s) => match expr::eval(s, vars) {
+            // whole numbers for the integer fields
+            Ok(v) => {
+                *value = if v.fract() == 0.0 && v.abs() < 1e15 {
+                    Value::from(v as i64)
+                } else {
+                    Value::from(v)
+                };
+            }
+            Err(e) if !is_name(s) => errors.push(format!("{}: {}", path, e)),
+            Err(_) => {}
+        },
+        Value::Array(list) => {
+            for (i, v) in list.iter_mut().enumerate() {
+                evaluate_at(v, vars, &format!("{}/{}", path, i), errors);
+            }
+        }
+        Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                if !STRING_KEYS.contains(&k.as_str()) {
+                    evaluate_at(v, vars, &format!("{}/{}", path, k), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// like the name of a variant, or of a variable
+fn is_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// the animated outputs take all the frames, the others get the number of the frame in 4 digits
+// in place of {frame}, in N digits in place of {frame:N}, or before the extension
+fn frame_out(out: &str, frame: u32) -> String {
+    if is_animated(out) {
+        return out.to_string();
+    }
+    let filled = fill(out, "frame", |width| {
+        format!("{:0width$}", frame, width = width.unwrap_or(4))
+    });
+    if let Some(out) = filled {
+        return out;
+    }
+    let number = format!("{:04}", frame);
+    match out.rfind('.') {
+        Some(i) if !out[i..].contains('/') => format!("{}_{}{}", &out[..i], number, &out[i..]),
+        _ => format!("{}_{}", out, number),
+    }
+}
+
+// the image of the layer, the name of it in place of {layer} of out_layers, or before the
+// extension
+pub fn layer_out(out: &str, layer: &str) -> String {
+    if out.contains("{layer}") {
+        return out.replace("{layer}", layer);
+    }
+    match out.rfind('.') {
+        Some(i) if !out[i..].contains('/') => format!("{}_{}{}", &out[..i], layer, &out[i..]),
+        _ => format!("{}_{}", out, layer),
+    }
+}
+
+// the name of the config, or its index in the file, in place of {name}
+pub fn name_out(out: &str, name: &str) -> String {
+    fill(out, "name", |_| name.to_string()).unwrap_or_else(|| out.to_string())
+}
+
+// the {key} and {key:width} in the text by the value for the width, none without them
+fn fill(text: &str, key: &str, value: impl Fn(Option<usize>) -> String) -> Option<String> {
+    let open = format!("{{{}", key);
+    let mut result = String::new();
+    let mut rest = text;
+    let mut found = false;
+    while let Some(i) = rest.find(&open) {
+        let after = &rest[i + open.len()..];
+        let end = match after.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        let width = match &after[..end] {
+            "" => None,
+            spec => match spec.strip_prefix(':').and_then(|w| w.parse().ok()) {
+                Some(width) => Some(width),
+                // like {frames}, not a key
+                None => {
+                    result.push_str(&rest[..i + open.len()]);
+                    rest = after;
+                    continue;
+                }
+            },
+        };
+        result.push_str(&rest[..i]);
+        result.push_str(&value(width));
+        rest = &after[end + 1..];
+        found = true;
+    }
+    result.push_str(rest);
+    found.then_some(result)
+}
+
+// an image of the pyramid, as high as the aspect of the render makes it
+#[derive(Serialize, Deserialize)]
+pub struct Level {
+    pub out: String,
+    pub width: u32,
+}
+
+#[allow(dead_code)]
+impl Level {
+    // of the image, for a render of the size
+    pub fn size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        let h = (self.width as f64 * height as f64 / width as f64).round() as u32;
+        (self.width, h.max(1))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AnimationJson {
+    pub frames: u32,
+    #[serde(default = "default_fps")]
+    pub fps: f64,
+    #[serde(default)]
+    pub tracks: Vec<TrackJson>,
+}
+
+// the keyframes of a value in the config
+#[derive(Serialize, Deserialize)]
+pub struct TrackJson {
+    // a json pointer, like /scenes/0/shape/circle/cx
+    pub path: String,
+    pub keys: Vec<KeyJson>,
+    #[serde(default)]
+    pub ease: Ease,
 }
 
+// a number, or an array or object of numbers like a color, at the frame
+#[derive(Serialize, Deserialize)]
+pub struct KeyJson {
+    pub frame: f64,
+    pub value: Value,
+}
+
+// of the interpolation between two keys
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Ease {
+    #[default]
+    #[serde(rename = "linear")]
+    Linear,
+    #[serde(rename = "ease_in")]
+    EaseIn,
+    #[serde(rename = "ease_out")]
+    EaseOut,
+    #[serde(rename = "ease_in_out")]
+    EaseInOut,
+    // the value of the previous key until the next
+    #[serde(rename = "step")]
+    Step,
+}
+
+impl Ease {
+    fn apply(&self, t: f64) -> f64 {
+        match self {
+            Ease::Linear => t,
+            Ease::EaseIn => t * t,
+            Ease::EaseOut => t * (2.0 - t),
+            Ease::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Ease::Step => 0.0,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl TrackJson {
+    // the value at the frame, held before the first key and after the last
+    fn at(&self, frame: f64) -> Result<Value, String> {
+        let mut keys: Vec<&KeyJson> = self.keys.iter().collect();
+        keys.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        let (first, last) = match (keys.first(), keys.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(format!("/animation: no keys for {}", self.path)),
+        };
+        if frame <= first.frame {
+            return Ok(first.value.clone());
+        }
+        if frame >= last.frame {
+            return Ok(last.value.clone());
+        }
+        let i = keys.iter().rposition(|k| k.frame <= frame).unwrap();
+        let (a, b) = (keys[i], keys[i + 1]);
+        let t = self.ease.apply((frame - a.frame) / (b.frame - a.frame));
+        lerp(&a.value, &b.value, t)
+            .ok_or_else(|| format!("/animation: can't interpolate the keys of {}", self.path))
+    }
+}
+
+// numbers, or arrays or objects of them of the same shape, like colors
+fn lerp(a: &Value, b: &Value, t: f64) -> Option<Value> {
+    if t == 0.0 {
+        return Some(a.clone());
+    }
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (a.as_f64()?, b.as_f64()?);
+            Some(Value::from(a + (b - a) * t))
+        }
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => Some(Value::Array(
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| lerp(a, b, t))
+                .collect::<Option<Vec<Value>>>()?,
+        )),
+        (Value::Object(a), Value::Object(b)) if a.len() == b.len() => Some(Value::Object(
+            a.iter()
+                .map(|(k, a)| Some((k.clone(), lerp(a, b.get(k)?, t)?)))
+                .collect::<Option<serde_json::Map<String, Value>>>()?,
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MediumJson {
+    pub scattering: f64,
+    pub absorption: f64,
+    #[serde(default = "default_density")]
+    pub density: DensityJson,
+}
+
+// a number, or a noise
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DensityJson {
+    Constant(f64),
+    Noise { noise: NoiseJson },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NoiseJson {
+    #[serde(default = "default_octaves")]
+    pub octaves: u32,
+    pub scale: f64,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn default_density() -> DensityJson {
+    DensityJson::Constant(1.0)
+}
+
+fn default_octaves() -> u32 {
+    4
+}
+
+#[allow(dead_code)]
+impl MediumJson {
+    pub fn get_medium(self) -> Medium {
+        Medium {
+            scattering: self.scattering,
+            absorption: self.absorption,
+            density: match self.density {
+                DensityJson::Constant(c) => Density::Constant(c),
+                DensityJson::Noise { noise } => {
+                    Density::Noise(Perlin::new(noise.octaves, noise.scale, noise.seed))
+                }
+            },
+        }
+    }
+}
+
+// the material is either given by `material`, or by the flat fields of the older configs
 #[derive(Serialize, Deserialize)]
 pub struct EntityJson {
     pub shape: ShapeJson,
-    pub emissive: ColorJson,
+    #[serde(default)]
+    pub material: Option<MaterialJson>,
+    #[serde(default)]
+    pub emissive: Option<EmissionJson>,
+    // of the emission, of emissive or of the emitters of material. the colors are not clipped
+    // before the image, a small emitter can be far brighter than 1
+    #[serde(default = "default_intensity")]
+    pub intensity: f64,
+    #[serde(default)]
     pub reflectivity: f64,
+    #[serde(default)]
+    pub roughness: f64,
+    #[serde(default)]
+    pub diffuse: Option<ColorJson>,
+    #[serde(default)]
     pub eta: f64,
-    pub absorption: ColorJson,
+    #[serde(default)]
+    pub dispersion: f64,
+    // of eta, see material::Grin
+    #[serde(default)]
+    pub grin: Option<Grin>,
+    #[serde(default)]
+    pub absorption: Option<ColorJson>,
+    #[serde(default)]
+    pub profile: Option<ProfileJson>,
+    #[serde(default)]
+    pub falloff: Falloff,
+    // of the back of an open shape, like a polyline, the material on both sides without
+    #[serde(default)]
+    pub back: Option<MaterialJson>,
+    // the rays it is seen by, see render::Visibility
+    #[serde(default = "default_visible")]
+    pub visible_to_camera: bool,
+    #[serde(default = "default_visible")]
+    pub casts_shadow: bool,
+    #[serde(default = "default_visible")]
+    pub emits_light: bool,
+    // of the image of out_layers it is in, render::DEFAULT_LAYER without
+    #[serde(default)]
+    pub layer: Option<String>,
+}
+
+fn default_visible() -> bool {
+    true
 }
 
 #[allow(dead_code)]
 impl EntityJson {
-    pub fn get_entity(self) -> Entity {
-        Entity {
-            shape: get_shape(self.shape),
-            emissive: get_color(self.emissive),
-            reflectivity: self.reflectivity,
-            eta: self.eta,
-            absorption: get_color(self.absorption),
+    pub fn get_entity(self) -> Result<Entity, Error> {
+        let material = match self.material {
+            Some(mj) => get_material(mj)?,
+            None => {
+                let mut list = Vec::new();
+                let emissive = self
+                    .emissive
+                    .map_or(Ok(Emission::Uniform(Color::black())), get_emission)?;
+                let diffuse = self.diffuse.map_or(Ok(Color::black()), get_color)?;
+                let absorption = self.absorption.map_or(Ok(Color::black()), get_color)?;
+                if !emissive.is_black() {
+                    list.push(Material::Emitter(emissive));
+                }
+                if !diffuse.is_black() {
+                    list.push(Material::Diffuse(diffuse));
+                }
+                if self.eta > 0.0 {
+                    // the fresnel term takes the place of the reflectivity
+                    list.push(Material::Dielectric {
+                        eta: self.eta,
+                        dispersion: self.dispersion,
+                        roughness: self.roughness,
+                        absorption,
+                        grin: self.grin,
+                    });
+                } else {
+                    if self.reflectivity > 0.0 {
+                        list.push(Material::Mirror {
+                            reflectivity: self.reflectivity,
+                            roughness: self.roughness,
+                        });
+                    }
+                    if !absorption.is_black() {
+                        list.push(Material::Absorber(absorption));
+                    }
+                }
+                if list.len() == 1 {
+                    list.remove(0)
+                } else {
+                    Material::Mix(list)
+                }
+            }
+        };
+        let material = material.with_intensity(self.intensity);
+        let shape = get_shape(self.shape)?;
+        let back = self
+            .back
+            .map(|m| get_material(m).map(|m| m.with_intensity(self.intensity)))
+            .transpose()?;
+        Ok(Entity {
+            aabb: shape.aabb(),
+            shape,
+            material,
+            profile: match self.profile {
+                Some(ProfileJson::Cosine(n)) => Profile::Cosine(n),
+                Some(ProfileJson::Table(table)) => Profile::Table(table),
+                None => Profile::Uniform,
+            },
+            falloff: self.falloff,
+            visibility: Visibility {
+                camera: self.visible_to_camera,
+                shadow: self.casts_shadow,
+                light: self.emits_light,
+            },
+            layer: self.layer,
+            back,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum MaterialJson {
+    #[serde(rename = "emitter")]
+    Emitter(EmissionJson),
+    #[serde(rename = "mirror")]
+    Mirror {
+        reflectivity: f64,
+        #[serde(default)]
+        roughness: f64,
+    },
+    #[serde(rename = "dielectric")]
+    Dielectric {
+        eta: f64,
+        #[serde(default)]
+        dispersion: f64,
+        #[serde(default)]
+        roughness: f64,
+        #[serde(default)]
+        absorption: Option<ColorJson>,
+        #[serde(default)]
+        grin: Option<Grin>,
+    },
+    #[serde(rename = "thin_film")]
+    ThinFilm { eta: f64, thickness: f64 },
+    #[serde(rename = "grating")]
+    Grating {
+        period: f64,
+        #[serde(default = "default_reflectivity")]
+        reflectivity: f64,
+    },
+    #[serde(rename = "diffuse")]
+    Diffuse(ColorJson),
+    #[serde(rename = "absorber")]
+    Absorber(ColorJson),
+    #[serde(rename = "mix")]
+    Mix(Vec<MaterialJson>),
+}
+
+fn default_reflectivity() -> f64 {
+    1.0
+}
+
+fn get_material(material_json: MaterialJson) -> Result<Material, Error> {
+    Ok(match material_json {
+        MaterialJson::Emitter(e) => Material::Emitter(get_emission(e)?),
+        MaterialJson::Mirror {
+            reflectivity,
+            roughness,
+        } => Material::Mirror {
+            reflectivity,
+            roughness,
+        },
+        MaterialJson::Dielectric {
+            eta,
+            dispersion,
+            roughness,
+            absorption,
+            grin,
+        } => Material::Dielectric {
+            eta,
+            dispersion,
+            roughness,
+            absorption: absorption.map_or(Ok(Color::black()), get_color)?,
+            grin,
+        },
+        MaterialJson::ThinFilm { eta, thickness } => Material::ThinFilm { eta, thickness },
+        MaterialJson::Grating {
+            period,
+            reflectivity,
+        } => Material::Grating {
+            period,
+            reflectivity,
+        },
+        MaterialJson::Diffuse(c) => Material::Diffuse(get_color(c)?),
+        MaterialJson::Absorber(c) => Material::Absorber(get_color(c)?),
+        MaterialJson::Mix(list) => Material::Mix(
+            list.into_iter()
+                .map(get_material)
+                .collect::<Result<_, _>>()?,
+        ),
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ProfileJson {
+    #[serde(rename = "cosine")]
+    Cosine(f64),
+    #[serde(rename = "table")]
+    Table(Vec<f64>),
+}
+
+// a plain color, or a texture
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EmissionJson {
+    Uniform(ColorJson),
+    Texture(TextureJson),
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum TextureJson {
+    #[serde(rename = "linear_gradient")]
+    LinearGradient {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        from: ColorJson,
+        to: ColorJson,
+    },
+    #[serde(rename = "radial_gradient")]
+    RadialGradient {
+        cx: f64,
+        cy: f64,
+        r: f64,
+        inner: ColorJson,
+        outer: ColorJson,
+    },
+    #[serde(rename = "image")]
+    Image {
+        path: String,
+        x: f64,
+        y: f64,
+        w: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+fn get_emission(emission_json: EmissionJson) -> Result<Emission, Error> {
+    Ok(match emission_json {
+        EmissionJson::Uniform(c) => Emission::Uniform(get_color(c)?),
+        EmissionJson::Texture(TextureJson::LinearGradient {
+            x1,
+            y1,
+            x2,
+            y2,
+            from,
+            to,
+        }) => Emission::Linear {
+            x1,
+            y1,
+            x2,
+            y2,
+            a: get_color(from)?,
+            b: get_color(to)?,
+        },
+        EmissionJson::Texture(TextureJson::RadialGradient {
+            cx,
+            cy,
+            r,
+            inner,
+            outer,
+        }) => Emission::Radial {
+            cx,
+            cy,
+            r,
+            a: get_color(inner)?,
+            b: get_color(outer)?,
+        },
+        EmissionJson::Texture(TextureJson::Image {
+            path,
+            x,
+            y,
+            w,
+            intensity,
+        }) => Emission::image(&path, x, y, w, intensity)?,
+    })
+}
+
+// a plain color, or one by the direction
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BackgroundJson {
+    Uniform(ColorJson),
+    Environment(EnvironmentJson),
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum EnvironmentJson {
+    #[serde(rename = "vertical_gradient")]
+    VerticalGradient { top: ColorJson, bottom: ColorJson },
+    // around the circle, see material::Background
+    #[serde(rename = "angular")]
+    Angular(Vec<StopJson>),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StopJson {
+    pub angle: f64,
+    pub color: ColorJson,
+}
+
+fn get_background(background_json: BackgroundJson) -> Result<Background, Error> {
+    Ok(match background_json {
+        BackgroundJson::Uniform(c) => Background::Uniform(get_color(c)?),
+        BackgroundJson::Environment(EnvironmentJson::VerticalGradient { top, bottom }) => {
+            Background::Vertical {
+                top: get_color(top)?,
+                bottom: get_color(bottom)?,
+            }
         }
+        BackgroundJson::Environment(EnvironmentJson::Angular(stops)) => Background::angular(
+            stops
+                .into_iter()
+                .map(|s| Ok((s.angle, get_color(s.color)?)))
+                .collect::<Result<_, Error>>()?,
+        ),
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum LightJson {
+    #[serde(rename = "point_light")]
+    PointLight {
+        x: f64,
+        y: f64,
+        color: ColorJson,
+        intensity: f64,
+        #[serde(default = "default_falloff")]
+        falloff: f64,
+        // see light::Light::softness
+        #[serde(default)]
+        softness: f64,
+    },
+    #[serde(rename = "spot_light")]
+    SpotLight {
+        x: f64,
+        y: f64,
+        color: ColorJson,
+        intensity: f64,
+        #[serde(default = "default_falloff")]
+        falloff: f64,
+        // see light::Light::softness
+        #[serde(default)]
+        softness: f64,
+        e: f64,
+        inner: f64,
+        outer: f64,
+    },
+}
+
+fn default_falloff() -> f64 {
+    2.0
+}
+
+#[allow(dead_code)]
+impl LightJson {
+    pub fn get_light(self) -> Result<Box<dyn Light + Sync>, Error> {
+        Ok(match self {
+            LightJson::PointLight {
+                x,
+                y,
+                color,
+                intensity,
+                falloff,
+                softness,
+            } => Box::new(PointLight {
+                x,
+                y,
+                color: get_color(color)?,
+                intensity,
+                falloff,
+                softness,
+            }),
+            LightJson::SpotLight {
+                x,
+                y,
+                color,
+                intensity,
+                falloff,
+                softness,
+                e,
+                inner,
+                outer,
+            } => Box::new(SpotLight::new(
+                PointLight {
+                    x,
+                    y,
+                    color: get_color(color)?,
+                    intensity,
+                    falloff,
+                    softness,
+                },
+                e,
+                inner,
+                outer,
+            )),
+        })
     }
 }
 
@@ -37,10 +1389,75 @@ impl EntityJson {
 pub enum ShapeJson {
     #[serde(rename = "directional_light")]
     DirectionalLight { d: f64, nx: f64, ny: f64 },
+    #[serde(rename = "segment_light")]
+    SegmentLight { x1: f64, y1: f64, x2: f64, y2: f64 },
+    #[serde(rename = "polyline")]
+    Polyline(Vec<(f64, f64)>),
     #[serde(rename = "polygon")]
     Polygon(PolygonJson),
     #[serde(rename = "circle")]
     Circle(CircleJson),
+    #[serde(rename = "arc")]
+    Arc {
+        cx: f64,
+        cy: f64,
+        r: f64,
+        start: f64,
+        sweep: f64,
+    },
+    #[serde(rename = "ring")]
+    Ring { cx: f64, cy: f64, r1: f64, r2: f64 },
+    #[serde(rename = "ellipse")]
+    Ellipse {
+        cx: f64,
+        cy: f64,
+        rx: f64,
+        ry: f64,
+        e: f64,
+    },
+    #[serde(rename = "superellipse")]
+    Superellipse {
+        cx: f64,
+        cy: f64,
+        rx: f64,
+        ry: f64,
+        n: f64,
+        e: f64,
+    },
+    #[serde(rename = "rounded_rectangle")]
+    RoundedRectangle {
+        cx: f64,
+        cy: f64,
+        e: f64,
+        sx: f64,
+        sy: f64,
+        r: f64,
+    },
+    #[serde(rename = "capsule")]
+    Capsule {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        r: f64,
+    },
+    #[serde(rename = "bezier_path")]
+    BezierPath(Vec<(f64, f64)>),
+    #[serde(rename = "text")]
+    Text {
+        font: String,
+        text: String,
+        size: f64,
+        x: f64,
+        y: f64,
+    },
+    #[serde(rename = "image_mask")]
+    ImageMask {
+        path: String,
+        x: f64,
+        y: f64,
+        w: f64,
+    },
     #[serde(rename = "plane")]
     Plane { px: f64, py: f64, nx: f64, ny: f64 },
     #[serde(rename = "union")]
@@ -49,6 +1466,36 @@ pub enum ShapeJson {
     Intersect(Vec<Box<ShapeJson>>),
     #[serde(rename = "complement")]
     Complement(Box<ShapeJson>),
+    #[serde(rename = "transform")]
+    Transform(TransformJson),
+    #[serde(rename = "difference")]
+    Difference {
+        base: Box<ShapeJson>,
+        cutters: Vec<Box<ShapeJson>>,
+    },
+    #[serde(rename = "smooth_union")]
+    SmoothUnion { shapes: Vec<Box<ShapeJson>>, k: f64 },
+    #[serde(rename = "smooth_subtract")]
+    SmoothSubtract {
+        base: Box<ShapeJson>,
+        cutters: Vec<Box<ShapeJson>>,
+        k: f64,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TransformJson {
+    shape: Box<ShapeJson>,
+    #[serde(default)]
+    translate: (f64, f64),
+    #[serde(default)]
+    rotate: f64,
+    #[serde(default = "default_scale")]
+    scale: (f64, f64),
+}
+
+fn default_scale() -> (f64, f64) {
+    (1.0, 1.0)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -79,6 +1526,33 @@ pub enum PolygonJson {
         sx: f64,
         sy: f64,
     },
+    #[serde(rename = "heart")]
+    Heart { cx: f64, cy: f64, r: f64, e: f64 },
+    #[serde(rename = "crescent")]
+    Crescent {
+        cx: f64,
+        cy: f64,
+        r: f64,
+        d: f64,
+        e: f64,
+    },
+    #[serde(rename = "gear")]
+    Gear {
+        cx: f64,
+        cy: f64,
+        r1: f64,
+        r2: f64,
+        n: u32,
+        e: f64,
+    },
+    #[serde(rename = "arrow")]
+    Arrow {
+        cx: f64,
+        cy: f64,
+        l: f64,
+        w: f64,
+        e: f64,
+    },
 }
 
 #[derive(Serialize, Deserialize)]
@@ -88,49 +1562,146 @@ pub struct CircleJson {
     r: f64,
 }
 
+// a named color, or a color temperature
 #[derive(Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum ColorJson {
+    Named(NamedColorJson),
+    Kelvin {
+        kelvin: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum NamedColorJson {
     #[serde(rename = "grey")]
     Grey(f64),
     #[serde(rename = "black")]
     Black(bool),
     #[serde(rename = "rgb")]
     Rgb { r: f64, g: f64, b: f64 },
+    #[serde(rename = "hsv")]
+    Hsv { h: f64, s: f64, v: f64 },
+    #[serde(rename = "hsl")]
+    Hsl { h: f64, s: f64, l: f64 },
+    #[serde(rename = "hex")]
+    Hex(String),
+}
+
+fn get_color(color_json: ColorJson) -> Result<Color, Error> {
+    Ok(match color_json {
+        ColorJson::Named(NamedColorJson::Grey(n)) => Color::grey(n),
+        ColorJson::Named(NamedColorJson::Black(_)) => Color::black(),
+        ColorJson::Named(NamedColorJson::Rgb { r, g, b }) => Color { r, g, b },
+        ColorJson::Named(NamedColorJson::Hsv { h, s, v }) => Color::hsv(h, s, v),
+        ColorJson::Named(NamedColorJson::Hsl { h, s, l }) => Color::hsl(h, s, l),
+        ColorJson::Named(NamedColorJson::Hex(hex)) => {
+            Color::hex(&hex).ok_or_else(|| Error::Invalid(format!("invalid hex color: {}", hex)))?
+        }
+        ColorJson::Kelvin { kelvin, intensity } => Color::kelvin(kelvin) * intensity,
+    })
 }
 
-fn get_color(color_json: ColorJson) -> Color {
-    match color_json {
-        ColorJson::Grey(n) => Color::grey(n),
-        ColorJson::Black(_) => Color::black(),
-        ColorJson::Rgb { r, g, b } => Color { r, g, b },
+// the error of the constructor of the shape. the inner shapes are checked on their own, and the
+// fonts and the images are only read when the scene is built
+pub(crate) fn shape_error(shape_json: ShapeJson) -> Option<Error> {
+    match shape_json {
+        ShapeJson::Text { .. }
+        | ShapeJson::ImageMask { .. }
+        | ShapeJson::Union(_)
+        | ShapeJson::Intersect(_)
+        | ShapeJson::Complement(_)
+        | ShapeJson::Difference { .. }
+        | ShapeJson::SmoothUnion { .. }
+        | ShapeJson::SmoothSubtract { .. } => None,
+        // of an empty union in place of its shape
+        ShapeJson::Transform(tj) => {
+            let empty = Box::new(UnionShape { c: Vec::new() });
+            TransformedShape::new(empty, tj.translate, tj.rotate, tj.scale).err()
+        }
+        shape_json => get_shape(shape_json).err(),
     }
 }
 
-fn get_shape(shape_json: ShapeJson) -> Box<dyn Shape + Sync> {
+fn get_shape(shape_json: ShapeJson) -> Result<Box<dyn Shape + Sync>, Error> {
     let shape: Box<dyn Shape + Sync> = match shape_json {
         ShapeJson::DirectionalLight { d, nx, ny } => Box::new(DirectionalLight {
             d,
             nx: -nx,
             ny: -ny,
         }),
+        ShapeJson::SegmentLight { x1, y1, x2, y2 } => Box::new(SegmentLight { x1, y1, x2, y2 }),
+        ShapeJson::Polyline(points) => Box::new(Polyline::new(points)?),
         ShapeJson::Polygon(pj) => match pj {
-            PolygonJson::Points(points) => Box::new(Polygon::new(points)),
-            PolygonJson::Regular { cx, cy, r, n, e } => Box::new(Polygon::regular(cx, cy, r, n, e)),
-            PolygonJson::Star { cx, cy, r, n, e } => Box::new(Polygon::star(cx, cy, r, n, e)),
+            PolygonJson::Points(points) => Box::new(Polygon::new(points)?),
+            PolygonJson::Regular { cx, cy, r, n, e } => {
+                Box::new(Polygon::regular(cx, cy, r, n, e)?)
+            }
+            PolygonJson::Star { cx, cy, r, n, e } => Box::new(Polygon::star(cx, cy, r, n, e)?),
             PolygonJson::Rectangle { cx, cy, e, sx, sy } => {
                 Box::new(Polygon::rectangle(cx, cy, e, sx, sy))
             }
+            PolygonJson::Heart { cx, cy, r, e } => Box::new(Polygon::heart(cx, cy, r, e)),
+            PolygonJson::Crescent { cx, cy, r, d, e } => {
+                Box::new(Polygon::crescent(cx, cy, r, d, e)?)
+            }
+            PolygonJson::Gear {
+                cx,
+                cy,
+                r1,
+                r2,
+                n,
+                e,
+            } => Box::new(Polygon::gear(cx, cy, r1, r2, n, e)?),
+            PolygonJson::Arrow { cx, cy, l, w, e } => Box::new(Polygon::arrow(cx, cy, l, w, e)),
         },
         ShapeJson::Circle(cj) => Box::new(Circle {
             cx: cj.cx,
             cy: cj.cy,
             r: cj.r,
         }),
+        ShapeJson::Arc {
+            cx,
+            cy,
+            r,
+            start,
+            sweep,
+        } => Box::new(Sector::new(cx, cy, r, start, sweep)),
+        ShapeJson::Ring { cx, cy, r1, r2 } => Box::new(Ring { cx, cy, r1, r2 }),
+        ShapeJson::Ellipse { cx, cy, rx, ry, e } => Box::new(Ellipse::new(cx, cy, rx, ry, e)),
+        ShapeJson::Superellipse {
+            cx,
+            cy,
+            rx,
+            ry,
+            n,
+            e,
+        } => Box::new(Superellipse::new(cx, cy, rx, ry, n, e)?),
+        ShapeJson::RoundedRectangle {
+            cx,
+            cy,
+            e,
+            sx,
+            sy,
+            r,
+        } => Box::new(RoundedRect::new(cx, cy, e, sx, sy, r)),
+        ShapeJson::Capsule { x1, y1, x2, y2, r } => Box::new(Capsule { x1, y1, x2, y2, r }),
+        ShapeJson::BezierPath(points) => Box::new(BezierPath::new(points)?),
+        ShapeJson::Text {
+            font,
+            text,
+            size,
+            x,
+            y,
+        } => Box::new(text::text(&font, &text, size, x, y)?),
+        ShapeJson::ImageMask { path, x, y, w } => Box::new(ImageMask::open(&path, x, y, w)?),
         ShapeJson::Plane { px, py, nx, ny } => Box::new(Plane { px, py, nx, ny }),
         ShapeJson::Union(list) => {
             let mut shapes: Vec<Box<dyn Shape + Sync>> = Vec::new();
             for item in list {
-                let shape = get_shape(*item);
+                let shape = get_shape(*item)?;
                 shapes.push(shape);
             }
             Box::new(UnionShape { c: shapes })
@@ -138,12 +1709,146 @@ fn get_shape(shape_json: ShapeJson) -> Box<dyn Shape + Sync> {
         ShapeJson::Intersect(list) => {
             let mut shapes: Vec<Box<dyn Shape + Sync>> = Vec::new();
             for item in list {
-                let shape = get_shape(*item);
+                let shape = get_shape(*item)?;
                 shapes.push(shape);
             }
             Box::new(IntersectShape { c: shapes })
         }
-        ShapeJson::Complement(a) => Box::new(ComplementShape { a: get_shape(*a) }),
+        ShapeJson::Complement(a) => Box::new(ComplementShape { a: get_shape(*a)? }),
+        ShapeJson::Transform(tj) => Box::new(TransformedShape::new(
+            get_shape(*tj.shape)?,
+            tj.translate,
+            tj.rotate,
+            tj.scale,
+        )?),
+        ShapeJson::Difference { base, cutters } => {
+            let mut shapes: Vec<Box<dyn Shape + Sync>> = Vec::new();
+            for item in cutters {
+                let shape = get_shape(*item)?;
+                shapes.push(shape);
+            }
+            Box::new(DifferenceShape {
+                a: get_shape(*base)?,
+                c: shapes,
+            })
+        }
+        ShapeJson::SmoothUnion { shapes: list, k } => {
+            let mut shapes: Vec<Box<dyn Shape + Sync>> = Vec::new();
+            for item in list {
+                let shape = get_shape(*item)?;
+                shapes.push(shape);
+            }
+            Box::new(SmoothUnionShape { c: shapes, k })
+        }
+        ShapeJson::SmoothSubtract { base, cutters, k } => {
+            let mut shapes: Vec<Box<dyn Shape + Sync>> = Vec::new();
+            for item in cutters {
+                let shape = get_shape(*item)?;
+                shapes.push(shape);
+            }
+            Box::new(SmoothSubtractShape {
+                a: get_shape(*base)?,
+                c: shapes,
+                k,
+            })
+        }
     };
-    shape
+    Ok(shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn read(text: &str) -> Value {
+        serde_json::from_str(&strip_json(text)).unwrap()
+    }
+
+    #[test]
+    fn comments_become_spaces() {
+        let text = "{\"a\": 1, // one\n/* two\nlines */ \"b\": 2}";
+        let stripped = strip_json(text);
+        assert_eq!(stripped, "{\"a\": 1,       \n      \n         \"b\": 2}");
+        assert_eq!(stripped.chars().count(), text.chars().count());
+        assert_eq!(read(text), json!({"a": 1, "b": 2}));
+    }
+
+    #[test]
+    fn strings_are_kept() {
+        assert_eq!(
+            read(r#"{"a": "// no", "b": "/* no */", "c": "\"//", "d": "\\"} // yes"#),
+            json!({"a": "// no", "b": "/* no */", "c": "\"//", "d": "\\"})
+        );
+        assert_eq!(read(r#"["a,]", "b,}"]"#), json!(["a,]", "b,}"]));
+    }
+
+    #[test]
+    fn trailing_commas() {
+        assert_eq!(read("[1, 2,]"), json!([1, 2]));
+        assert_eq!(read("{\"a\": [1,\n],\n}"), json!({"a": [1]}));
+        // with comments between the comma and the end
+        assert_eq!(read("[1, // one\n /* end */ ]"), json!([1]));
+        // the commas between the values are kept
+        assert_eq!(read("[1, /* , */ 2]"), json!([1, 2]));
+    }
+
+    #[test]
+    fn unterminated() {
+        assert_eq!(strip_json("1 /* open"), "1        ");
+        assert_eq!(strip_json("1 // open"), "1        ");
+        assert_eq!(strip_json("\"open\\"), "\"open\\");
+        assert_eq!(strip_json("[1, /* open"), "[1,        ");
+    }
+
+    fn config() -> Value {
+        json!({
+            "enable": true,
+            "out": "a.png",
+            "width": 8,
+            "height": 8,
+            "stratification": 1,
+            "max_depth": 1,
+            "scenes": [{
+                "shape": {"circle": {"cx": "0.5", "cy": 0.5, "r": "0.1 * 2"}},
+                "emissive": {"grey": 2},
+            }],
+        })
+    }
+
+    #[test]
+    fn strings_are_not_evaluated() {
+        let mut value = config();
+        value["name"] = json!("2024");
+        value["out"] = json!("e");
+        value["out_depth"] = json!("1");
+        let item = expand(value).unwrap().pop().unwrap();
+        assert_eq!(item.name.as_deref(), Some("2024"));
+        assert_eq!(item.out, "e");
+        assert_eq!(item.out_depth.as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn expression_errors() {
+        let mut value = config();
+        value["scenes"][0]["shape"]["circle"]["r"] = json!("0.1 *");
+        value["tone_map"] = json!("aces");
+        let errors = match expand(value) {
+            Err(Error::Config(errors)) => errors,
+            _ => panic!("the config should not be read"),
+        };
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(
+            errors[0].starts_with("/scenes/0/shape/circle/r: "),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn errors_keep_their_lines_and_columns() {
+        let text = "{\n  /* a\n  comment */ \"a\": 1,\n  \"b\": ?\n}";
+        let e = serde_json::from_str::<Value>(&strip_json(text)).unwrap_err();
+        assert_eq!((e.line(), e.column()), (4, 8));
+    }
 }
@@ -0,0 +1,142 @@
+use image::{Rgb, RgbImage};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    render::{RenderSettings, Scene},
+    shapes::sdf_normal,
+};
+
+// the shapes drawn over the traced image, or over a black one with the insides filled
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DebugShapes {
+    #[serde(rename = "over")]
+    Over,
+    #[serde(rename = "only")]
+    Only,
+}
+
+const OUTLINE: Rgb<u8> = Rgb([0, 255, 255]);
+const EMITTER: Rgb<u8> = Rgb([255, 220, 0]);
+const TICK: Rgb<u8> = Rgb([255, 0, 255]);
+const INSIDE: Rgb<u8> = Rgb([48, 48, 48]);
+const EMITTER_INSIDE: Rgb<u8> = Rgb([80, 64, 0]);
+// of the cells with a tick on the outline of every entity, in pixels
+const CELL: u32 = 16;
+const TICK_LENGTH: f64 = 6.0;
+const CROSS: i64 = 4;
+
+// the outlines of the entities, the emitters in yellow, with ticks along their normals, which
+// point out of the shape. a cross at the middle of every emitter and at every light. with fill,
+// the insides are drawn too, over the image of the settings
+pub fn draw(image: &mut RgbImage, scene: &Scene, settings: &RenderSettings, fill: bool) {
+    let (width, height) = image.dimensions();
+    let pixel = settings.pixel_size();
+    let half = 0.5 * pixel;
+    let to_scene = |x: u32, y: u32| settings.to_world((x as f64, y as f64));
+    let put = |image: &mut RgbImage, p: (f64, f64), color: Rgb<u8>| {
+        let (x, y) = settings.to_pixel(p);
+        let (x, y) = (x.round(), y.round());
+        if x >= 0.0 && y >= 0.0 && x < width as f64 && y < height as f64 {
+            image.put_pixel(x as u32, y as u32, color);
+        }
+    };
+
+    // of every pixel, the first entity it is in, and the ones with the outline on it with their
+    // distances, row by row
+    type Pixel = (Option<usize>, Vec<(usize, f64)>);
+    let rows: Vec<Vec<Pixel>> = (0..height)
+        .into_par_iter()
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let p = to_scene(x, y);
+                    let mut inside = None;
+                    let mut outlines = Vec::new();
+                    for (i, e) in scene.entities.iter().enumerate() {
+                        let d = match e.shape.as_sdf() {
+                            Some(shape) => shape.sdf(p),
+                            None => continue,
+                        };
+                        if d < 0.0 && inside.is_none() {
+                            inside = Some(i);
+                        }
+                        if d.abs() <= half {
+                            outlines.push((i, d.abs()));
+                        }
+                    }
+                    (inside, outlines)
+                })
+                .collect()
+        })
+        .collect();
+    let emissive: Vec<bool> = scene
+        .entities
+        .iter()
+        .map(|e| e.material.emissive())
+        .collect();
+
+    if fill {
+        for (y, row) in rows.iter().enumerate() {
+            for (x, (inside, _)) in row.iter().enumerate() {
+                if let Some(i) = *inside {
+                    let color = if emissive[i] { EMITTER_INSIDE } else { INSIDE };
+                    image.put_pixel(x as u32, y as u32, color);
+                }
+            }
+        }
+    }
+
+    // the pixel nearest to the outline in every cell, for the ticks
+    let cells = (width.div_ceil(CELL) * height.div_ceil(CELL)) as usize;
+    let mut nearest: Vec<Vec<Option<(f64, u32, u32)>>> =
+        vec![vec![None; cells]; scene.entities.len()];
+    for (y, row) in rows.iter().enumerate() {
+        for (x, (_, outlines)) in row.iter().enumerate() {
+            let (x, y) = (x as u32, y as u32);
+            for &(i, d) in outlines {
+                image.put_pixel(x, y, if emissive[i] { EMITTER } else { OUTLINE });
+                let cell = &mut nearest[i][((y / CELL) * width.div_ceil(CELL) + x / CELL) as usize];
+                if cell.is_none_or(|(c, _, _)| d < c) {
+                    *cell = Some((d, x, y));
+                }
+            }
+        }
+    }
+    for (entity, cells) in scene.entities.iter().zip(&nearest) {
+        let shape = match entity.shape.as_sdf() {
+            Some(shape) => shape,
+            None => continue,
+        };
+        for &(_, x, y) in cells.iter().flatten() {
+            let p = to_scene(x, y);
+            let (nx, ny) = sdf_normal(shape, p);
+            if nx.is_nan() || ny.is_nan() {
+                continue;
+            }
+            for k in 1..=TICK_LENGTH as u32 {
+                let t = k as f64 * pixel;
+                put(image, (p.0 + nx * t, p.1 + ny * t), TICK);
+            }
+        }
+    }
+
+    let cross = |image: &mut RgbImage, (x, y): (f64, f64)| {
+        for k in -CROSS..=CROSS {
+            let k = k as f64 * pixel;
+            put(image, (x + k, y + k), EMITTER);
+            put(image, (x + k, y - k), EMITTER);
+        }
+    };
+    for &i in &scene.emitters {
+        if let Some(aabb) = scene.entities[i].aabb {
+            let middle = (
+                (aabb.min.0 + aabb.max.0) / 2.0,
+                (aabb.min.1 + aabb.max.1) / 2.0,
+            );
+            cross(image, middle);
+        }
+    }
+    for light in &scene.lights {
+        cross(image, light.position());
+    }
+}
@@ -0,0 +1,550 @@
+use eframe::egui::{
+    self, pos2, vec2, CollapsingHeader, Color32, ColorImage, ComboBox, DragValue, Key, Pos2, Rect,
+    ScrollArea, Sense, Slider, Stroke, TextureHandle, TextureOptions, Ui, ViewportCommand,
+};
+use image::RgbImage;
+use serde_json::{json, Value};
+use std::{
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use crate::{
+    config::{self, Config},
+    error::Error,
+    render::{Entity, RenderSettings, Renderer},
+};
+
+// the fields that are whole numbers, rounded when they are dragged
+const INTEGERS: [&str; 11] = [
+    "width",
+    "height",
+    "stratification",
+    "max_depth",
+    "tile",
+    "photons",
+    "bit_depth",
+    "seed",
+    "frames",
+    "min_samples",
+    "max_samples",
+];
+
+// an entity put in by the add button
+fn new_entity() -> Value {
+    json!({
+        "shape": {"circle": {"cx": 0.5, "cy": 0.5, "r": 0.1}},
+        "diffuse": {"grey": 0.8}
+    })
+}
+
+// opens the configs of the file in a window: the entities are picked and dragged on the preview,
+// and their fields changed in the panel on the right. saved back into the file by ctrl+s
+pub fn edit(path: &Path) -> Result<(), Error> {
+    let values = config::read(path)?;
+    let path = path.to_path_buf();
+    let title = format!("{} - imprint_of_light", path.display());
+    eframe::run_native(
+        &title,
+        eframe::NativeOptions::default(),
+        Box::new(move |cc| Box::new(Editor::new(path, values, cc.egui_ctx.clone()))),
+    )
+    .map_err(|e| Error::Invalid(format!("the editor: {}", e)))
+}
+
+struct Editor {
+    path: PathBuf,
+    values: Vec<Value>,
+    // the config shown, and its entity picked
+    config: usize,
+    entity: Option<usize>,
+    // of the preview, the samples of every pixel and the longer edge in pixels
+    samples: u32,
+    size: u32,
+    // the entities of the expanded config, to pick them on the preview. empty when their
+    // indexes aren't the ones in the file, with entities included in between
+    entities: Vec<Option<Entity>>,
+    // of the image of the config, its size and where it is in the world
+    settings: RenderSettings,
+    // not saved yet
+    changed: bool,
+    status: String,
+    texture: Option<TextureHandle>,
+    // to the thread rendering the previews, and back
+    requests: mpsc::Sender<Config>,
+    rendered: Arc<Mutex<Option<Result<RgbImage, Error>>>>,
+}
+
+impl Editor {
+    fn new(path: PathBuf, values: Vec<Value>, ctx: egui::Context) -> Self {
+        let (requests, rx) = mpsc::channel();
+        let rendered = Arc::new(Mutex::new(None));
+        let result = rendered.clone();
+        thread::spawn(move || previews(rx, result, ctx));
+        let mut editor = Self {
+            path,
+            values,
+            config: 0,
+            entity: None,
+            samples: 16,
+            size: 256,
+            entities: Vec::new(),
+            settings: RenderSettings::default().size(1, 1),
+            changed: false,
+            status: String::new(),
+            texture: None,
+            requests,
+            rendered,
+        };
+        editor.refresh();
+        editor
+    }
+
+    // the first frame of the config shown
+    fn expanded(&self) -> Result<Config, Error> {
+        let value = match self.values.get(self.config) {
+            Some(value) => value.clone(),
+            None => return Err(Error::Invalid("no config in the file".to_string())),
+        };
+        let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+        let mut list = config::include(value, dir).and_then(config::expand)?;
+        if list.is_empty() {
+            return Err(Error::Invalid("the config has no frames".to_string()));
+        }
+        Ok(list.remove(0))
+    }
+
+    // after a change of the config, for the entities to pick and a new preview
+    fn refresh(&mut self) {
+        let item = match self.expanded() {
+            Ok(item) => item,
+            Err(e) => {
+                self.status = e.to_string();
+                return;
+            }
+        };
+        self.status.clear();
+        self.settings = item.settings().size(item.width.max(1), item.height.max(1));
+        let inline = self.scenes().iter().any(|e| e.get("include").is_some());
+        self.entities = match self.expanded() {
+            Ok(item) if !inline => item
+                .scenes
+                .into_iter()
+                .map(|e| e.get_entity().ok())
+                .collect(),
+            _ => Vec::new(),
+        };
+        self.requests
+            .send(preview(item, self.samples, self.size))
+            .ok();
+    }
+
+    fn scenes(&self) -> &[Value] {
+        match self.values.get(self.config).and_then(|v| v.get("scenes")) {
+            Some(Value::Array(list)) => list,
+            _ => &[],
+        }
+    }
+
+    fn scenes_mut(&mut self) -> Option<&mut Vec<Value>> {
+        match self.values.get_mut(self.config)?.get_mut("scenes")? {
+            Value::Array(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    fn save(&mut self) {
+        match config::write(&self.path, &self.values) {
+            Ok(()) => {
+                self.changed = false;
+                self.status = format!("saved {}", self.path.display());
+            }
+            Err(e) => self.status = e.to_string(),
+        }
+    }
+
+    // the entity with its boundary nearest to the point, of the ones around it
+    fn pick(&mut self, p: (f64, f64)) {
+        self.entity = self
+            .entities
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| Some((i, e.as_ref()?.shape.as_sdf()?.sdf(p))))
+            .filter(|&(_, d)| d < 0.02)
+            .min_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+            .map(|(i, _)| i);
+    }
+
+    // moves the shape of the entity, in a transform of it
+    fn translate(&mut self, i: usize, (dx, dy): (f64, f64)) {
+        let shape = match self
+            .scenes_mut()
+            .and_then(|list| list.get_mut(i))
+            .and_then(|e| e.get_mut("shape"))
+        {
+            Some(shape) => shape,
+            None => return,
+        };
+        // the translation of an expression is kept, inside a new transform
+        let (x, y) = match shape.pointer("/transform/translate") {
+            Some(Value::Array(t)) if t.len() == 2 && t.iter().all(|v| v.is_number()) => {
+                (t[0].as_f64().unwrap(), t[1].as_f64().unwrap())
+            }
+            _ => {
+                let inner = shape.take();
+                *shape = json!({"transform": {"shape": inner}});
+                (0.0, 0.0)
+            }
+        };
+        shape["transform"]["translate"] = json!([x + dx, y + dy]);
+    }
+
+    fn top_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("save").clicked() {
+                self.save();
+            }
+            if self.values.len() > 1 {
+                let name = |i: usize, v: &Value| match v.get("name").or_else(|| v.get("out")) {
+                    Some(Value::String(name)) => name.clone(),
+                    _ => i.to_string(),
+                };
+                let selected = name(self.config, &self.values[self.config]);
+                let mut config = self.config;
+                ComboBox::from_id_source("config")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for (i, value) in self.values.iter().enumerate() {
+                            ui.selectable_value(&mut config, i, name(i, value));
+                        }
+                    });
+                if config != self.config {
+                    self.config = config;
+                    self.entity = None;
+                    self.refresh();
+                }
+            }
+            ui.label("samples");
+            let samples = ui.add(DragValue::new(&mut self.samples).clamp_range(1..=4096));
+            ui.label("size");
+            let size = ui.add(DragValue::new(&mut self.size).clamp_range(16..=2048));
+            if samples.changed() || size.changed() {
+                self.refresh();
+            }
+            if self.changed {
+                ui.label("*");
+            }
+            ui.label(&self.status);
+        });
+    }
+
+    fn entities_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("add").clicked() {
+                if let Some(list) = self.scenes_mut() {
+                    list.push(new_entity());
+                    self.entity = Some(list.len() - 1);
+                    self.changed = true;
+                    self.refresh();
+                }
+            }
+            if let Some(i) = self.entity {
+                if ui.button("copy").clicked() {
+                    if let Some(list) = self.scenes_mut() {
+                        list.insert(i + 1, list[i].clone());
+                        self.entity = Some(i + 1);
+                        self.changed = true;
+                        self.refresh();
+                    }
+                }
+                if ui.button("remove").clicked() {
+                    if let Some(list) = self.scenes_mut() {
+                        list.remove(i);
+                        self.entity = None;
+                        self.changed = true;
+                        self.refresh();
+                    }
+                }
+            }
+        });
+        ui.separator();
+        let summaries: Vec<String> = self.scenes().iter().map(summary).collect();
+        ScrollArea::vertical().show(ui, |ui| {
+            for (i, s) in summaries.into_iter().enumerate() {
+                if ui
+                    .selectable_label(self.entity == Some(i), format!("{}: {}", i, s))
+                    .clicked()
+                {
+                    self.entity = Some(i);
+                }
+            }
+        });
+    }
+
+    // the fields of the entity picked, or of the config without one
+    fn fields_ui(&mut self, ui: &mut Ui) {
+        let entity = self.entity;
+        let value = match entity {
+            Some(i) => self.scenes_mut().and_then(|list| list.get_mut(i)),
+            None => self.values.get_mut(self.config),
+        };
+        let value = match value {
+            Some(value) => value,
+            None => return,
+        };
+        let mut changed = false;
+        ScrollArea::vertical().show(ui, |ui| {
+            if let Value::Object(map) = value {
+                for (key, v) in map.iter_mut() {
+                    // in the list on the left
+                    if entity.is_none() && key == "scenes" {
+                        continue;
+                    }
+                    changed |= value_ui(ui, "", key, v, key);
+                }
+            }
+        });
+        if changed {
+            self.changed = true;
+            self.refresh();
+        }
+    }
+
+    fn preview_ui(&mut self, ui: &mut Ui) {
+        let texture = match &self.texture {
+            Some(texture) => texture.id(),
+            None => return,
+        };
+        let settings = self.settings;
+        let (width, height) = (settings.width as f32, settings.height as f32);
+        let available = ui.available_size();
+        let scale = (available.x / width).min(available.y / height);
+        let size = vec2(width * scale, height * scale);
+        let (rect, response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+        let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+        ui.painter().image(texture, rect, uv, Color32::WHITE);
+        // through the pixels of the image
+        let to_scene = |p: Pos2| {
+            let (x, y) = ((p.x - rect.min.x) / scale, (p.y - rect.min.y) / scale);
+            settings.to_world((x as f64, y as f64))
+        };
+        let to_screen = |p: (f64, f64)| {
+            let (x, y) = settings.to_pixel(p);
+            pos2(rect.min.x + x as f32 * scale, rect.min.y + y as f32 * scale)
+        };
+        if response.clicked() || response.drag_started() {
+            if let Some(p) = response.interact_pointer_pos() {
+                self.pick(to_scene(p));
+            }
+        }
+        if response.dragged() {
+            let d = response.drag_delta();
+            let p = response.interact_pointer_pos();
+            if let (Some(i), Some(p), true) = (self.entity, p, d != vec2(0.0, 0.0)) {
+                let ((x0, y0), (x1, y1)) = (to_scene(p - d), to_scene(p));
+                self.translate(i, (x1 - x0, y1 - y0));
+                self.changed = true;
+                self.refresh();
+            }
+        }
+        let aabb = self
+            .entity
+            .and_then(|i| self.entities.get(i))
+            .and_then(|e| e.as_ref())
+            .and_then(|e| e.aabb);
+        // turned with the camera
+        if let Some(aabb) = aabb {
+            let corners = [
+                aabb.min,
+                (aabb.max.0, aabb.min.1),
+                aabb.max,
+                (aabb.min.0, aabb.max.1),
+            ];
+            ui.painter().add(egui::Shape::closed_line(
+                corners.into_iter().map(to_screen).collect(),
+                Stroke::new(1.0, Color32::YELLOW),
+            ));
+        }
+    }
+}
+
+impl eframe::App for Editor {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(result) = self.rendered.lock().unwrap().take() {
+            match result {
+                Ok(image) => {
+                    let size = [image.width() as usize, image.height() as usize];
+                    let image = ColorImage::from_rgb(size, image.as_raw());
+                    match &mut self.texture {
+                        Some(texture) => texture.set(image, TextureOptions::LINEAR),
+                        None => {
+                            self.texture =
+                                Some(ctx.load_texture("preview", image, TextureOptions::LINEAR))
+                        }
+                    }
+                }
+                Err(e) => self.status = e.to_string(),
+            }
+        }
+        if ctx.input(|i| i.modifiers.command && i.key_pressed(Key::S)) {
+            self.save();
+        }
+        // once more to close without saving
+        if ctx.input(|i| i.viewport().close_requested()) && self.changed {
+            ctx.send_viewport_cmd(ViewportCommand::CancelClose);
+            self.changed = false;
+            self.status = "not saved, close again to lose the changes".to_string();
+        }
+        egui::TopBottomPanel::top("top").show(ctx, |ui| self.top_ui(ui));
+        egui::SidePanel::left("entities").show(ctx, |ui| self.entities_ui(ui));
+        egui::SidePanel::right("fields")
+            .min_width(240.0)
+            .show(ctx, |ui| self.fields_ui(ui));
+        egui::CentralPanel::default().show(ctx, |ui| self.preview_ui(ui));
+    }
+}
+
+// the type of the shape and of the material, or what is used in place of the entity
+fn summary(entity: &Value) -> String {
+    let name = |v: Option<&Value>| match v {
+        Some(Value::Object(map)) => map.keys().next().cloned().unwrap_or_default(),
+        Some(Value::String(s)) => s.clone(),
+        _ => String::new(),
+    };
+    if let Some(used) = entity.get("use") {
+        return format!("use {}", name(Some(used)));
+    }
+    if entity.get("include").is_some() {
+        return "include".to_string();
+    }
+    let material = ["material", "emissive", "diffuse", "absorption"]
+        .into_iter()
+        .find(|key| entity.get(key).is_some())
+        .map(|key| match key {
+            "material" => name(entity.get(key)),
+            _ => key.to_string(),
+        })
+        .unwrap_or_default();
+    format!("{} {}", name(entity.get("shape")), material)
+}
+
+// the widgets of the value and the ones in it, true when one of them changed
+fn value_ui(ui: &mut Ui, parent: &str, key: &str, value: &mut Value, id: &str) -> bool {
+    match value {
+        Value::Object(map) => CollapsingHeader::new(key)
+            .id_source(id)
+            .default_open(true)
+            .show(ui, |ui| {
+                let mut changed = false;
+                for (k, v) in map.iter_mut() {
+                    changed |= value_ui(ui, key, k, v, &format!("{}/{}", id, k));
+                }
+                changed
+            })
+            .body_returned
+            .unwrap_or(false),
+        Value::Array(list) => CollapsingHeader::new(key)
+            .id_source(id)
+            .show(ui, |ui| {
+                let mut changed = false;
+                for (i, v) in list.iter_mut().enumerate() {
+                    let k = i.to_string();
+                    changed |= value_ui(ui, key, &k, v, &format!("{}/{}", id, k));
+                }
+                changed
+            })
+            .body_returned
+            .unwrap_or(false),
+        Value::Number(_) => {
+            ui.horizontal(|ui| {
+                ui.label(key);
+                number_ui(ui, parent, key, value)
+            })
+            .inner
+        }
+        Value::Bool(b) => ui.checkbox(b, key).changed(),
+        Value::String(s) => {
+            ui.horizontal(|ui| {
+                ui.label(key);
+                ui.text_edit_singleline(s).changed()
+            })
+            .inner
+        }
+        Value::Null => {
+            ui.label(format!("{}: none", key));
+            false
+        }
+    }
+}
+
+fn number_ui(ui: &mut Ui, parent: &str, key: &str, value: &mut Value) -> bool {
+    let mut x = value.as_f64().unwrap_or_default();
+    let integer = INTEGERS.contains(&key);
+    let changed = match range(parent, key) {
+        Some(range) => ui.add(Slider::new(&mut x, range)).changed(),
+        None if integer => ui.add(DragValue::new(&mut x).speed(1.0)).changed(),
+        None => {
+            let speed = (x.abs() * 0.01).max(0.001);
+            ui.add(DragValue::new(&mut x).speed(speed)).changed()
+        }
+    };
+    if changed {
+        *value = if integer {
+            json!(x.round().max(0.0) as u64)
+        } else {
+            json!(x)
+        };
+    }
+    changed
+}
+
+// the fields with a range, for a slider
+fn range(parent: &str, key: &str) -> Option<RangeInclusive<f64>> {
+    match (parent, key) {
+        (_, "reflectivity" | "roughness") => Some(0.0..=1.0),
+        (_, "eta") => Some(1.0..=3.0),
+        ("hsv" | "hsl", "h") => Some(0.0..=360.0),
+        ("hsv" | "hsl", "s") => Some(0.0..=1.0),
+        _ => None,
+    }
+}
+
+// the config made small and fast
+fn preview(mut item: Config, samples: u32, size: u32) -> Config {
+    let scale = (size as f64 / item.width.max(item.height) as f64).min(1.0);
+    item.width = ((item.width as f64 * scale).round() as u32).max(1);
+    item.height = ((item.height as f64 * scale).round() as u32).max(1);
+    item.stratification = samples;
+    item.adaptive = None;
+    item.progressive = None;
+    item.photons = item.photons.min(samples * 1000);
+    item
+}
+
+// renders the configs sent, only the latest of them when more are waiting
+fn previews(
+    requests: mpsc::Receiver<Config>,
+    rendered: Arc<Mutex<Option<Result<RgbImage, Error>>>>,
+    ctx: egui::Context,
+) {
+    while let Ok(mut item) = requests.recv() {
+        while let Ok(newer) = requests.try_recv() {
+            item = newer;
+        }
+        let image = render(item);
+        *rendered.lock().unwrap() = Some(image);
+        ctx.request_repaint();
+    }
+}
+
+// with the same seed every time, so that the noise stays while a shape is dragged
+fn render(mut item: Config) -> Result<RgbImage, Error> {
+    let seed = item.seed.unwrap_or(0);
+    let mut scene = item.scene(Some(seed))?;
+    let film = Renderer::new(item.settings())
+        .photons(item.photons, item.photon_radius)
+        .render(&mut scene);
+    Ok(film.to_image(item.exposure, item.tone_map, item.gamma, item.dither))
+}
@@ -17,6 +17,93 @@ impl Color {
             b: 0.0,
         }
     }
+
+    // the color of a blackbody at the temperature t (in kelvin), brightest channel at 1.
+    // fitted to the blackbody locus by Tanner Helland, for 1000K to 40000K
+    pub fn kelvin(t: f64) -> Self {
+        let t = t.clamp(1000.0, 40000.0) / 100.0;
+        let r = if t <= 66.0 {
+            255.0
+        } else {
+            329.698727446 * (t - 60.0).powf(-0.1332047592)
+        };
+        let g = if t <= 66.0 {
+            99.4708025861 * t.ln() - 161.1195681661
+        } else {
+            288.1221695283 * (t - 60.0).powf(-0.0755148492)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (t - 10.0).ln() - 305.0447927307
+        };
+        Self {
+            r: r.clamp(0.0, 255.0) / 255.0,
+            g: g.clamp(0.0, 255.0) / 255.0,
+            b: b.clamp(0.0, 255.0) / 255.0,
+        }
+    }
+
+    // h in degrees, s and v from 0 to 1
+    pub fn hsv(h: f64, s: f64, v: f64) -> Self {
+        let c = v * s;
+        Self::hue(h, c, v - c)
+    }
+
+    // h in degrees, s and l from 0 to 1
+    pub fn hsl(h: f64, s: f64, l: f64) -> Self {
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        Self::hue(h, c, l - c / 2.0)
+    }
+
+    // the color of the hue h with the chroma c, plus m on every channel
+    fn hue(h: f64, c: f64, m: f64) -> Self {
+        let h = h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        Self {
+            r: r + m,
+            g: g + m,
+            b: b + m,
+        }
+    }
+
+    // "#rrggbb" or "#rgb", the "#" is optional
+    pub fn hex(s: &str) -> Option<Self> {
+        let s = s.strip_prefix('#').unwrap_or(s);
+        let digits: Vec<u32> = s
+            .chars()
+            .map(|c| c.to_digit(16))
+            .collect::<Option<Vec<u32>>>()?;
+        let (r, g, b) = match digits[..] {
+            [r, g, b] => (r * 17, g * 17, b * 17),
+            [r1, r2, g1, g2, b1, b2] => (r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2),
+            _ => return None,
+        };
+        Some(Self {
+            r: r as f64 / 255.0,
+            g: g as f64 / 255.0,
+            b: b as f64 / 255.0,
+        })
+    }
+
+    // the perceived brightness, rec. 709
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.r <= 0.0 && self.g <= 0.0 && self.b <= 0.0
+    }
 }
 
 impl std::ops::Add<Color> for Color {
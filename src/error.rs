@@ -0,0 +1,44 @@
+use std::{fmt, io};
+
+// what went wrong in the library, with the path of the file when there is one
+#[derive(Debug)]
+pub enum Error {
+    // reading or writing a file
+    Io(String, io::Error),
+    // decoding or encoding an image
+    Image(String, image::ImageError),
+    // a config or font file that can't be parsed
+    Parse(String, String),
+    // the errors of a config, each with the json pointer of the value
+    Config(Vec<String>),
+    // a value out of its range, like too few points for a polygon
+    Invalid(String),
+    // writing the animation, or running ffmpeg for it
+    Animation(String, String),
+    // by the one watching the render, nothing is saved
+    Stopped,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "{}: {}", path, e),
+            Error::Image(path, e) => write!(f, "{}: {}", path, e),
+            Error::Parse(path, e) => write!(f, "{}: {}", path, e),
+            Error::Config(errors) => write!(f, "{}", errors.join("\n")),
+            Error::Invalid(e) => write!(f, "{}", e),
+            Error::Animation(path, e) => write!(f, "{}: {}", path, e),
+            Error::Stopped => write!(f, "the render was stopped"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, e) => Some(e),
+            Error::Image(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::f64::consts::{E, PI, TAU};
+
+// the value of an arithmetic expression like "0.5 + 0.3 * sin(2 * pi * t)", with + - * / % ^,
+// the functions below, the constants pi, tau and e, and the variables
+pub fn eval(s: &str, vars: &HashMap<String, f64>) -> Result<f64, String> {
+    let mut parser = Parser {
+        chars: s.chars().collect(),
+        pos: 0,
+        vars,
+    };
+    let value = parser.expr()?;
+    parser.skip_spaces();
+    if parser.pos < parser.chars.len() {
+        return Err(format!(
+            "unexpected {:?} in {:?}",
+            parser.chars[parser.pos], s
+        ));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    vars: &'a HashMap<String, f64>,
+}
+
+impl Parser<'_> {
+    fn skip_spaces(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_spaces();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                value /= self.unary()?;
+            } else if self.eat('%') {
+                value = value.rem_euclid(self.unary()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.eat('-') {
+            return Ok(-self.unary()?);
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    // right associative, -2^2 is -4
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            return Ok(base.powf(self.unary()?));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let value = self.expr()?;
+                if !self.eat(')') {
+                    return Err("missing )".to_string());
+                }
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {
+                let name = self.name();
+                if self.eat('(') {
+                    let mut args = Vec::new();
+                    if !self.eat(')') {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.eat(')') {
+                                break;
+                            }
+                            if !self.eat(',') {
+                                return Err(format!("missing ) after the arguments of {}", name));
+                            }
+                        }
+                    }
+                    return call(&name, &args);
+                }
+                if let Some(value) = self.vars.get(&name) {
+                    return Ok(*value);
+                }
+                match name.as_str() {
+                    "pi" => Ok(PI),
+                    "tau" => Ok(TAU),
+                    "e" => Ok(E),
+                    _ => Err(format!("unknown variable {}", name)),
+                }
+            }
+            Some(c) => Err(format!("unexpected {:?}", c)),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let start = self.pos;
+        while self.pos < self.chars.len() {
+            let c = self.chars[self.pos];
+            if !(c.is_alphanumeric() || c == '_' || (c == '$' && self.pos == start)) {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        let start = self.pos;
+        let digits = |p: &mut Self| {
+            while p.pos < p.chars.len()
+                && (p.chars[p.pos].is_ascii_digit() || p.chars[p.pos] == '.')
+            {
+                p.pos += 1;
+            }
+        };
+        digits(self);
+        // the exponent, only when digits follow, so that 2e is 2 * e
+        if matches!(self.chars.get(self.pos), Some('e') | Some('E')) {
+            let sign = matches!(self.chars.get(self.pos + 1), Some('+') | Some('-')) as usize;
+            if self
+                .chars
+                .get(self.pos + 1 + sign)
+                .is_some_and(|c| c.is_ascii_digit())
+            {
+                self.pos += 1 + sign;
+                digits(self);
+            }
+        }
+        let s: String = self.chars[start..self.pos].iter().collect();
+        s.parse().map_err(|_| format!("invalid number {}", s))
+    }
+}
+
+fn call(name: &str, args: &[f64]) -> Result<f64, String> {
+    let value = match (name, args) {
+        ("sin", [x]) => x.sin(),
+        ("cos", [x]) => x.cos(),
+        ("tan", [x]) => x.tan(),
+        ("asin", [x]) => x.asin(),
+        ("acos", [x]) => x.acos(),
+        ("atan", [x]) => x.atan(),
+        ("atan2", [y, x]) => y.atan2(*x),
+        ("sqrt", [x]) => x.sqrt(),
+        ("abs", [x]) => x.abs(),
+        ("exp", [x]) => x.exp(),
+        ("ln", [x]) => x.ln(),
+        ("log10", [x]) => x.log10(),
+        ("floor", [x]) => x.floor(),
+        ("ceil", [x]) => x.ceil(),
+        ("round", [x]) => x.round(),
+        ("fract", [x]) => x - x.floor(),
+        ("sign", [x]) => x.signum(),
+        ("pow", [x, y]) => x.powf(*y),
+        ("min", [x, y]) => x.min(*y),
+        ("max", [x, y]) => x.max(*y),
+        ("clamp", [x, a, b]) => x.max(*a).min(*b),
+        // from a to b by t
+        ("mix", [a, b, t]) => a + (b - a) * t,
+        ("deg", [x]) => x.to_degrees(),
+        ("rad", [x]) => x.to_radians(),
+        _ => {
+            return Err(format!(
+                "unknown function {} of {} arguments",
+                name,
+                args.len()
+            ))
+        }
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(s: &str) -> f64 {
+        eval(s, &HashMap::new()).unwrap()
+    }
+
+    fn error(s: &str) -> String {
+        eval(s, &HashMap::new()).unwrap_err()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(value("1 + 2 * 3"), 7.0);
+        assert_eq!(value("(1 + 2) * 3"), 9.0);
+        assert_eq!(value("10 - 4 - 3"), 3.0);
+        assert_eq!(value("12 / 3 / 2"), 2.0);
+        // right associative, and the sign applies after the power
+        assert_eq!(value("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(value("-2 ^ 2"), -4.0);
+        assert_eq!(value("2 ^ -1"), 0.5);
+        assert_eq!(value("--3"), 3.0);
+        assert_eq!(value("+3"), 3.0);
+    }
+
+    #[test]
+    fn remainder_is_never_negative() {
+        assert_eq!(value("7 % 3"), 1.0);
+        assert_eq!(value("-1 % 3"), 2.0);
+        assert_eq!(value("5.5 % 2"), 1.5);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(value("1e3"), 1000.0);
+        assert_eq!(value("2.5e-1"), 0.25);
+        assert_eq!(value("1E+2"), 100.0);
+        assert_eq!(value(".5"), 0.5);
+        // without digits after it, the e is the constant
+        assert_eq!(error("2e"), "unexpected 'e' in \"2e\"");
+        assert_eq!(error("1.2.3"), "invalid number 1.2.3");
+    }
+
+    #[test]
+    fn functions_and_constants() {
+        assert_eq!(value("pi"), PI);
+        assert_eq!(value("tau / 2"), PI);
+        assert_eq!(value("ln(e)"), 1.0);
+        assert!((value("sin(pi / 2)") - 1.0).abs() < 1e-12);
+        assert_eq!(value("atan2(0, -1)"), PI);
+        assert_eq!(value("clamp(5, 0, 1)"), 1.0);
+        assert_eq!(value("mix(0, 10, 0.25)"), 2.5);
+        assert_eq!(value("fract(-0.25)"), 0.75);
+        assert_eq!(value("max(min(1, 2), pow(2, 0))"), 1.0);
+        assert_eq!(value("deg(pi)"), 180.0);
+    }
+
+    #[test]
+    fn variables() {
+        let vars = HashMap::from([("t".to_string(), 0.5), ("$w".to_string(), 4.0)]);
+        assert_eq!(eval("2 * t + $w", &vars), Ok(5.0));
+        // before the constants
+        let vars = HashMap::from([("e".to_string(), 2.0)]);
+        assert_eq!(eval("e", &vars), Ok(2.0));
+    }
+
+    #[test]
+    fn division_by_zero_is_not_an_error() {
+        assert_eq!(value("1 / 0"), f64::INFINITY);
+        assert!(value("0 / 0").is_nan());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error(""), "unexpected end");
+        assert_eq!(error("1 +"), "unexpected end");
+        assert_eq!(error("(1 + 2"), "missing )");
+        assert_eq!(error("1 2"), "unexpected '2' in \"1 2\"");
+        assert_eq!(error("1 + )"), "unexpected ')'");
+        assert_eq!(error("x"), "unknown variable x");
+        assert_eq!(error("sin(1, 2)"), "unknown function sin of 2 arguments");
+        assert_eq!(error("nope()"), "unknown function nope of 0 arguments");
+        assert_eq!(error("max(1 2)"), "missing ) after the arguments of max");
+    }
+}
@@ -0,0 +1,182 @@
+// the functions take the pointers they are given as valid, which is said in the comments
+#![allow(clippy::missing_safety_doc)]
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+    ptr, slice,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    config::{self, Config},
+    error::Error,
+    progress::Progress,
+    render::{photon_map, render, render_progressive, Live, Scene},
+};
+
+// a config loaded by imprint_load, until imprint_free. while it is rendered by imprint_render,
+// imprint_progress and imprint_stop can be called on other threads
+pub struct Job {
+    item: Config,
+    scene: Scene,
+    live: Live,
+    // the steps of the render, pixels or passes
+    done: AtomicU64,
+    total: AtomicU64,
+}
+
+thread_local! {
+    // the message of the last error on the thread
+    static ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(e: Error) {
+    let message = CString::new(e.to_string().replace('\0', " ")).unwrap_or_default();
+    ERROR.with(|error| *error.borrow_mut() = message);
+}
+
+// counts the steps into the job
+struct Count<'a>(&'a Job);
+
+impl Progress for Count<'_> {
+    fn start(&mut self, total: u64) {
+        self.0.done.store(0, Ordering::Relaxed);
+        self.0.total.store(total, Ordering::Relaxed);
+    }
+
+    fn add(&mut self, n: u64) {
+        self.0.done.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn finish_print(&mut self, _message: &str) {}
+
+    fn finish(&mut self) {}
+}
+
+// the config in the nul terminated json, one or a list of them for the first, without files to
+// include. the first frame of an animation. the photon map is built here, with the integrator.
+// null on an error, with the message in imprint_error
+#[no_mangle]
+pub unsafe extern "C" fn imprint_load(json: *const c_char) -> *mut Job {
+    if json.is_null() {
+        fail(Error::Invalid("the config is null".to_string()));
+        return ptr::null_mut();
+    }
+    let job = CStr::from_ptr(json)
+        .to_str()
+        .map_err(|e| Error::Parse("the config".to_string(), e.to_string()))
+        .and_then(config::from_str)
+        .and_then(|mut item| {
+            let mut scene = item.scene(None)?;
+            if scene.needs_photons() {
+                scene.photons = Some(photon_map(
+                    &scene,
+                    item.photons,
+                    item.max_depth,
+                    item.photon_radius,
+                ));
+            }
+            Ok(Job {
+                item,
+                scene,
+                live: Live::new(),
+                done: AtomicU64::new(0),
+                total: AtomicU64::new(0),
+            })
+        });
+    match job {
+        Ok(job) => Box::into_raw(Box::new(job)),
+        Err(e) => {
+            fail(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+// the size of the image of the job
+#[no_mangle]
+pub unsafe extern "C" fn imprint_width(job: *const Job) -> u32 {
+    (*job).item.width
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn imprint_height(job: *const Job) -> u32 {
+    (*job).item.height
+}
+
+// renders the job into buffer, 4 bytes of rgba for each pixel, row by row. len is the size of
+// the buffer, at least width × height × 4. 0 when done, -1 on an error or a stop
+#[no_mangle]
+pub unsafe extern "C" fn imprint_render(job: *const Job, buffer: *mut u8, len: usize) -> c_int {
+    let job = &*job;
+    let item = &job.item;
+    let size = item.width as usize * item.height as usize * 4;
+    if buffer.is_null() || len < size {
+        fail(Error::Invalid(format!(
+            "the buffer has {} bytes, {} are needed",
+            len, size
+        )));
+        return -1;
+    }
+    let film = match item.passes() {
+        Some(progressive) => render_progressive(
+            &job.scene,
+            &item.settings(),
+            progressive,
+            &job.live,
+            |_| Ok(()),
+            Count(job),
+        ),
+        None => Ok(render(&job.scene, &item.settings(), &job.live, Count(job))),
+    };
+    let film = match film {
+        Ok(_) if job.live.is_stopped() => Err(Error::Stopped),
+        film => film,
+    };
+    match film {
+        Ok(film) => {
+            let image = film.to_image(item.exposure, item.tone_map, item.gamma, item.dither);
+            let buffer = slice::from_raw_parts_mut(buffer, size);
+            for (rgba, p) in buffer.chunks_exact_mut(4).zip(image.pixels()) {
+                rgba.copy_from_slice(&[p[0], p[1], p[2], 255]);
+            }
+            0
+        }
+        Err(e) => {
+            fail(e);
+            -1
+        }
+    }
+}
+
+// the part of the render done, from 0 to 1
+#[no_mangle]
+pub unsafe extern "C" fn imprint_progress(job: *const Job) -> f64 {
+    let job = &*job;
+    match job.total.load(Ordering::Relaxed) {
+        0 => 0.0,
+        total => job.done.load(Ordering::Relaxed) as f64 / total as f64,
+    }
+}
+
+// stops the render of the job, the renders after it stop at once too
+#[no_mangle]
+pub unsafe extern "C" fn imprint_stop(job: *const Job) {
+    (*job).live.stop();
+}
+
+// the job is not used after it, null is ignored
+#[no_mangle]
+pub unsafe extern "C" fn imprint_free(job: *mut Job) {
+    if !job.is_null() {
+        drop(Box::from_raw(job));
+    }
+}
+
+// the message of the last error on the thread, until the next one
+#[no_mangle]
+pub extern "C" fn imprint_error() -> *const c_char {
+    ERROR.with(|error| error.borrow().as_ptr())
+}
@@ -0,0 +1,492 @@
+use image::{ImageBuffer, Rgb, Rgb32FImage, RgbImage};
+use std::{
+    cmp::min,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{element::Color, error::Error};
+
+// the float image a render accumulates into, turned into 8 bits only when saved.
+// with the samples of every pixel, for the heatmap of stats_image
+#[derive(Clone)]
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Color>,
+    stats: Vec<Stats>,
+}
+
+#[allow(dead_code)]
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::black(); (width * height) as usize],
+            stats: vec![Stats::default(); (width * height) as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn put(&mut self, x: u32, y: u32, color: Color) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    pub fn get_stats(&self, x: u32, y: u32) -> Stats {
+        self.stats[(y * self.width + x) as usize]
+    }
+
+    pub fn put_stats(&mut self, x: u32, y: u32, stats: Stats) {
+        self.stats[(y * self.width + x) as usize] = stats;
+    }
+
+    // the pixels and the stats of the other film, of the same size, added to these
+    pub fn add(&mut self, other: &Film) {
+        for (p, &q) in self.pixels.iter_mut().zip(&other.pixels) {
+            *p = *p + q;
+        }
+        for (s, &t) in self.stats.iter_mut().zip(&other.stats) {
+            *s = *s + t;
+        }
+    }
+
+    // the pixels times the ones of the other film, of the same size
+    pub fn multiply(&mut self, other: &Film) {
+        for (p, &q) in self.pixels.iter_mut().zip(&other.pixels) {
+            *p = *p * q;
+        }
+    }
+
+    // the samples taken by every pixel on the left, and the noise left on the right, each from
+    // black for none to white for the most in the image
+    pub fn stats_image(&self) -> RgbImage {
+        let most = |f: fn(&Stats) -> f64| self.stats.iter().map(f).fold(0.0, f64::max);
+        let count = |s: &Stats| s.count as f64;
+        let noise = |s: &Stats| s.noise();
+        let (most_count, most_noise) = (most(count), most(noise));
+        ImageBuffer::from_fn(self.width * 2, self.height, |x, y| {
+            let (v, most) = match x < self.width {
+                true => (count(&self.get_stats(x, y)), most_count),
+                false => (noise(&self.get_stats(x - self.width, y)), most_noise),
+            };
+            heat(if most > 0.0 { v / most } else { 0.0 })
+        })
+    }
+
+    // the film at another size, every pixel the mean of the pixels under it, each weighted by
+    // how much of it is under it. without the stats
+    pub fn resize(&self, width: u32, height: u32) -> Film {
+        let sx = self.width as f64 / width as f64;
+        let sy = self.height as f64 / height as f64;
+        let mut film = Film::new(width, height);
+        for y in 0..height {
+            let (y0, y1) = (y as f64 * sy, (y + 1) as f64 * sy);
+            for x in 0..width {
+                let (x0, x1) = (x as f64 * sx, (x + 1) as f64 * sx);
+                let mut sum = Color::black();
+                let mut area = 0.0;
+                for j in y0 as u32..min(y1.ceil() as u32, self.height) {
+                    let h = y1.min(j as f64 + 1.0) - y0.max(j as f64);
+                    for i in x0 as u32..min(x1.ceil() as u32, self.width) {
+                        let w = x1.min(i as f64 + 1.0) - x0.max(i as f64);
+                        sum = sum + self.get(i, j) * (w * h);
+                        area += w * h;
+                    }
+                }
+                film.put(x, y, sum * (1.0 / area));
+            }
+        }
+        film
+    }
+
+    // every pixel scaled by the exposure and tone mapped, still linear
+    pub fn tone_mapped(&self, exposure: f64, tone_map: ToneMap) -> Film {
+        let mut film = self.clone();
+        for c in &mut film.pixels {
+            *c = tone_map.apply(*c * exposure);
+        }
+        film
+    }
+
+    // every pixel scaled by the exposure, tone mapped, clamped, then encoded by the srgb curve,
+    // or by the power 1 / gamma when there is one, and cut to its level after the dither
+    pub fn to_image(
+        &self,
+        exposure: f64,
+        tone_map: ToneMap,
+        gamma: Option<f64>,
+        dither: Dither,
+    ) -> RgbImage {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let c = tone_map.apply(self.get(x, y) * exposure);
+            to_rgb(c, gamma, dither.at(x, y))
+        })
+    }
+
+    // to_image, with 16 bits per channel
+    pub fn to_image16(
+        &self,
+        exposure: f64,
+        tone_map: ToneMap,
+        gamma: Option<f64>,
+    ) -> ImageBuffer<Rgb<u16>, Vec<u16>> {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let c = tone_map.apply(self.get(x, y) * exposure);
+            let f = |v: f64| min((encode(v, gamma) * 65535.0) as u32, 65535) as u16;
+            Rgb([f(c.r), f(c.g), f(c.b)])
+        })
+    }
+
+    // only scaled by the exposure
+    pub fn to_float_image(&self, exposure: f64) -> Rgb32FImage {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let c = self.get(x, y) * exposure;
+            Rgb([c.r as f32, c.g as f32, c.b as f32])
+        })
+    }
+
+    // by the extension of the path, the float values for exr and pfm, to_image, or to_image16
+    // by the bit depth, for the others. only the 8 bits are dithered
+    pub fn save(
+        &self,
+        path: &str,
+        exposure: f64,
+        tone_map: ToneMap,
+        gamma: Option<f64>,
+        bit_depth: u8,
+        dither: Dither,
+    ) -> Result<(), Error> {
+        create_parent(path)?;
+        let image_error = |e| Error::Image(path.to_string(), e);
+        match extension(path).as_deref() {
+            Some("exr") => self
+                .to_float_image(exposure)
+                .save(path)
+                .map_err(image_error),
+            Some("pfm") => self
+                .save_pfm(path, exposure)
+                .map_err(|e| Error::Io(path.to_string(), e)),
+            _ => match bit_depth {
+                8 => self
+                    .to_image(exposure, tone_map, gamma, dither)
+                    .save(path)
+                    .map_err(image_error),
+                16 => self
+                    .to_image16(exposure, tone_map, gamma)
+                    .save(path)
+                    .map_err(image_error),
+                _ => Err(Error::Invalid(format!(
+                    "bit depth {}, should be 8 or 16",
+                    bit_depth
+                ))),
+            },
+        }
+    }
+
+    fn save_pfm(&self, path: &str, exposure: f64) -> std::io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_pfm(&mut w, exposure)?;
+        w.flush()
+    }
+
+    // the portable float map, little endian, its rows from the bottom up
+    fn write_pfm<W: Write>(&self, w: &mut W, exposure: f64) -> std::io::Result<()> {
+        write!(w, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        let img = self.to_float_image(exposure);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                for v in img.get_pixel(x, y).0 {
+                    w.write_all(&v.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// of the samples of a pixel, by their luminance
+#[derive(Clone, Copy, Default)]
+pub struct Stats {
+    pub count: u32,
+    pub sum: f64,
+    pub sum2: f64,
+}
+
+#[allow(dead_code)]
+impl Stats {
+    pub fn add(&mut self, color: Color) {
+        let l = color.luminance();
+        self.count += 1;
+        self.sum += l;
+        self.sum2 += l * l;
+    }
+
+    // of the samples, 0 with less than 2
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        let n = self.count as f64;
+        ((self.sum2 - self.sum * self.sum / n) / (n - 1.0)).max(0.0)
+    }
+
+    // the standard error of the mean, the noise left in the pixel
+    pub fn noise(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            n => (self.variance() / n as f64).sqrt(),
+        }
+    }
+}
+
+impl std::ops::Add for Stats {
+    type Output = Stats;
+
+    fn add(self, rhs: Stats) -> Stats {
+        Stats {
+            count: self.count + rhs.count,
+            sum: self.sum + rhs.sum,
+            sum2: self.sum2 + rhs.sum2,
+        }
+    }
+}
+
+// from black through blue, red and yellow to white, for v from 0 to 1
+fn heat(v: f64) -> Rgb<u8> {
+    const STOPS: [[f64; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 1.0, 1.0],
+    ];
+    let v = v.clamp(0.0, 1.0) * 4.0;
+    let i = (v as usize).min(3);
+    let t = v - i as f64;
+    let c = |k: usize| ((STOPS[i][k] * (1.0 - t) + STOPS[i + 1][k] * t) * 255.0).round() as u8;
+    Rgb([c(0), c(1), c(2)])
+}
+
+// how the colors above 1 are brought into the image, channel by channel
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum ToneMap {
+    // cut off at 1
+    #[default]
+    #[serde(rename = "clamp")]
+    Clamp,
+    // c / (1 + c), extended so that the white maps to 1
+    #[serde(rename = "reinhard")]
+    Reinhard {
+        #[serde(default = "default_reinhard_white")]
+        white: f64,
+    },
+    // the fit of the ACES filmic curve by Krzysztof Narkowicz
+    #[serde(rename = "aces")]
+    Aces,
+    // the curve of John Hable for Uncharted 2
+    #[serde(rename = "filmic")]
+    Filmic {
+        #[serde(default = "default_filmic_white")]
+        white: f64,
+    },
+}
+
+fn default_reinhard_white() -> f64 {
+    f64::INFINITY
+}
+
+fn default_filmic_white() -> f64 {
+    11.2
+}
+
+impl ToneMap {
+    pub fn apply(&self, c: Color) -> Color {
+        let f = |v: f64| self.map(v.max(0.0));
+        Color {
+            r: f(c.r),
+            g: f(c.g),
+            b: f(c.b),
+        }
+    }
+
+    fn map(&self, v: f64) -> f64 {
+        match self {
+            ToneMap::Clamp => v,
+            ToneMap::Reinhard { white } => v * (1.0 + v / (white * white)) / (1.0 + v),
+            ToneMap::Aces => {
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                v * (a * v + b) / (v * (c * v + d) + e)
+            }
+            ToneMap::Filmic { white } => {
+                // the exposure bias of the original
+                hable(2.0 * v) / hable(*white)
+            }
+        }
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+// of the 8 bit images, added to the levels of the colors before they are cut, so that the
+// smooth gradients don't band
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Dither {
+    // the colors cut down to their levels
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    // by the 8x8 bayer matrix
+    #[serde(rename = "ordered")]
+    Ordered,
+    // by the interleaved gradient noise of Jorge Jimenez, without the grid of ordered
+    #[serde(rename = "noise")]
+    Noise,
+}
+
+impl Dither {
+    // of the pixel, from 0 to 1
+    fn at(&self, x: u32, y: u32) -> f64 {
+        match self {
+            Dither::None => 0.0,
+            Dither::Ordered => {
+                // the bits of x ^ y and x interleaved, the lowest ones first
+                let (x, y) = (x & 7, y & 7);
+                let v = (0..3).fold(0, |v, k| {
+                    v | ((((x ^ y) >> k) & 1) << (5 - 2 * k)) | (((x >> k) & 1) << (4 - 2 * k))
+                });
+                (v as f64 + 0.5) / 64.0
+            }
+            Dither::Noise => {
+                let (x, y) = (x as f64, y as f64);
+                (52.982919 * (0.06711056 * x + 0.00583715 * y).fract()).fract()
+            }
+        }
+    }
+}
+
+// the directory the file is saved in, with the ones above it
+pub fn create_parent(path: &str) -> Result<(), Error> {
+    match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            fs::create_dir_all(dir).map_err(|e| Error::Io(dir.display().to_string(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+// in lowercase
+pub(crate) fn extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+// the dither from 0 to 1 is added to the level before it is cut
+fn to_rgb(color: Color, gamma: Option<f64>, dither: f64) -> Rgb<u8> {
+    let f = |v: f64| min((encode(v, gamma) * 255.0 + dither) as u32, 255) as u8;
+    Rgb([f(color.r), f(color.g), f(color.b)])
+}
+
+// from the linear value to the one stored in the image
+fn encode(v: f64, gamma: Option<f64>) -> f64 {
+    let v = v.clamp(0.0, 1.0);
+    match gamma {
+        Some(gamma) => v.powf(1.0 / gamma),
+        None if v <= 0.0031308 => v * 12.92,
+        None => 1.055 * v.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn tone_map_curves() {
+        let maps = [
+            ToneMap::Clamp,
+            ToneMap::Reinhard {
+                white: f64::INFINITY,
+            },
+            ToneMap::Reinhard { white: 4.0 },
+            ToneMap::Aces,
+            ToneMap::Filmic { white: 11.2 },
+        ];
+        for map in &maps {
+            assert!(close(map.map(0.0), 0.0));
+            // rising, and the negative values are black
+            let vs: Vec<f64> = (0..100).map(|i| map.map(i as f64 * 0.1)).collect();
+            assert!(vs.windows(2).all(|w| w[0] < w[1]), "{:?}", vs);
+            assert_eq!(map.apply(Color::grey(-1.0)).r, 0.0);
+        }
+        assert_eq!(ToneMap::Clamp.map(2.5), 2.5);
+        let reinhard = |white: f64| ToneMap::Reinhard { white };
+        assert!(close(reinhard(f64::INFINITY).map(1.0), 0.5));
+        assert!(close(reinhard(f64::INFINITY).map(3.0), 0.75));
+        // the white is brought to 1
+        assert!(close(reinhard(4.0).map(4.0), 1.0));
+        assert!(close(ToneMap::Filmic { white: 11.2 }.map(5.6), 1.0));
+        assert!(close(ToneMap::Aces.map(1.0), 2.54 / 3.16));
+        // towards a / c
+        assert!((ToneMap::Aces.map(1e6) - 2.51 / 2.43).abs() < 1e-6);
+    }
+
+    #[test]
+    fn srgb_curve() {
+        assert_eq!(encode(0.0, None), 0.0);
+        assert!(close(encode(1.0, None), 1.0));
+        assert!((encode(0.5, None) - 0.735357).abs() < 1e-6);
+        assert!(close(encode(0.002, None), 0.002 * 12.92));
+        // the two parts meet at the threshold
+        let (below, above) = (encode(0.0031308, None), encode(0.0031309, None));
+        assert!((above - below).abs() < 1e-5, "{} {}", below, above);
+        // clamped
+        assert_eq!(encode(-0.5, None), 0.0);
+        assert!(close(encode(3.0, None), 1.0));
+        assert!(close(encode(0.5, Some(2.0)), 0.5f64.sqrt()));
+        assert!(close(encode(0.25, Some(1.0)), 0.25));
+        let rgb = to_rgb(Color::grey(0.5), None, 0.0);
+        assert_eq!(rgb, Rgb([187, 187, 187]));
+        // the dither never goes past the top level
+        assert_eq!(to_rgb(Color::grey(1.0), None, 0.99), Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn pfm_layout() {
+        let mut film = Film::new(2, 2);
+        film.put(0, 0, Color::grey(1.0));
+        film.put(
+            1,
+            1,
+            Color {
+                r: 0.25,
+                g: 0.5,
+                b: 2.0,
+            },
+        );
+        let mut bytes = Vec::new();
+        film.write_pfm(&mut bytes, 2.0).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let values: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        // the bottom row first, scaled by the exposure
+        let expected = [[0.0; 3], [0.5, 1.0, 4.0], [2.0; 3], [0.0; 3]];
+        assert_eq!(values, expected.concat());
+    }
+}
@@ -0,0 +1,202 @@
+use image::RgbImage;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{self, Config},
+    error::Error,
+    film::{create_parent, extension},
+    render::Renderer,
+};
+
+// of the scenes rendered and compared by the tests and the compare subcommand
+pub const DIR: &str = "test-scenes";
+
+// how far the render may be from its reference, on the values of the image from 0 to 1
+#[derive(Clone, Copy)]
+pub struct Tolerance {
+    // of the differences of all the pixels
+    pub mean: f64,
+    // a pixel further than this is a differing one
+    pub pixel: f64,
+    // the share of the differing pixels
+    pub differing: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            mean: 0.01,
+            pixel: 0.1,
+            differing: 0.01,
+        }
+    }
+}
+
+// of two images of the same size, each blurred over 3x3 pixels so that the noise counts less
+// than the shapes, by the largest difference of the channels of a pixel
+#[derive(Clone, Copy, Debug)]
+pub struct Difference {
+    pub mean: f64,
+    // the share of the pixels further than the pixel of the tolerance
+    pub differing: f64,
+}
+
+impl Difference {
+    pub fn within(&self, tolerance: &Tolerance) -> bool {
+        self.mean <= tolerance.mean && self.differing <= tolerance.differing
+    }
+}
+
+pub enum Verdict {
+    // blessed, the reference is the render now
+    Saved,
+    // no reference to compare with, a failure without bless
+    Missing,
+    Same(Difference),
+    Different(Difference),
+}
+
+// of a scene of the directory, by the name of its file
+pub struct Outcome {
+    pub name: String,
+    pub verdict: Result<Verdict, Error>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        matches!(self.verdict, Ok(Verdict::Saved) | Ok(Verdict::Same(_)))
+    }
+}
+
+// every json file of the directory rendered by its first config, with the seed of it, and
+// compared with the png of the same name in the golden directory next to them. the renders
+// are saved in out to be looked at, and in place of the references only with bless
+pub fn run(
+    dir: &Path,
+    out: &Path,
+    tolerance: &Tolerance,
+    bless: bool,
+) -> Result<Vec<Outcome>, Error> {
+    let paths = scenes(dir)?;
+    let golden = dir.join("golden");
+    fs::create_dir_all(out).map_err(|e| Error::Io(out.display().to_string(), e))?;
+    Ok(paths
+        .iter()
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            let file = format!("{}.png", name);
+            let verdict = render(path).and_then(|image| {
+                let rendered = out.join(&file);
+                image
+                    .save(&rendered)
+                    .map_err(|e| Error::Image(rendered.display().to_string(), e))?;
+                judge(&image, &golden.join(&file), tolerance, bless)
+            });
+            Outcome { name, verdict }
+        })
+        .collect())
+}
+
+// the json files of the directory, by their names
+pub fn scenes(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| Error::Io(dir.display().to_string(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| extension(&path.display().to_string()).as_deref() == Some("json"))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+// the image of the first config of the file, without the effects after the render
+pub fn render(path: &Path) -> Result<RgbImage, Error> {
+    let mut item = load(path)?;
+    let mut scene = item.scene(None)?;
+    let film = Renderer::new(item.settings())
+        .photons(item.photons, item.photon_radius)
+        .render(&mut scene);
+    Ok(film.to_image(item.exposure, item.tone_map, item.gamma, item.dither))
+}
+
+// the first frame of the first config of the file
+pub fn load(path: &Path) -> Result<Config, Error> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let value = config::read(path)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Invalid(format!("{}: no config", path.display())))?;
+    config::expand(config::include(value, dir)?)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Invalid(format!("{}: the config has no frames", path.display())))
+}
+
+fn judge(
+    image: &RgbImage,
+    reference: &Path,
+    tolerance: &Tolerance,
+    bless: bool,
+) -> Result<Verdict, Error> {
+    let name = reference.display().to_string();
+    if bless {
+        create_parent(&name)?;
+        image.save(reference).map_err(|e| Error::Image(name, e))?;
+        return Ok(Verdict::Saved);
+    }
+    if !reference.exists() {
+        return Ok(Verdict::Missing);
+    }
+    let expected = image::open(reference)
+        .map_err(|e| Error::Image(name.clone(), e))?
+        .to_rgb8();
+    let difference = compare(image, &expected, tolerance)
+        .ok_or_else(|| Error::Invalid(format!("{}: not of the size of the render", name)))?;
+    if difference.within(tolerance) {
+        return Ok(Verdict::Same(difference));
+    }
+    Ok(Verdict::Different(difference))
+}
+
+// none when the sizes differ
+pub fn compare(a: &RgbImage, b: &RgbImage, tolerance: &Tolerance) -> Option<Difference> {
+    if a.dimensions() != b.dimensions() {
+        return None;
+    }
+    let (a, b) = (blur(a), blur(b));
+    let distances: Vec<f64> = a
+        .iter()
+        .zip(&b)
+        .map(|(p, q)| (0..3).map(|k| (p[k] - q[k]).abs()).fold(0.0, f64::max))
+        .collect();
+    let count = distances.len().max(1) as f64;
+    Some(Difference {
+        mean: distances.iter().sum::<f64>() / count,
+        differing: distances.iter().filter(|&&d| d > tolerance.pixel).count() as f64 / count,
+    })
+}
+
+// the channels from 0 to 1, each the mean of the 3x3 pixels around, the ones on the edges
+// of the pixels inside
+fn blur(image: &RgbImage) -> Vec<[f64; 3]> {
+    let (w, h) = (image.width() as i64, image.height() as i64);
+    (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let mut sum = [0.0; 3];
+            let mut count = 0.0;
+            for sy in (y - 1).max(0)..=(y + 1).min(h - 1) {
+                for sx in (x - 1).max(0)..=(x + 1).min(w - 1) {
+                    let p = image.get_pixel(sx as u32, sy as u32);
+                    for (s, v) in sum.iter_mut().zip(p.0) {
+                        *s += v as f64 / 255.0;
+                    }
+                    count += 1.0;
+                }
+            }
+            sum.map(|v| v / count)
+        })
+        .collect()
+}
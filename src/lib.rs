@@ -1,7 +1,36 @@
+pub mod animation;
+pub mod builder;
+pub mod bvh;
 pub mod calculate;
 pub mod config;
+pub mod debug;
+#[cfg(feature = "editor")]
+pub mod editor;
 pub mod element;
+pub mod error;
+pub mod expr;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod film;
+pub mod golden;
+pub mod light;
+pub mod mask;
+pub mod material;
+pub mod medium;
+pub mod noise;
+pub mod photon;
+pub mod post;
+pub mod profile;
+pub mod progress;
+pub mod random;
 pub mod render;
+pub mod ron;
 pub mod shapes;
+pub mod text;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "window")]
+pub mod window;
 #[macro_use]
 extern crate serde_derive;
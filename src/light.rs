@@ -0,0 +1,104 @@
+use std::f64::consts::PI;
+
+use crate::{calculate::distance, element::Color};
+
+// the nearest distance a light is evaluated at, so that it stays finite on its own position
+const MIN_DISTANCE: f64 = 1e-3;
+const WHOLE_ANGLE: f64 = 360.0;
+
+// a light without a shape, it can't be hit by rays, so it's sampled directly from every point
+pub trait Light {
+    fn position(&self) -> (f64, f64);
+    // the light arriving at p, shadows not considered
+    fn illuminate(&self, p: (f64, f64)) -> Color;
+    // the radius around the position the shadow rays aim within, the wider the softer the
+    // shadows. 0 for the hard ones of a point
+    fn softness(&self) -> f64 {
+        0.0
+    }
+}
+
+#[allow(dead_code)]
+pub struct PointLight {
+    pub x: f64,
+    pub y: f64,
+    pub color: Color,
+    pub intensity: f64,
+    // the exponent of the distance, 2 for the inverse square law
+    pub falloff: f64,
+    pub softness: f64,
+}
+
+impl Light for PointLight {
+    fn position(&self) -> (f64, f64) {
+        (self.x, self.y)
+    }
+
+    fn illuminate(&self, p: (f64, f64)) -> Color {
+        let d = distance(p, (self.x, self.y)).max(MIN_DISTANCE);
+        self.color * (self.intensity / d.powf(self.falloff))
+    }
+
+    fn softness(&self) -> f64 {
+        self.softness
+    }
+}
+
+// a point light restricted to a cone around the direction e (in degrees, counterclockwise on
+// the image), full inside the inner half angle and fading out smoothly until the outer one
+#[allow(dead_code)]
+pub struct SpotLight {
+    light: PointLight,
+    dx: f64,
+    dy: f64,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+#[allow(dead_code)]
+impl SpotLight {
+    pub fn new(light: PointLight, e: f64, inner: f64, outer: f64) -> Self {
+        let theta = -2.0 * PI * e / WHOLE_ANGLE;
+        let outer = outer.clamp(0.0, WHOLE_ANGLE / 2.0);
+        let inner = inner.clamp(0.0, outer);
+        Self {
+            light,
+            dx: theta.cos(),
+            dy: theta.sin(),
+            cos_inner: (2.0 * PI * inner / WHOLE_ANGLE).cos(),
+            cos_outer: (2.0 * PI * outer / WHOLE_ANGLE).cos(),
+        }
+    }
+
+    fn cone(&self, (px, py): (f64, f64)) -> f64 {
+        let (lx, ly) = self.light.position();
+        let len = distance((px, py), (lx, ly));
+        if len < MIN_DISTANCE {
+            return 1.0;
+        }
+        let cos = ((px - lx) * self.dx + (py - ly) * self.dy) / len;
+        if cos >= self.cos_inner {
+            return 1.0;
+        }
+        if cos <= self.cos_outer {
+            return 0.0;
+        }
+        // smoothstep
+        let s = (cos - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        s * s * (3.0 - 2.0 * s)
+    }
+}
+
+impl Light for SpotLight {
+    fn position(&self) -> (f64, f64) {
+        self.light.position()
+    }
+
+    fn illuminate(&self, p: (f64, f64)) -> Color {
+        self.light.illuminate(p) * self.cone(p)
+    }
+
+    fn softness(&self) -> f64 {
+        self.light.softness
+    }
+}
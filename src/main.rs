@@ -1,12 +1,72 @@
-use clap::{App, Arg};
-use std::fs::File;
+use clap::{App, Arg, ArgMatches};
+use image::{DynamicImage, ImageOutputFormat, RgbImage};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use pbr::{MultiBar, ProgressBar};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::{Method, Response};
 
+#[cfg(feature = "editor")]
+use imprint_of_light::editor;
+#[cfg(feature = "window")]
+use imprint_of_light::window;
 use imprint_of_light::{
-    config::Config,
-    render::{render as r, Entity, Scene},
+    animation::{self, Animation},
+    config::{self, Config},
+    debug::{self, DebugShapes},
+    error::Error,
+    film::{self, Dither, Film, ToneMap},
+    golden::{self, Tolerance, Verdict},
+    profile::{self, Profile},
+    progress::{JsonLines, Progress, Sequence},
+    render::{
+        ambient_occlusion, aovs, photon_map, render as r, render_progressive, Live, Renderer, Scene,
+    },
 };
 
+// of the arguments, or the config file that can't be read
+const EXIT_USAGE: i32 = 1;
+// some of the configs are invalid, the others are rendered
+const EXIT_CONFIG: i32 = 2;
+// an image that couldn't be rendered or saved
+const EXIT_RENDER: i32 = 3;
+// a render of compare unlike its reference
+const EXIT_DIFFERENT: i32 = 4;
+
+// of every pixel, for --watch without preview
+const PREVIEW_SAMPLES: u32 = 16;
+
+// what is printed besides the errors, by --progress and --quiet
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+    // a bar for every image, and the messages
+    Bar,
+    // a json line for every event of a render, and nothing else
+    Json,
+    Quiet,
+}
+
+static OUTPUT: OnceLock<Output> = OnceLock::new();
+
+fn output() -> Output {
+    *OUTPUT.get().unwrap_or(&Output::Bar)
+}
+
+// a message, only with the bars
+fn say(message: &str) {
+    if output() == Output::Bar {
+        println!("{}", message);
+    }
+}
+
 fn main() {
     args_check();
 }
@@ -22,42 +82,1435 @@ fn args_check() {
                 .long("config")
                 .value_name("FILE")
                 .help("the config file for rendering images")
-                .default_value("config.json"),
+                .default_value("config.json")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("the seed of the random numbers, instead of the ones in the config")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .help("render the images at the same time, on N threads in all")
+                .global(true),
+        )
+        // over the values of every config
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .value_name("PIXELS")
+                .help("the width of the images, instead of the ones in the config")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("height")
+                .long("height")
+                .value_name("PIXELS")
+                .help("the height of the images, instead of the ones in the config")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("stratification")
+                .long("stratification")
+                .value_name("N")
+                .help("the samples of every pixel, instead of the ones in the config")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .value_name("N")
+                .help("the bounces of a path, instead of the ones in the config")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("time-limit")
+                .long("time-limit")
+                .value_name("SECONDS")
+                .help("of every image, then the samples taken are saved, instead of max_seconds in the config")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("debug-shapes")
+                .long("debug-shapes")
+                .value_name("over|only")
+                .help("draw the outlines of the shapes, their normals and the emitters over the images, or only them with =only")
+                .possible_values(["over", "only"])
+                .min_values(0)
+                .require_equals(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("out-dir")
+                .long("out-dir")
+                .value_name("DIR")
+                .help("the directory the images are saved in, with the file names of the config")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("no-clobber")
+                .long("no-clobber")
+                .help("skip the configs whose images are there already, instead of overwrite in the config")
+                .conflicts_with("force")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("render the images again even when they are there already, instead of overwrite in the config")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("only")
+                .long("only")
+                .value_name("NAMES")
+                .help(
+                    "only the configs with these names or indexes in the file, even disabled ones",
+                )
+                .use_value_delimiter(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("skip")
+                .long("skip")
+                .value_name("NAMES")
+                .help("not the configs with these names or indexes in the file")
+                .use_value_delimiter(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .help("render the images again at the quality of the preview, when the config file is saved")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("window")
+                .long("window")
+                .help("show the images as they are rendered, s saves the image so far, escape stops it. not with --jobs")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("progress")
+                .long("progress")
+                .value_name("FORMAT")
+                .help("the progress of the renders, as bars, or as json lines for the programs reading it")
+                .possible_values(["bar", "json"])
+                .default_value("bar")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short('q')
+                .long("quiet")
+                .help("print nothing but the errors")
+                .global(true),
+        )
+        // render without one
+        .subcommand(App::new("render").about("render the images of the configs"))
+        .subcommand(
+            App::new("preview")
+                .about("render the images fast with few samples, into <out>_preview files")
+                .arg(
+                    Arg::with_name("samples")
+                        .long("samples")
+                        .value_name("N")
+                        .help("the samples of every pixel")
+                        .default_value("16"),
+                ),
+        )
+        .subcommand(App::new("validate").about("check the configs without rendering them"))
+        .subcommand(App::new("info").about("print the size, samples and entities of every config"))
+        .subcommand(
+            App::new("serve")
+                .about("render the configs for a web page showing the image so far, new configs are posted to it")
+                .arg(
+                    Arg::with_name("address")
+                        .long("address")
+                        .value_name("HOST:PORT")
+                        .help("where the page is served")
+                        .default_value("127.0.0.1:8080"),
+                ),
+        )
+        .subcommand(
+            App::new("compare")
+                .about("render the test scenes and compare them with their reference images")
+                .arg(
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .value_name("DIR")
+                        .help("of the scenes, with the references in its golden directory")
+                        .default_value(golden::DIR),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .value_name("DIR")
+                        .help("where the renders are saved to be looked at, a temporary directory without"),
+                )
+                .arg(
+                    Arg::with_name("bless")
+                        .long("bless")
+                        .help("save the renders as the references instead"),
+                ),
+        )
+        .subcommand(
+            App::new("bench")
+                .about("render the test scenes and print the rays per second, the time of the intersections, the shading and the output, and the tests of every kind of shape")
+                .arg(
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .value_name("DIR")
+                        .help("of the scenes")
+                        .default_value(golden::DIR),
+                ),
+        )
+        .subcommand(
+            App::new("edit")
+                .about("move the entities and change their fields in a window, with a preview"),
         )
         .get_matches();
 
     let config_file_name = matches.value_of("config").unwrap();
+    let output = match matches.value_of("progress") {
+        _ if matches.is_present("quiet") => Output::Quiet,
+        Some("json") => Output::Json,
+        _ => Output::Bar,
+    };
+    OUTPUT.set(output).ok();
+    let seed: Option<u64> = matches.value_of("seed").map(|s| match s.parse() {
+        Ok(seed) => seed,
+        Err(e) => {
+            eprintln!("--seed {}: {}", s, e);
+            process::exit(EXIT_USAGE)
+        }
+    });
+    let jobs: Option<usize> = matches.value_of("jobs").map(|s| match s.parse() {
+        Ok(jobs) if jobs > 0 => jobs,
+        Ok(_) => {
+            eprintln!("--jobs {}: should be at least 1", s);
+            process::exit(EXIT_USAGE)
+        }
+        Err(e) => {
+            eprintln!("--jobs {}: {}", s, e);
+            process::exit(EXIT_USAGE)
+        }
+    });
+
+    if let Some(("edit", _)) = matches.subcommand() {
+        edit(Path::new(config_file_name));
+    }
+    if let Some(("bench", m)) = matches.subcommand() {
+        let code = bench(Path::new(m.value_of("dir").unwrap()));
+        process::exit(code)
+    }
+    if let Some(("compare", m)) = matches.subcommand() {
+        let out = match m.value_of("out") {
+            Some(out) => PathBuf::from(out),
+            None => env::temp_dir().join("imprint_of_light").join(golden::DIR),
+        };
+        let code = compare(
+            Path::new(m.value_of("dir").unwrap()),
+            &out,
+            m.is_present("bless"),
+        );
+        process::exit(code)
+    }
+
+    let window = matches.is_present("window");
+    if window && !cfg!(feature = "window") {
+        eprintln!("--window: built without the window, by cargo build --features window");
+        process::exit(EXIT_USAGE)
+    }
+    if window && jobs.is_some() {
+        eprintln!("--window: one image at a time, not with --jobs");
+        process::exit(EXIT_USAGE)
+    }
 
-    let file = match File::open(config_file_name) {
-        Ok(f) => f,
+    let overrides = Overrides {
+        width: number(&matches, "width", 1),
+        height: number(&matches, "height", 1),
+        stratification: number(&matches, "stratification", 1),
+        max_depth: number(&matches, "max-depth", 0),
+        max_seconds: matches.value_of("time-limit").map(|s| match s.parse() {
+            Ok(seconds) if seconds > 0.0 => seconds,
+            _ => {
+                eprintln!("--time-limit {}: should be a number above 0", s);
+                process::exit(EXIT_USAGE)
+            }
+        }),
+        debug_shapes: matches.is_present("debug-shapes").then(|| {
+            match matches.value_of("debug-shapes") {
+                Some("only") => DebugShapes::Only,
+                _ => DebugShapes::Over,
+            }
+        }),
+        out_dir: matches.value_of("out-dir").map(|s| s.to_string()),
+        overwrite: match (
+            matches.is_present("force"),
+            matches.is_present("no-clobber"),
+        ) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        },
+    };
+    if let Some(dir) = &overrides.out_dir {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("--out-dir {}: {}", dir, e);
+            process::exit(EXIT_USAGE)
+        }
+    }
+
+    let names = |name| -> Vec<String> {
+        matches
+            .values_of(name)
+            .map(|v| v.map(|s| s.to_string()).collect())
+            .unwrap_or_default()
+    };
+    let selection = Selection {
+        only: matches.is_present("only").then(|| names("only")),
+        skip: names("skip"),
+    };
+
+    let samples = match matches.subcommand() {
+        Some(("preview", m)) => {
+            let samples = m.value_of("samples").unwrap();
+            match samples.parse() {
+                Ok(samples) if samples > 0 => Some(samples),
+                _ => {
+                    eprintln!("--samples {}: should be a number above 0", samples);
+                    process::exit(EXIT_USAGE)
+                }
+            }
+        }
+        _ => None,
+    };
+    if let Some(("serve", m)) = matches.subcommand() {
+        let address = m.value_of("address").unwrap();
+        let code = serve(address, config_file_name, selection, overrides, seed);
+        process::exit(code)
+    }
+    let checking = matches!(matches.subcommand(), Some(("validate" | "info", _)));
+    if matches.is_present("watch") && !checking {
+        let samples = samples.unwrap_or(PREVIEW_SAMPLES);
+        let code = watch(
+            config_file_name,
+            &selection,
+            &overrides,
+            samples,
+            seed,
+            jobs,
+            window,
+        );
+        process::exit(code)
+    }
+
+    let values = match config::read(Path::new(config_file_name)) {
+        Ok(values) => values,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_USAGE)
+        }
+    };
+    let values = values.into_iter().enumerate().collect();
+    let (configs, picked, code) = load(config_file_name, values, &selection, &overrides);
+    for name in selection.only.iter().flatten() {
+        if !picked.contains(&name.as_str()) {
+            eprintln!("--only {}: no config with this name or index", name);
+        }
+    }
+
+    match matches.subcommand() {
+        Some(("validate", _)) => {
+            if code == 0 {
+                say(&format!(
+                    "{}: {} images, all valid",
+                    config_file_name,
+                    configs.len()
+                ));
+            }
+            process::exit(code)
+        }
+        Some(("info", _)) => {
+            for item in &configs {
+                info(item);
+            }
+            process::exit(code)
+        }
+        _ => {}
+    }
+    let configs = match samples {
+        Some(samples) => configs
+            .into_iter()
+            .map(|item| preview(item, samples))
+            .collect(),
+        None => configs,
+    };
+    let code = render_all(configs, seed, jobs, window, code);
+    process::exit(code)
+}
+
+#[cfg(feature = "editor")]
+fn edit(path: &Path) {
+    match editor::edit(path) {
+        Ok(()) => process::exit(0),
+        Err(e) => {
+            report(&path.display().to_string(), &e);
+            process::exit(EXIT_USAGE)
+        }
+    }
+}
+
+#[cfg(not(feature = "editor"))]
+fn edit(_path: &Path) {
+    eprintln!("edit: built without the editor, by cargo build --features editor");
+    process::exit(EXIT_USAGE)
+}
+
+// every scene of the directory against its reference, printed, by golden::run
+fn compare(dir: &Path, out: &Path, bless: bool) -> i32 {
+    let outcomes = match golden::run(dir, out, &Tolerance::default(), bless) {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
+    };
+    let mut code = 0;
+    for outcome in &outcomes {
+        let name = &outcome.name;
+        match &outcome.verdict {
+            Ok(Verdict::Saved) => say(&format!("{}: saved as the reference", name)),
+            Ok(Verdict::Missing) => {
+                eprintln!("{}: no reference, saved by --bless", name);
+                code = code.max(EXIT_DIFFERENT);
+            }
+            Ok(Verdict::Same(d)) => say(&format!("{}: same, mean {:.4}", name, d.mean)),
+            Ok(Verdict::Different(d)) => {
+                eprintln!(
+                    "{}: different, mean {:.4}, {:.2}% of the pixels differ",
+                    name,
+                    d.mean,
+                    d.differing * 100.0
+                );
+                code = code.max(EXIT_DIFFERENT);
+            }
+            Err(e) => {
+                report(name, e);
+                code = code.max(EXIT_RENDER);
+            }
+        }
+    }
+    code
+}
+
+// every scene of the directory rendered with the profile on, and timed, printed
+fn bench(dir: &Path) -> i32 {
+    let paths = match golden::scenes(dir) {
+        Ok(paths) => paths,
         Err(e) => {
-            println!("{}: {}", config_file_name, e.to_string());
-            process::exit(1)
+            eprintln!("{}", e);
+            return EXIT_USAGE;
         }
     };
+    let threads = rayon::current_num_threads();
+    let mut code = 0;
+    let mut total = (0, Duration::ZERO);
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        match bench_scene(&path) {
+            Ok((item, timing, profile)) => {
+                let (render, output) = timing;
+                let seconds = render.as_secs_f64();
+                // of the time of all the threads of the render
+                let share =
+                    (profile.intersection.as_secs_f64() / (seconds * threads as f64)).min(1.0);
+                println!(
+                    "{}: {}x{}, {} rays in {:.3}s, {:.0} rays/s, {:.0}% intersection, {:.0}% shading, output {:.3}s",
+                    name,
+                    item.width,
+                    item.height,
+                    profile.rays,
+                    seconds,
+                    profile.rays as f64 / seconds,
+                    share * 100.0,
+                    (1.0 - share) * 100.0,
+                    output.as_secs_f64(),
+                );
+                for (kind, n) in &profile.tests {
+                    println!("    {}: {} tests", kind, n);
+                }
+                total = (total.0 + profile.rays, total.1 + render);
+            }
+            Err(e) => {
+                report(&name, &e);
+                code = EXIT_RENDER;
+            }
+        }
+    }
+    println!(
+        "{} rays in {:.3}s on {} threads, {:.0} rays/s",
+        total.0,
+        total.1.as_secs_f64(),
+        threads,
+        total.0 as f64 / total.1.as_secs_f64()
+    );
+    code
+}
 
-    let configs: Vec<Config> = serde_json::from_reader(file).unwrap();
+// the config of the scene, the time of its render and of its output into a png, and the
+// profile of the render
+fn bench_scene(path: &Path) -> Result<(Config, (Duration, Duration), Profile), Error> {
+    let mut item = golden::load(path)?;
+    let mut scene = item.scene(None)?;
+    profile::enable(true);
+    profile::take();
+    let begin = Instant::now();
+    let film = Renderer::new(item.settings())
+        .photons(item.photons, item.photon_radius)
+        .render(&mut scene);
+    let render = begin.elapsed();
+    profile::enable(false);
+    let profile = profile::take();
+    let begin = Instant::now();
+    let image = film.to_image(item.exposure, item.tone_map, item.gamma, item.dither);
+    let mut png = Vec::new();
+    DynamicImage::ImageRgb8(image)
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .map_err(|e| Error::Image(path.display().to_string(), e))?;
+    Ok((item, (render, begin.elapsed()), profile))
+}
+
+// the configs of the values, each with its index in the file, picked and overridden.
+// with the names they were picked by, and the exit code for the invalid ones, which are reported
+fn load<'a>(
+    file: &str,
+    values: Vec<(usize, Value)>,
+    selection: &'a Selection,
+    overrides: &Overrides,
+) -> (Vec<Config>, Vec<&'a str>, i32) {
+    let dir = Path::new(file).parent().unwrap_or_else(|| Path::new(""));
+    let mut code = 0;
+    let mut configs: Vec<Config> = Vec::new();
+    let mut picked: Vec<&str> = Vec::new();
+    for (i, value) in values {
+        match config::include(value, dir).and_then(config::expand) {
+            Ok(list) => {
+                for mut item in list {
+                    match selection.pick(i, &item) {
+                        Some(by) => picked.push(by),
+                        None => continue,
+                    }
+                    // asked for by name
+                    item.enable |= selection.only.is_some();
+                    let name = item.name.clone().unwrap_or_else(|| i.to_string());
+                    item.name_outs(&name);
+                    configs.push(overrides.apply(item));
+                }
+            }
+            Err(e) => {
+                report(&format!("{}: /{}", file, i), &e);
+                code = EXIT_CONFIG;
+            }
+        }
+    }
+    (configs, picked, code)
+}
+
+// renders the configs as previews, then again each time the file is saved, only the ones
+// that changed. returns when the file can't be watched
+fn watch(
+    file: &str,
+    selection: &Selection,
+    overrides: &Overrides,
+    samples: u32,
+    seed: Option<u64>,
+    jobs: Option<usize>,
+    window: bool,
+) -> i32 {
+    let path = Path::new(file);
+    // the editors write to a new file and rename it, so the directory is watched
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("{}: {}", file, e);
+            return EXIT_USAGE;
+        }
+    };
+    if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+        eprintln!("{}: {}", dir.display(), e);
+        return EXIT_USAGE;
+    }
 
-    for item in configs {
-        if !item.enable {
-            continue;
+    let mut previous: Vec<Value> = Vec::new();
+    loop {
+        match config::read(path) {
+            Ok(values) => {
+                let changed: Vec<(usize, Value)> = values
+                    .iter()
+                    .cloned()
+                    .enumerate()
+                    .filter(|(i, value)| previous.get(*i) != Some(value))
+                    .collect();
+                previous = values;
+                if changed.is_empty() {
+                    say(&format!("{}: no config changed", file));
+                } else {
+                    let (configs, _, code) = load(file, changed, selection, overrides);
+                    let configs = configs
+                        .into_iter()
+                        .map(|item| preview(item, samples))
+                        .collect();
+                    render_all(configs, seed, jobs, window, code);
+                }
+            }
+            // kept until the file is fixed
+            Err(e) => eprintln!("{}", e),
         }
-        if item.scenes.len() == 0 {
-            continue;
+        say(&format!("watching {} for changes", file));
+        if !saved(&rx, path) {
+            return 0;
         }
-        println!("try to render image: {}", item.out);
+    }
+}
 
-        let mut entities: Vec<Entity> = Vec::new();
-        for entity_json in item.scenes {
-            entities.push(entity_json.get_entity());
+// waits for the file to be written, and the writes right after it.
+// false when the watcher is gone
+fn saved(rx: &mpsc::Receiver<notify::Result<Event>>, path: &Path) -> bool {
+    let is_file = |event: &notify::Result<Event>| match event {
+        Ok(event) => {
+            matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name() == path.file_name())
+        }
+        Err(_) => false,
+    };
+    loop {
+        match rx.recv() {
+            Ok(event) if is_file(&event) => break,
+            Ok(_) => {}
+            Err(_) => return false,
         }
-        let scene = Scene { entities };
-        let img = r(
+    }
+    while rx.recv_timeout(Duration::from_millis(200)).is_ok() {}
+    true
+}
+
+// the page of serve, polling the image and the status
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>imprint_of_light</title>
+<style>
+body { font-family: sans-serif; display: flex; gap: 16px; }
+img { max-width: 60vw; max-height: 90vh; image-rendering: pixelated; background: #000; }
+textarea { width: 36vw; height: 70vh; font-family: monospace; }
+pre { white-space: pre-wrap; }
+</style>
+</head>
+<body>
+<div><img id="image" alt="nothing rendered yet"><pre id="status"></pre></div>
+<div>
+<textarea id="config"></textarea><br>
+<button onclick="post()">render</button>
+<pre id="posted"></pre>
+</div>
+<script>
+async function poll() {
+  const status = await (await fetch("/status")).json();
+  document.getElementById("status").textContent =
+    (status.done ? "done: " : "rendering: ") + status.out + "\n" + status.errors.join("\n");
+  document.getElementById("image").src = "/image.png?" + Date.now();
+}
+async function post() {
+  const response = await fetch("/config", {method: "POST", body: document.getElementById("config").value});
+  document.getElementById("posted").textContent = await response.text();
+}
+fetch("/config").then(r => r.text()).then(t => document.getElementById("config").value = t);
+poll();
+setInterval(poll, 1000);
+</script>
+</body>
+</html>
+"#;
+
+// the render of serve, shared with the thread rendering
+struct Served {
+    values: Vec<Value>,
+    live: Arc<Live>,
+    // the image rendered now, with the exposure, tone map and gamma of its config
+    out: String,
+    look: (f64, ToneMap, Option<f64>, Dither),
+    done: bool,
+    errors: Vec<String>,
+}
+
+// renders the configs of the file, and the ones posted to /config in their place, showing the
+// image so far on the page at /. returns when the address can't be served
+fn serve(
+    address: &str,
+    file: &str,
+    selection: Selection,
+    overrides: Overrides,
+    seed: Option<u64>,
+) -> i32 {
+    let values = match config::read(Path::new(file)) {
+        Ok(values) => values,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
+    };
+    let server = match tiny_http::Server::http(address) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("--address {}: {}", address, e);
+            return EXIT_USAGE;
+        }
+    };
+    say(&format!("serving {} on http://{}", file, address));
+    let served = Arc::new(Mutex::new(Served {
+        values: Vec::new(),
+        live: Arc::new(Live::new()),
+        out: String::new(),
+        look: (1.0, ToneMap::default(), None, Dither::None),
+        done: true,
+        errors: Vec::new(),
+    }));
+    start(&served, values, file, &selection, &overrides, seed);
+
+    for mut request in server.incoming_requests() {
+        let url = request
+            .url()
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let (status, content_type, body) = match (request.method(), url.as_str()) {
+            (Method::Get, "/") => (200, "text/html", PAGE.as_bytes().to_vec()),
+            (Method::Get, "/image.png") => match image_png(&served) {
+                Some(png) => (200, "image/png", png),
+                None => (404, "text/plain", b"nothing rendered yet".to_vec()),
+            },
+            (Method::Get, "/status") => {
+                let served = served.lock().unwrap();
+                let status =
+                    json!({"out": served.out, "done": served.done, "errors": served.errors});
+                (200, "application/json", status.to_string().into_bytes())
+            }
+            (Method::Get, "/config") => {
+                let values = Value::Array(served.lock().unwrap().values.clone());
+                let text = serde_json::to_string_pretty(&values).unwrap_or_default();
+                (200, "application/json", text.into_bytes())
+            }
+            (Method::Post, "/config") => {
+                let mut text = String::new();
+                let read = request.as_reader().read_to_string(&mut text);
+                match read
+                    .map_err(|e| e.to_string())
+                    .and_then(|_| posted(&text, file))
+                {
+                    Ok(values) => {
+                        let n = values.len();
+                        start(&served, values, file, &selection, &overrides, seed);
+                        (
+                            200,
+                            "text/plain",
+                            format!("rendering {} configs", n).into_bytes(),
+                        )
+                    }
+                    Err(e) => (400, "text/plain", e.into_bytes()),
+                }
+            }
+            _ => (404, "text/plain", b"not found".to_vec()),
+        };
+        let header = tiny_http::Header::from_bytes("Content-Type", content_type).unwrap();
+        let response = Response::from_data(body)
+            .with_status_code(status)
+            .with_header(header);
+        // the page is polled again
+        request.respond(response).ok();
+    }
+    0
+}
+
+// the configs of the body of the post, a list of them or one, with the errors of all of them
+fn posted(text: &str, file: &str) -> Result<Vec<Value>, String> {
+    let values = match serde_json::from_str(text).map_err(|e| e.to_string())? {
+        Value::Array(list) => list,
+        value => vec![value],
+    };
+    let dir = Path::new(file).parent().unwrap_or_else(|| Path::new(""));
+    let mut errors = Vec::new();
+    for (i, value) in values.iter().enumerate() {
+        if let Err(e) = config::include(value.clone(), dir).and_then(config::expand) {
+            errors.extend(describe(&format!("/{}", i), &e));
+        }
+    }
+    if errors.is_empty() {
+        Ok(values)
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+// the film so far of the image rendered now
+fn image_png(served: &Mutex<Served>) -> Option<Vec<u8>> {
+    let (live, (exposure, tone_map, gamma, dither)) = {
+        let served = served.lock().unwrap();
+        (served.live.clone(), served.look)
+    };
+    let image = live
+        .film
+        .lock()
+        .unwrap()
+        .to_image(exposure, tone_map, gamma, dither);
+    if image.width() == 0 || image.height() == 0 {
+        return None;
+    }
+    let mut png = Vec::new();
+    DynamicImage::ImageRgb8(image)
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .ok()?;
+    Some(png)
+}
+
+// stops the render going on, and renders the values in its place on another thread
+fn start(
+    served: &Arc<Mutex<Served>>,
+    values: Vec<Value>,
+    file: &str,
+    selection: &Selection,
+    overrides: &Overrides,
+    seed: Option<u64>,
+) {
+    let indexed = values.iter().cloned().enumerate().collect();
+    let (configs, _, _) = load(file, indexed, selection, overrides);
+    let live = Arc::new(Live::new());
+    {
+        let mut served = served.lock().unwrap();
+        served.live.stop();
+        served.live = live.clone();
+        served.values = values;
+        served.done = false;
+        served.errors.clear();
+    }
+    let served = served.clone();
+    thread::spawn(move || {
+        // the render is still the one shown
+        let current = |served: &Served| Arc::ptr_eq(&served.live, &live);
+        let mut animations: Vec<Animation> = Vec::new();
+        for item in configs.into_iter().filter(renderable) {
+            if live.is_stopped() {
+                return;
+            }
+            let (out, fps) = (item.out.clone(), item.fps);
+            {
+                let mut served = served.lock().unwrap();
+                if !current(&served) {
+                    return;
+                }
+                served.out = out.clone();
+                served.look = (item.exposure, item.tone_map, item.gamma, item.dither);
+            }
+            say(&format!("try to render image: {}", out));
+            let result = render_config(item, seed, (), &live).and_then(|frame| match frame {
+                Some(frame) => push_frame(&mut animations, &out, fps, frame),
+                None => Ok(()),
+            });
+            if let Err(e) = result {
+                let mut served = served.lock().unwrap();
+                // stopped for the next
+                if !current(&served) {
+                    return;
+                }
+                report(&out, &e);
+                served.errors.extend(describe(&out, &e));
+            }
+        }
+        for animation in animations {
+            let path = animation.path.clone();
+            match animation.finish() {
+                Ok(message) => say(&message),
+                Err(e) => {
+                    report(&path, &e);
+                    served.lock().unwrap().errors.extend(describe(&path, &e));
+                }
+            }
+        }
+        let mut served = served.lock().unwrap();
+        if current(&served) {
+            served.done = true;
+        }
+    });
+}
+
+// the values given on the command line, for all the configs
+struct Overrides {
+    width: Option<u32>,
+    height: Option<u32>,
+    stratification: Option<u32>,
+    max_depth: Option<u32>,
+    max_seconds: Option<f64>,
+    debug_shapes: Option<DebugShapes>,
+    out_dir: Option<String>,
+    overwrite: Option<bool>,
+}
+
+impl Overrides {
+    fn apply(&self, mut item: Config) -> Config {
+        item.width = self.width.unwrap_or(item.width);
+        item.height = self.height.unwrap_or(item.height);
+        item.stratification = self.stratification.unwrap_or(item.stratification);
+        item.max_depth = self.max_depth.unwrap_or(item.max_depth);
+        item.max_seconds = self.max_seconds.or(item.max_seconds);
+        item.debug_shapes = self.debug_shapes.or(item.debug_shapes);
+        item.overwrite = self.overwrite.unwrap_or(item.overwrite);
+        if let Some(dir) = &self.out_dir {
+            item.out = in_dir(dir, &item.out);
+            for out in item.other_outs_mut() {
+                *out = in_dir(dir, out);
+            }
+        }
+        item
+    }
+}
+
+// the file of out in the directory
+fn in_dir(dir: &str, out: &str) -> String {
+    let name = Path::new(out).file_name().unwrap_or_default();
+    Path::new(dir).join(name).to_string_lossy().to_string()
+}
+
+// the configs given on the command line, each by its name or its index in the file
+struct Selection {
+    only: Option<Vec<String>>,
+    skip: Vec<String>,
+}
+
+impl Selection {
+    // the name the config is picked by, none when it is left out
+    fn pick(&self, index: usize, item: &Config) -> Option<&str> {
+        let index = index.to_string();
+        let is = |name: &String| item.name.as_ref() == Some(name) || *name == index;
+        if self.skip.iter().any(is) {
+            return None;
+        }
+        match &self.only {
+            Some(only) => only.iter().find(|name| is(name)).map(|name| name.as_str()),
+            None => Some(""),
+        }
+    }
+}
+
+// the value of the argument, at least min
+fn number(matches: &ArgMatches, name: &str, min: u32) -> Option<u32> {
+    matches.value_of(name).map(|s| match s.parse() {
+        Ok(n) if n >= min => n,
+        Ok(_) => {
+            eprintln!("--{} {}: should be at least {}", name, s, min);
+            process::exit(EXIT_USAGE)
+        }
+        Err(e) => {
+            eprintln!("--{} {}: {}", name, s, e);
+            process::exit(EXIT_USAGE)
+        }
+    })
+}
+
+// renders the enabled configs, returns the exit code
+fn render_all(
+    configs: Vec<Config>,
+    seed: Option<u64>,
+    jobs: Option<usize>,
+    window: bool,
+    mut code: i32,
+) -> i32 {
+    let mut configs: Vec<Config> = configs
+        .into_iter()
+        .filter(renderable)
+        .filter(writable)
+        .collect();
+    for item in &mut configs {
+        // the ones of the layers by Film::save, once they are known
+        let outs: Vec<String> = item
+            .other_outs_mut()
+            .filter(|out| !out.contains("{layer}"))
+            .map(|out| out.to_string())
+            .collect();
+        for out in [item.out.clone()].iter().chain(&outs) {
+            if let Err(e) = film::create_parent(out) {
+                report(out, &e);
+                code = EXIT_RENDER;
+            }
+        }
+    }
+    let outs: Vec<(String, f64)> = configs.iter().map(|c| (c.out.clone(), c.fps)).collect();
+
+    let mut animations: Vec<Animation> = Vec::new();
+    match jobs {
+        None => {
+            // one after another, each on all the threads, the frames streamed as they are done
+            for (item, (out, fps)) in configs.into_iter().zip(outs) {
+                say(&format!("try to render image: {}", item.out));
+                let frame = if window {
+                    render_window(item, seed)
+                } else {
+                    let pb = progress(&item);
+                    render_config(item, seed, pb, &Live::new())
+                };
+                say("");
+                let result = frame.and_then(|frame| match frame {
+                    Some(frame) => push_frame(&mut animations, &out, fps, frame),
+                    None => Ok(()),
+                });
+                if let Err(e) = result {
+                    report(&out, &e);
+                    code = EXIT_RENDER;
+                }
+            }
+        }
+        Some(jobs) => {
+            let frames = render_jobs(configs, seed, jobs);
+            for ((out, fps), frame) in outs.into_iter().zip(frames) {
+                let result = frame.and_then(|frame| match frame {
+                    Some(frame) => push_frame(&mut animations, &out, fps, frame),
+                    None => Ok(()),
+                });
+                if let Err(e) = result {
+                    report(&out, &e);
+                    code = EXIT_RENDER;
+                }
+            }
+        }
+    }
+    for animation in animations {
+        let path = animation.path.clone();
+        match animation.finish() {
+            Ok(message) => say(&message),
+            Err(e) => {
+                report(&path, &e);
+                code = EXIT_RENDER;
+            }
+        }
+    }
+    code
+}
+
+fn renderable(item: &Config) -> bool {
+    item.enable && !(item.scenes.is_empty() && item.lights.is_empty() && item.background.is_none())
+}
+
+// whether the image of the config is rendered, it isn't overwritten without overwrite
+fn writable(item: &Config) -> bool {
+    if item.overwrite || !Path::new(&item.out).exists() {
+        return true;
+    }
+    say(&format!("{} is there already, skipped", item.out));
+    false
+}
+
+// one line for the config
+fn info(item: &Config) {
+    let name = |v: Value| v.as_str().map(|s| s.to_string()).unwrap_or_default();
+    let samples = match item.adaptive {
+        Some(adaptive) => format!("{} to {}", adaptive.min_samples, adaptive.max_samples),
+        None => item.stratification.to_string(),
+    };
+    let limit = item
+        .max_seconds
+        .map(|seconds| format!(" in {} seconds at most", seconds))
+        .unwrap_or_default();
+    println!(
+        "{}{}: {}x{}, {} samples per pixel{}, depth {}, {} entities, {} lights, {} integrator, {} engine{}",
+        item.out,
+        item.name.as_ref().map(|n| format!(" ({})", n)).unwrap_or_default(),
+        item.width,
+        item.height,
+        samples,
+        limit,
+        item.max_depth,
+        item.scenes.len(),
+        item.lights.len(),
+        name(json!(item.integrator)),
+        name(json!(item.engine)),
+        if renderable(item) { "" } else { ", disabled" },
+    );
+}
+
+// few samples, nothing adaptive or progressive, saved next to the out
+fn preview(mut item: Config, samples: u32) -> Config {
+    item.stratification = samples;
+    item.adaptive = None;
+    item.progressive = None;
+    item.photons = item.photons.min(samples * 1000);
+    item.out = preview_path(&item.out);
+    for out in item.other_outs_mut() {
+        *out = preview_path(out);
+    }
+    item
+}
+
+// _preview before the extension
+fn preview_path(out: &str) -> String {
+    match out.rfind('.') {
+        Some(i) if !out[i..].contains('/') => format!("{}_preview{}", &out[..i], &out[i..]),
+        _ => format!("{}_preview", out),
+    }
+}
+
+// on stderr like every error, stdout is left to the json lines of --progress json
+fn report(about: &str, e: &Error) {
+    for line in describe(about, e) {
+        eprintln!("{}", line);
+    }
+}
+
+// the error after what it's about, a line for every error of a config
+fn describe(about: &str, e: &Error) -> Vec<String> {
+    match e {
+        Error::Config(errors) => errors.iter().map(|e| format!("{}{}", about, e)).collect(),
+        Error::Io(..) | Error::Image(..) | Error::Parse(..) | Error::Animation(..) => {
+            vec![e.to_string()]
+        }
+        Error::Invalid(_) | Error::Stopped => vec![format!("{}: {}", about, e)],
+    }
+}
+
+// to the animation of the path, at the fps of its first frame
+fn push_frame(
+    animations: &mut Vec<Animation>,
+    path: &str,
+    fps: f64,
+    frame: RgbImage,
+) -> Result<(), Error> {
+    match animations.iter_mut().find(|a| a.path == path) {
+        Some(animation) => animation.push(frame),
+        None => {
+            let mut animation = Animation::new(path, fps);
+            let result = animation.push(frame);
+            animations.push(animation);
+            result
+        }
+    }
+}
+
+fn render_jobs(
+    configs: Vec<Config>,
+    seed: Option<u64>,
+    jobs: usize,
+) -> Vec<Result<Option<RgbImage>, Error>> {
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(jobs).build() {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("--jobs {}: {}", jobs, e);
+            process::exit(EXIT_USAGE)
+        }
+    };
+    // a bar for every image, the images taken by the threads as they are free
+    let mb = MultiBar::new();
+    let items: Vec<_> = configs
+        .into_iter()
+        .map(|item| {
+            let pb: Box<dyn Progress + Send> = match output() {
+                Output::Bar => {
+                    let mut pb = mb.create_bar(0);
+                    pb.message(&format!("{} ", item.out));
+                    Box::new(pb)
+                }
+                _ => progress(&item),
+            };
+            (item, pb)
+        })
+        .collect();
+    let listen = thread::spawn(move || mb.listen());
+    let frames = pool.install(|| {
+        items
+            .into_par_iter()
+            .map(|(item, pb)| render_config(item, seed, pb, &Live::new()))
+            .collect()
+    });
+    listen.join().unwrap();
+    frames
+}
+
+// renders the config on another thread, showing it in a window on this one
+#[cfg(feature = "window")]
+fn render_window(item: Config, seed: Option<u64>) -> Result<Option<RgbImage>, Error> {
+    let live = Live::new();
+    let out = item.out.clone();
+    let (exposure, tone_map, gamma, bit_depth, dither) = (
+        item.exposure,
+        item.tone_map,
+        item.gamma,
+        item.bit_depth,
+        item.dither,
+    );
+    let size = (item.width, item.height);
+    thread::scope(|s| {
+        let pb = progress(&item);
+        let render = s.spawn(|| render_config(item, seed, pb, &live));
+        let shown = window::show(
+            &out,
+            size,
+            &live,
+            |film| film.to_image(exposure, tone_map, gamma, dither),
+            |film| match film.save(&out, exposure, tone_map, gamma, bit_depth, dither) {
+                Ok(()) => say(&format!("saved {}", out)),
+                Err(e) => report(&out, &e),
+            },
+            || render.is_finished(),
+        );
+        // the render goes on without the window
+        if let Err(e) = shown {
+            report(&out, &e);
+        }
+        render.join().unwrap()
+    })
+}
+
+#[cfg(not(feature = "window"))]
+fn render_window(item: Config, seed: Option<u64>) -> Result<Option<RgbImage>, Error> {
+    let pb = progress(&item);
+    render_config(item, seed, pb, &Live::new())
+}
+
+// the progress of the render of the config, by the output
+fn progress(item: &Config) -> Box<dyn Progress + Send> {
+    match output() {
+        Output::Bar => Box::new(ProgressBar::new(0)),
+        Output::Json => {
+            // of a pixel, or of every pixel in a pass
+            let samples = match (item.passes(), item.adaptive) {
+                (Some(_), _) => Some(item.width as u64 * item.height as u64),
+                (None, Some(_)) => None,
+                (None, None) => Some(item.stratification as u64),
+            };
+            Box::new(JsonLines::new(io::stdout(), &item.out, samples))
+        }
+        Output::Quiet => Box::new(()),
+    }
+}
+
+// the effects of the config on the rendered film: the bloom before the exposure, the finish
+// after the tone map, saved by look
+fn post(item: &Config, seed: Option<u64>, film: &mut Film) {
+    if let Some(bloom) = item.bloom {
+        bloom.apply(film);
+    }
+    if let Some(finish) = item.finish {
+        *film = film.tone_mapped(item.exposure, item.tone_map);
+        finish.apply(film, seed.or(item.seed));
+    }
+}
+
+// every layer of the scene rendered alone and saved by out_layers, the film of out is the sum
+// of them. nothing is saved when it is stopped
+fn render_layers<P: Progress + Send>(
+    scene: &mut Scene,
+    item: &Config,
+    live: &Live,
+    pb: P,
+) -> Result<Film, Error> {
+    let layers = scene.layers();
+    let mut pb = Sequence::new(pb, layers.len() as u64);
+    let mut films = Vec::new();
+    for layer in layers {
+        scene.layer = Some(layer.clone());
+        let film = r(scene, &item.settings(), live, &mut pb);
+        if live.is_stopped() {
+            pb.finish();
+            return Err(Error::Stopped);
+        }
+        films.push((layer, film));
+    }
+    scene.layer = None;
+    let mut sum = Film::new(item.width, item.height);
+    for (layer, film) in &films {
+        if let Some(out) = &item.out_layers {
+            film.save(
+                &config::layer_out(out, layer),
+                item.exposure,
+                item.tone_map,
+                item.gamma,
+                item.bit_depth,
+                item.dither,
+            )?;
+        }
+        sum.add(film);
+    }
+    Ok(sum)
+}
+
+// saves the image, or returns it as a frame of an animation
+// the film is put into live as it is rendered, nothing is saved when it is stopped
+fn render_config<P: Progress + Send>(
+    mut item: Config,
+    seed: Option<u64>,
+    mut pb: P,
+    live: &Live,
+) -> Result<Option<RgbImage>, Error> {
+    let animated = animation::is_animated(&item.out);
+    // the bar is finished on an error too, the multi bar waits for all of them
+    let mut scene = match item.scene(seed) {
+        Ok(scene) => scene,
+        Err(e) => {
+            pb.finish();
+            return Err(e);
+        }
+    };
+    let only = item.debug_shapes == Some(DebugShapes::Only);
+    if scene.needs_photons() && !only {
+        scene.photons = Some(photon_map(
             &scene,
-            (item.width, item.height),
-            item.stratification,
+            item.photons,
             item.max_depth,
-        );
-        img.save(item.out.clone()).unwrap();
+            item.photon_radius,
+        ));
+    }
+    let (exposure, tone_map) = item.look();
+    let save = |film: &Film| {
+        film.save(
+            &item.out,
+            exposure,
+            tone_map,
+            item.gamma,
+            item.bit_depth,
+            item.dither,
+        )
+    };
+    let counting = item.stats.then(profile::start);
+    let mut film = match item.passes() {
+        // nothing is traced
+        _ if only => {
+            pb.start(1);
+            pb.add(1);
+            pb.finish_print("");
+            Film::new(item.width, item.height)
+        }
+        // every layer in a render of its own, not in passes. not of the frames
+        _ if !animated && item.out_layers.is_some() => render_layers(&mut scene, &item, live, pb)?,
+        // the frames of an animation are only taken at the end, and the image of max_seconds
+        Some(progressive) => render_progressive(
+            &scene,
+            &item.settings(),
+            progressive,
+            live,
+            |film| {
+                if animated || item.progressive.is_none() {
+                    return Ok(());
+                }
+                let mut film = film.clone();
+                post(&item, seed, &mut film);
+                save(&film)
+            },
+            pb,
+        )?,
+        None => r(&scene, &item.settings(), live, pb),
+    };
+    if live.is_stopped() {
+        return Err(Error::Stopped);
+    }
+    if let Some(counting) = counting {
+        drop(counting);
+        say(&format!("{}: {}", item.out, profile::take()));
+    }
+    // of the last frame of an animation
+    if let Some(path) = &item.out_stats {
+        film.stats_image()
+            .save(path)
+            .map_err(|e| Error::Image(path.clone(), e))?;
+    }
+    let passes = [&item.out_normal, &item.out_depth, &item.out_id];
+    let guided = item.denoise.is_some_and(|denoise| denoise.is_guided());
+    let aovs =
+        (passes.iter().any(|out| out.is_some()) || guided).then(|| aovs(&scene, &item.settings()));
+    if let Some(aovs) = &aovs {
+        for (out, film) in passes
+            .into_iter()
+            .zip([&aovs.normal, &aovs.depth, &aovs.id])
+        {
+            if let Some(path) = out {
+                // the values themselves
+                film.save(
+                    path,
+                    1.0,
+                    ToneMap::Clamp,
+                    Some(1.0),
+                    item.bit_depth,
+                    Dither::None,
+                )?;
+            }
+        }
+    }
+    if let Some(denoise) = item.denoise {
+        denoise.apply(&mut film, aovs.as_ref());
+    }
+    if let Some(ao) = item.ao {
+        let occlusion = ambient_occlusion(&scene, &item.settings(), ao);
+        if let Some(path) = &item.out_ao {
+            occlusion.save(
+                path,
+                1.0,
+                ToneMap::Clamp,
+                Some(1.0),
+                item.bit_depth,
+                Dither::None,
+            )?;
+        }
+        if ao.multiply {
+            film.multiply(&occlusion);
+        }
+    }
+    post(&item, seed, &mut film);
+    // of the render without the debug shapes, not of the frames
+    if !animated {
+        for level in &item.pyramid {
+            let (width, height) = level.size((film.width, film.height));
+            film.resize(width, height).save(
+                &level.out,
+                exposure,
+                tone_map,
+                item.gamma,
+                item.bit_depth,
+                item.dither,
+            )?;
+        }
+    }
+    if item.debug_shapes.is_some() {
+        let mut image = film.to_image(exposure, tone_map, item.gamma, item.dither);
+        debug::draw(&mut image, &scene, &item.settings(), only);
+        if animated {
+            return Ok(Some(image));
+        }
+        image
+            .save(&item.out)
+            .map_err(|e| Error::Image(item.out.clone(), e))?;
+        return Ok(None);
+    }
+    if animated {
+        return Ok(Some(film.to_image(
+            exposure,
+            tone_map,
+            item.gamma,
+            item.dither,
+        )));
     }
+    save(&film)?;
+    Ok(None)
 }
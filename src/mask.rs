@@ -0,0 +1,271 @@
+use crate::{
+    calculate::{closest_on_segment, distance},
+    error::Error,
+    shapes::{Aabb, Intersection, Sdf, Shape},
+};
+
+const THRESHOLD: f64 = 0.5;
+const EPSILON: f64 = 1e-9;
+
+struct Segment {
+    a: (f64, f64),
+    b: (f64, f64),
+    // points to the dark side
+    normal: (f64, f64),
+}
+
+// the white pixels of an image, placed with its top left corner at (x, y) and a width of w.
+// the boundary is the contour of the pixel values at 0.5, found by marching squares
+pub struct ImageMask {
+    values: Vec<f64>,
+    width: i64,
+    height: i64,
+    x: f64,
+    y: f64,
+    // size of a pixel
+    scale: f64,
+    // segments of every cell, the cell (i, j) lies between the centers of the pixels
+    // (i, j) and (i + 1, j + 1), i from -1 to width - 1, j from -1 to height - 1
+    cells: Vec<Vec<Segment>>,
+}
+
+#[allow(dead_code)]
+impl ImageMask {
+    pub fn open(path: &str, x: f64, y: f64, w: f64) -> Result<Self, Error> {
+        let img = image::open(path)
+            .map_err(|e| Error::Image(path.to_string(), e))?
+            .into_luma8();
+        let (width, height) = img.dimensions();
+        let values = img.pixels().map(|p| p.0[0] as f64 / 255.0).collect();
+        let mut mask = Self {
+            values,
+            width: width as i64,
+            height: height as i64,
+            x,
+            y,
+            scale: w / width as f64,
+            cells: Vec::new(),
+        };
+        mask.cells = (-1..mask.height)
+            .flat_map(|j| (-1..mask.width).map(move |i| (i, j)))
+            .map(|(i, j)| mask.march_cell(i, j))
+            .collect();
+        Ok(mask)
+    }
+
+    // the pixels out of the image are black, so that every contour is closed
+    fn value(&self, i: i64, j: i64) -> f64 {
+        if i < 0 || j < 0 || i >= self.width || j >= self.height {
+            0.0
+        } else {
+            self.values[(j * self.width + i) as usize]
+        }
+    }
+
+    // bilinear interpolation, in pixel coordinates
+    fn sample(&self, (u, v): (f64, f64)) -> f64 {
+        let i = u.floor() as i64;
+        let j = v.floor() as i64;
+        let fu = u - i as f64;
+        let fv = v - j as f64;
+        let top = self.value(i, j) * (1.0 - fu) + self.value(i + 1, j) * fu;
+        let bottom = self.value(i, j + 1) * (1.0 - fu) + self.value(i + 1, j + 1) * fu;
+        top * (1.0 - fv) + bottom * fv
+    }
+
+    fn cell(&self, i: i64, j: i64) -> &[Segment] {
+        if i < -1 || j < -1 || i >= self.width || j >= self.height {
+            return &[];
+        }
+        &self.cells[((j + 1) * (self.width + 1) + i + 1) as usize]
+    }
+
+    fn to_local(&self, (px, py): (f64, f64)) -> (f64, f64) {
+        (
+            (px - self.x) / self.scale - 0.5,
+            (py - self.y) / self.scale - 0.5,
+        )
+    }
+
+    fn to_world(&self, (u, v): (f64, f64)) -> (f64, f64) {
+        (
+            self.x + (u + 0.5) * self.scale,
+            self.y + (v + 0.5) * self.scale,
+        )
+    }
+
+    fn march_cell(&self, i: i64, j: i64) -> Vec<Segment> {
+        // corners clockwise on the image from the top left, edges top, right, bottom, left
+        let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+        let values = corners.map(|(ci, cj)| self.value(ci, cj));
+        let crossings: Vec<Option<(f64, f64)>> = (0..4)
+            .map(|k| {
+                let (v0, v1) = (values[k], values[(k + 1) % 4]);
+                if (v0 > THRESHOLD) == (v1 > THRESHOLD) {
+                    return None;
+                }
+                let s = (THRESHOLD - v0) / (v1 - v0);
+                let (a, b) = (corners[k], corners[(k + 1) % 4]);
+                Some((
+                    a.0 as f64 + (b.0 - a.0) as f64 * s,
+                    a.1 as f64 + (b.1 - a.1) as f64 * s,
+                ))
+            })
+            .collect();
+        let pairs: Vec<(usize, usize)> = match crossings.iter().filter(|c| c.is_some()).count() {
+            2 => {
+                let found: Vec<usize> = (0..4).filter(|&k| crossings[k].is_some()).collect();
+                vec![(found[0], found[1])]
+            }
+            // a saddle, the corners of the other color than the center are cut off
+            4 => {
+                let center = values.iter().sum::<f64>() / 4.0 > THRESHOLD;
+                if (values[1] > THRESHOLD) != center {
+                    vec![(0, 1), (2, 3)]
+                } else {
+                    vec![(3, 0), (1, 2)]
+                }
+            }
+            _ => Vec::new(),
+        };
+        pairs
+            .into_iter()
+            .map(|(k0, k1)| {
+                let a = crossings[k0].unwrap();
+                let b = crossings[k1].unwrap();
+                let (mut nx, mut ny) = (a.1 - b.1, b.0 - a.0);
+                let len = (nx * nx + ny * ny).sqrt().max(EPSILON);
+                nx /= len;
+                ny /= len;
+                // towards the dark side
+                let m = ((a.0 + b.0) * 0.5, (a.1 + b.1) * 0.5);
+                if self.sample((m.0 + nx * 0.01, m.1 + ny * 0.01))
+                    > self.sample((m.0 - nx * 0.01, m.1 - ny * 0.01))
+                {
+                    nx = -nx;
+                    ny = -ny;
+                }
+                Segment {
+                    a: self.to_world(a),
+                    b: self.to_world(b),
+                    normal: (nx, ny),
+                }
+            })
+            .collect()
+    }
+
+    fn hit(segment: &Segment, (px, py): (f64, f64), (dx, dy): (f64, f64)) -> Option<Intersection> {
+        let (a, b) = (segment.a, segment.b);
+        let ex = b.0 - a.0;
+        let ey = b.1 - a.1;
+        let denom = dx * ey - dy * ex;
+        if denom.abs() < EPSILON * EPSILON {
+            return None;
+        }
+        let t = ((a.0 - px) * ey - (a.1 - py) * ex) / denom;
+        let s = ((a.0 - px) * dy - (a.1 - py) * dx) / denom;
+        if t > 1e-6 && (0.0..1.0).contains(&s) {
+            Some(Intersection {
+                point: (px + dx * t, py + dy * t),
+                normal: segment.normal,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl Sdf for ImageMask {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        let d = self
+            .cells
+            .iter()
+            .flatten()
+            .map(|segment| distance(p, closest_on_segment(p, segment.a, segment.b)))
+            .fold(f64::INFINITY, f64::min);
+        if self.is_inside(p) {
+            -d
+        } else {
+            d
+        }
+    }
+}
+
+impl Shape for ImageMask {
+    fn aabb(&self) -> Option<Aabb> {
+        let b = Aabb {
+            min: (self.x, self.y),
+            max: (
+                self.x + self.width as f64 * self.scale,
+                self.y + self.height as f64 * self.scale,
+            ),
+        };
+        Some(b.expand(self.scale))
+    }
+
+    // walk through the cells along the ray
+    fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
+        let mut result: Vec<Intersection> = Vec::new();
+        let (u, v) = self.to_local(p);
+        let (du, dv) = (d.0 / self.scale, d.1 / self.scale);
+
+        // the part of the ray inside the grid
+        let mut t0: f64 = 0.0;
+        let mut t1 = f64::INFINITY;
+        for (o, dd, max) in [(u, du, self.width as f64), (v, dv, self.height as f64)] {
+            if dd.abs() < EPSILON {
+                if o < -1.0 || o > max {
+                    return result;
+                }
+            } else {
+                let a = (-1.0 - o) / dd;
+                let b = (max - o) / dd;
+                t0 = t0.max(a.min(b));
+                t1 = t1.min(a.max(b));
+            }
+        }
+        if t0 > t1 {
+            return result;
+        }
+
+        let start = (u + du * t0, v + dv * t0);
+        let mut i = (start.0.floor() as i64).clamp(-1, self.width - 1);
+        let mut j = (start.1.floor() as i64).clamp(-1, self.height - 1);
+        let step_i = if du > 0.0 { 1 } else { -1 };
+        let step_j = if dv > 0.0 { 1 } else { -1 };
+        let next = |c: i64, step: i64, o: f64, dd: f64| {
+            if dd.abs() < EPSILON {
+                f64::INFINITY
+            } else {
+                ((c + step.max(0)) as f64 - o) / dd
+            }
+        };
+        let mut ti = next(i, step_i, u, du);
+        let mut tj = next(j, step_j, v, dv);
+        let delta_i = (1.0 / du).abs();
+        let delta_j = (1.0 / dv).abs();
+        while i >= -1 && j >= -1 && i < self.width && j < self.height {
+            for segment in self.cell(i, j) {
+                if let Some(item) = Self::hit(segment, p, d) {
+                    result.push(item);
+                }
+            }
+            if ti < tj {
+                i += step_i;
+                ti += delta_i;
+            } else {
+                j += step_j;
+                tj += delta_j;
+            }
+        }
+        result
+    }
+
+    fn is_inside(&self, p: (f64, f64)) -> bool {
+        self.sample(self.to_local(p)) > THRESHOLD
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
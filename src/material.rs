@@ -0,0 +1,441 @@
+use image::RgbImage;
+use std::f64::consts::FRAC_PI_2;
+
+use crate::{calculate::distance, element::Color, error::Error};
+
+// how the surface of an entity, and its inside, treat light
+pub enum Material {
+    // 放射
+    Emitter(Emission),
+    Mirror {
+        reflectivity: f64,
+        // 粗糙度, 0 for a mirror, up to 1 for scattering over the whole half plane
+        roughness: f64,
+    },
+    // refracts, and reflects by the fresnel term
+    Dielectric {
+        // 折射率
+        eta: f64,
+        // 色散, the cauchy coefficient B in μm², eta is the index at the green wavelength
+        dispersion: f64,
+        roughness: f64,
+        // 吸收
+        absorption: Color,
+        // the change of eta over space, the rays inside are bent by it
+        grin: Option<Grin>,
+    },
+    // 薄膜, a film as thin as the light is long, like a soap bubble. reflects the wavelengths
+    // interfering in step at the angle, the rest goes straight through
+    ThinFilm {
+        eta: f64,
+        // in μm
+        thickness: f64,
+    },
+    // 光栅, reflects every wavelength into one of its diffraction orders, like a cd
+    Grating {
+        // of the grooves, in μm
+        period: f64,
+        reflectivity: f64,
+    },
+    // 漫反射, lambertian scattering with the albedo
+    Diffuse(Color),
+    // absorbs the light coming from its inside, without any effect on the surface
+    Absorber(Color),
+    // materials on the same surface, their contributions added up
+    Mix(Vec<Material>),
+}
+
+impl Material {
+    // the absorption coefficients of the inside
+    pub fn absorption(&self) -> Color {
+        match self {
+            Material::Dielectric { absorption, .. } | Material::Absorber(absorption) => *absorption,
+            Material::Mix(list) => list.iter().map(|m| m.absorption()).sum(),
+            _ => Color::black(),
+        }
+    }
+
+    // the emitted color at p, before the profile
+    pub fn emission(&self, p: (f64, f64)) -> Color {
+        match self {
+            Material::Emitter(emission) => emission.at(p),
+            Material::Mix(list) => list.iter().map(|m| m.emission(p)).sum(),
+            _ => Color::black(),
+        }
+    }
+
+    // a color of the material for the layout mode, nothing traced: the emission, the albedo,
+    // grey for the mirrors and blue for the dielectrics
+    pub fn flat(&self, p: (f64, f64)) -> Color {
+        match self {
+            // the black ones, only blocking the light, are seen too
+            Material::Emitter(emission) if emission.is_black() => Color::grey(0.15),
+            Material::Mix(list) if list.is_empty() => Color::grey(0.15),
+            Material::Emitter(emission) => emission.at(p),
+            Material::Mirror { reflectivity, .. } => Color::grey(0.3 + 0.5 * reflectivity),
+            Material::Dielectric { .. } => Color {
+                r: 0.4,
+                g: 0.6,
+                b: 0.9,
+            },
+            Material::ThinFilm { .. } => Color {
+                r: 0.7,
+                g: 0.5,
+                b: 0.8,
+            },
+            Material::Grating { reflectivity, .. } => Color::grey(0.3 + 0.5 * reflectivity),
+            Material::Diffuse(albedo) => *albedo,
+            Material::Absorber(_) => Color::grey(0.1),
+            Material::Mix(list) => {
+                let sum: Color = list.iter().map(|m| m.flat(p)).sum();
+                sum * (1.0 / list.len().max(1) as f64)
+            }
+        }
+    }
+
+    // the eta and the gradient of the dielectric with one, or of the first one mixed
+    pub fn grin(&self) -> Option<(f64, &Grin)> {
+        match self {
+            Material::Dielectric {
+                eta,
+                grin: Some(grin),
+                ..
+            } => Some((*eta, grin)),
+            Material::Mix(list) => list.iter().find_map(|m| m.grin()),
+            _ => None,
+        }
+    }
+
+    // the emission of it, and of the mixed ones, times k
+    pub fn with_intensity(self, k: f64) -> Self {
+        match self {
+            Material::Emitter(emission) => Material::Emitter(emission.scaled(k)),
+            Material::Mix(list) => {
+                Material::Mix(list.into_iter().map(|m| m.with_intensity(k)).collect())
+            }
+            material => material,
+        }
+    }
+
+    pub fn emissive(&self) -> bool {
+        match self {
+            Material::Emitter(emission) => !emission.is_black(),
+            Material::Mix(list) => list.iter().any(|m| m.emissive()),
+            _ => false,
+        }
+    }
+
+    pub fn dispersive(&self) -> bool {
+        match self {
+            Material::Dielectric {
+                eta, dispersion, ..
+            } => *eta > 0.0 && *dispersion != 0.0,
+            // the orders of the wavelengths apart
+            Material::Grating { .. } => true,
+            Material::Mix(list) => list.iter().any(|m| m.dispersive()),
+            _ => false,
+        }
+    }
+}
+
+// the emitted color, as a function of the position on the surface
+pub enum Emission {
+    Uniform(Color),
+    // from the color a at (x1, y1) to b at (x2, y2), constant beyond both ends
+    Linear {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        a: Color,
+        b: Color,
+    },
+    // from the color a at the center to b at the radius r and beyond
+    Radial {
+        cx: f64,
+        cy: f64,
+        r: f64,
+        a: Color,
+        b: Color,
+    },
+    // an image placed with its top left corner at (x, y) and a width of w, black out of it
+    Image {
+        img: RgbImage,
+        x: f64,
+        y: f64,
+        w: f64,
+        intensity: f64,
+    },
+}
+
+#[allow(dead_code)]
+impl Emission {
+    pub fn image(path: &str, x: f64, y: f64, w: f64, intensity: f64) -> Result<Self, Error> {
+        let img = image::open(path)
+            .map_err(|e| Error::Image(path.to_string(), e))?
+            .into_rgb8();
+        Ok(Emission::Image {
+            img,
+            x,
+            y,
+            w,
+            intensity,
+        })
+    }
+
+    // every color of it times k, above 1 for the lights far brighter than the rest
+    pub fn scaled(self, k: f64) -> Self {
+        match self {
+            Emission::Uniform(c) => Emission::Uniform(c * k),
+            Emission::Linear {
+                x1,
+                y1,
+                x2,
+                y2,
+                a,
+                b,
+            } => Emission::Linear {
+                x1,
+                y1,
+                x2,
+                y2,
+                a: a * k,
+                b: b * k,
+            },
+            Emission::Radial { cx, cy, r, a, b } => Emission::Radial {
+                cx,
+                cy,
+                r,
+                a: a * k,
+                b: b * k,
+            },
+            Emission::Image {
+                img,
+                x,
+                y,
+                w,
+                intensity,
+            } => Emission::Image {
+                img,
+                x,
+                y,
+                w,
+                intensity: intensity * k,
+            },
+        }
+    }
+
+    pub fn is_black(&self) -> bool {
+        match self {
+            Emission::Uniform(c) => c.is_black(),
+            _ => false,
+        }
+    }
+
+    pub fn at(&self, (px, py): (f64, f64)) -> Color {
+        match self {
+            Emission::Uniform(c) => *c,
+            Emission::Linear {
+                x1,
+                y1,
+                x2,
+                y2,
+                a,
+                b,
+            } => {
+                let (dx, dy) = (x2 - x1, y2 - y1);
+                let len2 = dx * dx + dy * dy;
+                let t = if len2 > 0.0 {
+                    (((px - x1) * dx + (py - y1) * dy) / len2).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                mix(*a, *b, t)
+            }
+            Emission::Radial { cx, cy, r, a, b } => {
+                let t = if *r > 0.0 {
+                    (distance((px, py), (*cx, *cy)) / r).min(1.0)
+                } else {
+                    1.0
+                };
+                mix(*a, *b, t)
+            }
+            Emission::Image {
+                img,
+                x,
+                y,
+                w,
+                intensity,
+            } => {
+                let scale = img.width() as f64 / w;
+                let u = ((px - x) * scale).floor();
+                let v = ((py - y) * scale).floor();
+                if u < 0.0 || v < 0.0 || u >= img.width() as f64 || v >= img.height() as f64 {
+                    return Color::black();
+                }
+                let [r, g, b] = img.get_pixel(u as u32, v as u32).0;
+                Color {
+                    r: r as f64 / 255.0,
+                    g: g as f64 / 255.0,
+                    b: b as f64 / 255.0,
+                } * *intensity
+            }
+        }
+    }
+}
+
+// the light of the rays hitting nothing, as a function of their direction
+pub enum Background {
+    Uniform(Color),
+    // from top for the rays going up the image to bottom for the ones going down
+    Vertical { top: Color, bottom: Color },
+    // the colors at the angles in degrees, clockwise on the image from the right, mixed around
+    // the circle. sorted by the angle, in [0, 360)
+    Angular(Vec<(f64, Color)>),
+}
+
+#[allow(dead_code)]
+impl Background {
+    pub fn angular(stops: Vec<(f64, Color)>) -> Self {
+        let mut stops: Vec<_> = stops
+            .into_iter()
+            .map(|(angle, c)| (angle.rem_euclid(360.0), c))
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Background::Angular(stops)
+    }
+
+    pub fn at(&self, (dx, dy): (f64, f64)) -> Color {
+        match self {
+            Background::Uniform(c) => *c,
+            Background::Vertical { top, bottom } => {
+                let t = (dy / (dx * dx + dy * dy).sqrt() + 1.0) / 2.0;
+                mix(*top, *bottom, t)
+            }
+            Background::Angular(stops) if stops.is_empty() => Color::black(),
+            Background::Angular(stops) => {
+                let a = dy.atan2(dx).to_degrees().rem_euclid(360.0);
+                // the stops on both sides of a, the last one before 0 and the first one after 360
+                let i = stops.partition_point(|&(angle, _)| angle <= a);
+                let (a0, c0) = match i {
+                    0 => (stops[stops.len() - 1].0 - 360.0, stops[stops.len() - 1].1),
+                    _ => stops[i - 1],
+                };
+                let (a1, c1) = match stops.get(i) {
+                    Some(&stop) => stop,
+                    None => (stops[0].0 + 360.0, stops[0].1),
+                };
+                let t = if a1 > a0 { (a - a0) / (a1 - a0) } else { 0.0 };
+                mix(c0, c1, t)
+            }
+        }
+    }
+}
+
+fn mix(a: Color, b: Color, t: f64) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+// a gradient index, added to the eta of a dielectric. only the paths from the pixels are bent
+// by it, the light paths and the photons go straight
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Grin {
+    // by rate per unit length along (nx, ny) from (x, y)
+    #[serde(rename = "linear")]
+    Linear {
+        x: f64,
+        y: f64,
+        nx: f64,
+        ny: f64,
+        rate: f64,
+    },
+    // by rate times the squared distance from (cx, cy), a lens with a negative rate
+    #[serde(rename = "radial")]
+    Radial { cx: f64, cy: f64, rate: f64 },
+}
+
+impl Grin {
+    // added to the eta at p
+    pub fn at(&self, (x, y): (f64, f64)) -> f64 {
+        match *self {
+            Grin::Linear {
+                x: x0,
+                y: y0,
+                nx,
+                ny,
+                rate,
+            } => {
+                let len = (nx * nx + ny * ny).sqrt();
+                rate * ((x - x0) * nx + (y - y0) * ny) / len
+            }
+            Grin::Radial { cx, cy, rate } => {
+                let d = distance((x, y), (cx, cy));
+                rate * d * d
+            }
+        }
+    }
+
+    // of the index at p
+    pub fn gradient(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        match *self {
+            Grin::Linear { nx, ny, rate, .. } => {
+                let len = (nx * nx + ny * ny).sqrt();
+                (rate * nx / len, rate * ny / len)
+            }
+            Grin::Radial { cx, cy, rate } => (2.0 * rate * (x - cx), 2.0 * rate * (y - cy)),
+        }
+    }
+}
+
+// how the emission dims with the distance the light goes from the surface of the emitter to
+// the next one. each by the distance it is halved at
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Falloff {
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    // 1 / (1 + d / half)
+    #[serde(rename = "linear")]
+    Linear(f64),
+    // 1 / (1 + (d / half)²)
+    #[serde(rename = "inverse_square")]
+    InverseSquare(f64),
+}
+
+impl Falloff {
+    pub fn factor(&self, d: f64) -> f64 {
+        match *self {
+            Falloff::None => 1.0,
+            Falloff::Linear(half) => 1.0 / (1.0 + d / half),
+            Falloff::InverseSquare(half) => 1.0 / (1.0 + (d / half) * (d / half)),
+        }
+    }
+}
+
+// how the emission varies with the angle to the outward normal
+pub enum Profile {
+    Uniform,
+    // cos^n, nothing sideways or backwards
+    Cosine(f64),
+    // factors evenly spaced from along the normal (0°) to sideways (90°), linearly interpolated
+    Table(Vec<f64>),
+}
+
+impl Profile {
+    pub fn factor(&self, cos: f64) -> f64 {
+        match self {
+            Profile::Uniform => 1.0,
+            Profile::Cosine(n) => cos.max(0.0).powf(*n),
+            Profile::Table(table) => {
+                if cos <= 0.0 || table.is_empty() {
+                    return 0.0;
+                }
+                let t = cos.min(1.0).acos() / FRAC_PI_2 * (table.len() - 1) as f64;
+                let i = (t.floor() as usize).min(table.len() - 1);
+                let j = (i + 1).min(table.len() - 1);
+                let f = t - i as f64;
+                table[i] * (1.0 - f) + table[j] * f
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{calculate::distance, noise::Perlin, random::gen_range};
+
+// how much of the medium there is at a point, from 0 to 1
+pub enum Density {
+    Constant(f64),
+    Noise(Perlin),
+}
+
+impl Density {
+    fn at(&self, p: (f64, f64)) -> f64 {
+        match self {
+            Density::Constant(c) => *c,
+            Density::Noise(noise) => noise.at(p),
+        }
+    }
+
+    fn max(&self) -> f64 {
+        match self {
+            Density::Constant(c) => *c,
+            Density::Noise(_) => 1.0,
+        }
+    }
+}
+
+// a participating medium filling the scene, like fog or smoke
+pub struct Medium {
+    // the coefficients per unit length, at the density 1
+    pub scattering: f64,
+    pub absorption: f64,
+    pub density: Density,
+}
+
+#[allow(dead_code)]
+impl Medium {
+    fn extinction(&self) -> f64 {
+        self.scattering + self.absorption
+    }
+
+    // the largest extinction anywhere
+    fn majorant(&self) -> f64 {
+        self.extinction() * self.density.max()
+    }
+
+    // the chance of scattering, instead of absorption, at an interaction
+    pub fn albedo(&self) -> f64 {
+        if self.extinction() > 0.0 {
+            self.scattering / self.extinction()
+        } else {
+            0.0
+        }
+    }
+
+    // the fraction of light left after travelling from a to b, estimated by ratio tracking
+    pub fn transmittance(&self, a: (f64, f64), b: (f64, f64)) -> f64 {
+        let len = distance(a, b);
+        if let Density::Constant(c) = self.density {
+            return (-self.extinction() * c * len).exp();
+        }
+        let majorant = self.majorant();
+        if majorant <= 0.0 || len <= 0.0 {
+            return 1.0;
+        }
+        let d = ((b.0 - a.0) / len, (b.1 - a.1) / len);
+        let mut result = 1.0;
+        let mut t = 0.0;
+        loop {
+            t -= (1.0 - gen_range(0.0..1.0f64)).ln() / majorant;
+            if t >= len {
+                return result;
+            }
+            let p = (a.0 + d.0 * t, a.1 + d.1 * t);
+            result *= 1.0 - self.density.at(p) / self.density.max();
+        }
+    }
+
+    // the distance of the next interaction along the unit direction d from p,
+    // None if the ray reaches t_max first. found by delta tracking
+    pub fn sample(&self, p: (f64, f64), d: (f64, f64), t_max: f64) -> Option<f64> {
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return None;
+        }
+        let mut t = 0.0;
+        loop {
+            t -= (1.0 - gen_range(0.0..1.0f64)).ln() / majorant;
+            if t >= t_max {
+                return None;
+            }
+            let q = (p.0 + d.0 * t, p.1 + d.1 * t);
+            if gen_range(0.0..1.0) * self.density.max() < self.density.at(q) {
+                return Some(t);
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+// 2D gradient noise by Ken Perlin, summed over octaves
+pub struct Perlin {
+    perm: Vec<usize>,
+    octaves: u32,
+    scale: f64,
+}
+
+#[allow(dead_code)]
+impl Perlin {
+    pub fn new(octaves: u32, scale: f64, seed: u64) -> Self {
+        let mut perm: Vec<usize> = (0..256).collect();
+        perm.shuffle(&mut StdRng::seed_from_u64(seed));
+        perm.extend_from_within(..);
+        Self {
+            perm,
+            octaves: octaves.max(1),
+            scale,
+        }
+    }
+
+    fn gradient(&self, i: i64, j: i64, x: f64, y: f64) -> f64 {
+        let h = self.perm[self.perm[(i & 255) as usize] + (j & 255) as usize];
+        match h & 7 {
+            0 => x + y,
+            1 => x - y,
+            2 => -x + y,
+            3 => -x - y,
+            4 => x,
+            5 => -x,
+            6 => y,
+            _ => -y,
+        }
+    }
+
+    // about -1 to 1
+    fn noise(&self, x: f64, y: f64) -> f64 {
+        let (i, j) = (x.floor() as i64, y.floor() as i64);
+        let (fx, fy) = (x - i as f64, y - j as f64);
+        let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let (u, v) = (fade(fx), fade(fy));
+        let a = self.gradient(i, j, fx, fy);
+        let b = self.gradient(i + 1, j, fx - 1.0, fy);
+        let c = self.gradient(i, j + 1, fx, fy - 1.0);
+        let d = self.gradient(i + 1, j + 1, fx - 1.0, fy - 1.0);
+        let top = a + (b - a) * u;
+        let bottom = c + (d - c) * u;
+        top + (bottom - top) * v
+    }
+
+    // from 0 to 1, every octave doubles the frequency and halves the amplitude
+    pub fn at(&self, (x, y): (f64, f64)) -> f64 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = self.scale;
+        for _ in 0..self.octaves {
+            sum += self.noise(x * frequency, y * frequency) * amplitude;
+            total += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        (0.5 + 0.5 * sum / total).clamp(0.0, 1.0)
+    }
+}
@@ -0,0 +1,82 @@
+use crate::element::Color;
+
+// where a photon hit a diffuse surface
+#[derive(Clone, Copy)]
+pub struct Photon {
+    pub point: (f64, f64),
+    // of the surface, on the side the photon came from
+    pub normal: (f64, f64),
+    pub flux: Color,
+}
+
+// the photons in a kd tree, the median of every range splits it, by x at even depths and by y at
+// odd ones
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    // of the photons gathered around a point
+    pub radius: f64,
+}
+
+fn axis(p: (f64, f64), depth: usize) -> f64 {
+    if depth.is_multiple_of(2) {
+        p.0
+    } else {
+        p.1
+    }
+}
+
+#[allow(dead_code)]
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>, radius: f64) -> Self {
+        build(&mut photons, 0);
+        Self { photons, radius }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    // f is called with every photon within the radius of p
+    pub fn gather<F: FnMut(&Photon)>(&self, p: (f64, f64), mut f: F) {
+        search(&self.photons, 0, p, self.radius, &mut f);
+    }
+}
+
+fn build(photons: &mut [Photon], depth: usize) {
+    if photons.len() <= 1 {
+        return;
+    }
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| {
+        axis(a.point, depth).total_cmp(&axis(b.point, depth))
+    });
+    let (left, right) = photons.split_at_mut(mid);
+    build(left, depth + 1);
+    build(&mut right[1..], depth + 1);
+}
+
+fn search<F: FnMut(&Photon)>(photons: &[Photon], depth: usize, p: (f64, f64), r: f64, f: &mut F) {
+    if photons.is_empty() {
+        return;
+    }
+    let mid = photons.len() / 2;
+    let photon = &photons[mid];
+    let (dx, dy) = (photon.point.0 - p.0, photon.point.1 - p.1);
+    if dx * dx + dy * dy <= r * r {
+        f(photon);
+    }
+    let delta = axis(p, depth) - axis(photon.point, depth);
+    let (near, far) = if delta < 0.0 {
+        (&photons[..mid], &photons[mid + 1..])
+    } else {
+        (&photons[mid + 1..], &photons[..mid])
+    };
+    search(near, depth + 1, p, r, f);
+    if delta.abs() <= r {
+        search(far, depth + 1, p, r, f);
+    }
+}
@@ -0,0 +1,243 @@
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{element::Color, film::Film, random, render::Aovs};
+
+// the noise of a render of few samples smoothed away by a bilateral filter: the pixels around
+// averaged by how near and how alike they are, and by the normals and the depths too when
+// they are given, so that the edges stay sharp. before the bloom
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Denoise {
+    // of the pixels averaged around a pixel, in pixels
+    #[serde(default = "default_denoise_radius")]
+    pub radius: u32,
+    // the difference of the colors, relative to the brightness of the pixel, below which they
+    // are alike
+    #[serde(default = "default_denoise_color")]
+    pub color: f64,
+    // the same for the normals, guided by the normal aov. not by them without
+    #[serde(default)]
+    pub normal: Option<f64>,
+    // the same for the depths, in world units, guided by the depth aov
+    #[serde(default)]
+    pub depth: Option<f64>,
+}
+
+fn default_denoise_radius() -> u32 {
+    3
+}
+
+fn default_denoise_color() -> f64 {
+    0.5
+}
+
+impl Denoise {
+    // needs the aovs
+    pub fn is_guided(&self) -> bool {
+        self.normal.is_some() || self.depth.is_some()
+    }
+
+    pub fn apply(&self, film: &mut Film, aovs: Option<&Aovs>) {
+        let (width, height) = (film.width as i64, film.height as i64);
+        let r = self.radius as i64;
+        let sigma = (self.radius as f64 / 2.0).max(0.5);
+        let src = &*film;
+        let pixels: Vec<Color> = (0..height)
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                (0..width).map(move |x| {
+                    let c = src.get(x as u32, y as u32);
+                    let scale = self.color * (1.0 + c.luminance());
+                    let mut sum = Color::black();
+                    let mut total = 0.0;
+                    for sy in (y - r).max(0)..=(y + r).min(height - 1) {
+                        for sx in (x - r).max(0)..=(x + r).min(width - 1) {
+                            let o = src.get(sx as u32, sy as u32);
+                            let d2 = ((sx - x).pow(2) + (sy - y).pow(2)) as f64;
+                            let mut e = d2 / (2.0 * sigma * sigma)
+                                + distance2(c, o) / (2.0 * scale * scale);
+                            if let Some(aovs) = aovs {
+                                let (p, q) = ((x as u32, y as u32), (sx as u32, sy as u32));
+                                if let Some(s) = self.normal {
+                                    let d = distance2(
+                                        aovs.normal.get(p.0, p.1),
+                                        aovs.normal.get(q.0, q.1),
+                                    );
+                                    e += d / (2.0 * s * s);
+                                }
+                                if let Some(s) = self.depth {
+                                    let (a, b) =
+                                        (aovs.depth.get(p.0, p.1).r, aovs.depth.get(q.0, q.1).r);
+                                    // both far from every boundary
+                                    let d = if a == b { 0.0 } else { a - b };
+                                    e += d * d / (2.0 * s * s);
+                                }
+                            }
+                            let w = (-e).exp();
+                            sum = sum + o * w;
+                            total += w;
+                        }
+                    }
+                    sum * (1.0 / total)
+                })
+            })
+            .collect();
+        for (i, c) in pixels.into_iter().enumerate() {
+            film.put(i as u32 % film.width, i as u32 / film.width, c);
+        }
+    }
+}
+
+// of the colors, squared
+fn distance2(a: Color, b: Color) -> f64 {
+    (a.r - b.r).powi(2) + (a.g - b.g).powi(2) + (a.b - b.b).powi(2)
+}
+
+// glow around the bright pixels, the light of the render above the threshold spread by a
+// gaussian and added back. before the exposure and the tone map
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Bloom {
+    // of the luminance, the light of a pixel above it glows
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    // the standard deviation of the gaussian, in pixels
+    pub radius: f64,
+    // of the glow added
+    #[serde(default = "default_strength")]
+    pub strength: f64,
+}
+
+fn default_threshold() -> f64 {
+    1.0
+}
+
+fn default_strength() -> f64 {
+    1.0
+}
+
+impl Bloom {
+    pub fn apply(&self, film: &mut Film) {
+        let (width, height) = (film.width, film.height);
+        let bright: Vec<Color> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let c = film.get(x, y);
+                let lum = c.luminance();
+                if lum <= self.threshold {
+                    return Color::black();
+                }
+                c * ((lum - self.threshold) / lum)
+            })
+            .collect();
+        let glow = blur(&bright, (width, height), &gaussian(self.radius));
+        for y in 0..height {
+            for x in 0..width {
+                let c = film.get(x, y) + glow[(y * width + x) as usize] * self.strength;
+                film.put(x, y, c);
+            }
+        }
+    }
+}
+
+// the finishing touches of a lens and a film, on the colors after the exposure and the tone
+// map, before the encoding
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Finish {
+    // the red moved out from the center and the blue in, by these pixels at the corners
+    #[serde(default)]
+    pub aberration: f64,
+    // the darkening of the corners, 0 for none to 1 for black
+    #[serde(default)]
+    pub vignette: f64,
+    // the largest noise added to a pixel, the same on every channel
+    #[serde(default)]
+    pub grain: f64,
+}
+
+impl Finish {
+    // the grain is the same for every render with a seed
+    pub fn apply(&self, film: &mut Film, seed: Option<u64>) {
+        let (width, height) = (film.width, film.height);
+        let (cx, cy) = (width as f64 / 2.0, height as f64 / 2.0);
+        let corner = cx.hypot(cy);
+        let src = film.clone();
+        random::reseed(seed, &[]);
+        for y in 0..height {
+            for x in 0..width {
+                let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+                let mut c = src.get(x, y);
+                if self.aberration != 0.0 {
+                    let s = self.aberration / corner;
+                    c.r = sample(&src, cx + dx * (1.0 - s), cy + dy * (1.0 - s)).r;
+                    c.b = sample(&src, cx + dx * (1.0 + s), cy + dy * (1.0 + s)).b;
+                }
+                let r = dx.hypot(dy) / corner;
+                c = c * (1.0 - self.vignette * r * r).max(0.0);
+                if self.grain > 0.0 {
+                    let n = random::gen_range(-self.grain..self.grain);
+                    c = Color {
+                        r: (c.r + n).max(0.0),
+                        g: (c.g + n).max(0.0),
+                        b: (c.b + n).max(0.0),
+                    };
+                }
+                film.put(x, y, c);
+            }
+        }
+    }
+}
+
+// of the film at the point, between the centers of the pixels around it. the pixels past the
+// edges are the ones on them
+fn sample(film: &Film, x: f64, y: f64) -> Color {
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let get = |i: f64, j: f64| {
+        let i = (i as i64).clamp(0, film.width as i64 - 1) as u32;
+        let j = (j as i64).clamp(0, film.height as i64 - 1) as u32;
+        film.get(i, j)
+    };
+    let top = get(x0, y0) * (1.0 - tx) + get(x0 + 1.0, y0) * tx;
+    let bottom = get(x0, y0 + 1.0) * (1.0 - tx) + get(x0 + 1.0, y0 + 1.0) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+// the weights of the gaussian of the standard deviation sigma out to 3 sigma on both sides,
+// summing to 1
+fn gaussian(sigma: f64) -> Vec<f64> {
+    if sigma <= 0.0 {
+        return vec![1.0];
+    }
+    let r = (3.0 * sigma).ceil() as i64;
+    let weights: Vec<f64> = (-r..=r)
+        .map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = weights.iter().sum();
+    weights.into_iter().map(|w| w / sum).collect()
+}
+
+// the pixels, row after row, blurred by the kernel along the rows then along the columns. the
+// pixels past the edges are the ones on them
+fn blur(pixels: &[Color], (width, height): (u32, u32), kernel: &[f64]) -> Vec<Color> {
+    let r = (kernel.len() / 2) as i64;
+    let (w, h) = (width as i64, height as i64);
+    let pass = |src: &[Color], along: (i64, i64)| -> Vec<Color> {
+        (0..h)
+            .flat_map(|y| (0..w).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, &weight)| {
+                        let o = k as i64 - r;
+                        let sx = (x + o * along.0).clamp(0, w - 1);
+                        let sy = (y + o * along.1).clamp(0, h - 1);
+                        src[(sy * w + sx) as usize] * weight
+                    })
+                    .sum()
+            })
+            .collect()
+    };
+    let rows = pass(pixels, (1, 0));
+    pass(&rows, (0, 1))
+}
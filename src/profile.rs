@@ -0,0 +1,145 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use web_time::Instant;
+
+// of the renders counted at the same time, nothing is counted without one. the renders only
+// check this
+static ENABLED: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static PROFILE: RefCell<Profile> = RefCell::new(Profile::default());
+}
+
+// the work of the renders, counted on every thread and gathered by take
+#[derive(Clone, Default)]
+pub struct Profile {
+    // the intersections asked of the scene, a ray each
+    pub rays: u64,
+    // the time taken by them, of all the threads together
+    pub intersection: Duration,
+    // the paths traced from the points of the pixels, and the surfaces they were scattered by
+    pub paths: u64,
+    pub bounces: u64,
+    // the rays totally reflected inside a dielectric
+    pub tir: u64,
+    // the intersections of a ray with a shape, or the distances to it of the sdf engine, by
+    // the kind of the shape
+    pub tests: BTreeMap<&'static str, u64>,
+}
+
+impl Profile {
+    // of the surfaces a path was scattered by
+    pub fn depth(&self) -> f64 {
+        match self.paths {
+            0 => 0.0,
+            n => self.bounces as f64 / n as f64,
+        }
+    }
+
+    pub fn merge(&mut self, other: Profile) {
+        self.rays += other.rays;
+        self.intersection += other.intersection;
+        self.paths += other.paths;
+        self.bounces += other.bounces;
+        self.tir += other.tir;
+        for (kind, n) in other.tests {
+            *self.tests.entry(kind).or_default() += n;
+        }
+    }
+}
+
+// on for one more render, or off for one less
+pub fn enable(on: bool) {
+    match on {
+        true => ENABLED.fetch_add(1, Ordering::Relaxed),
+        false => ENABLED.fetch_sub(1, Ordering::Relaxed),
+    };
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed) > 0
+}
+
+// on while it lives
+pub struct Counting;
+
+pub fn start() -> Counting {
+    enable(true);
+    Counting
+}
+
+impl Drop for Counting {
+    fn drop(&mut self) {
+        enable(false);
+    }
+}
+
+fn count(f: impl FnOnce(&mut Profile)) {
+    if is_enabled() {
+        PROFILE.with(|p| f(&mut p.borrow_mut()));
+    }
+}
+
+// the start of the intersections of rays, ended by rays
+pub fn begin() -> Option<Instant> {
+    is_enabled().then(Instant::now)
+}
+
+pub fn rays(begin: Option<Instant>, n: u64) {
+    if let Some(begin) = begin {
+        let elapsed = begin.elapsed();
+        PROFILE.with(|p| {
+            let mut p = p.borrow_mut();
+            p.rays += n;
+            p.intersection += elapsed;
+        });
+    }
+}
+
+pub fn tests(kind: &'static str, n: u64) {
+    count(|p| *p.tests.entry(kind).or_default() += n);
+}
+
+pub fn paths(n: u64) {
+    count(|p| p.paths += n);
+}
+
+pub fn bounce() {
+    count(|p| p.bounces += 1);
+}
+
+pub fn tir() {
+    count(|p| p.tir += 1);
+}
+
+// the counts of every thread of the pool and of this one, which start again from nothing
+pub fn take() -> Profile {
+    let mut sum = PROFILE.with(|p| p.take());
+    for profile in rayon::broadcast(|_| PROFILE.with(|p| p.take())) {
+        sum.merge(profile);
+    }
+    sum
+}
+
+// a line for the rays and the paths, then one for every kind of shape
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} rays, {} paths of depth {:.2} on average, {} total internal reflections",
+            self.rays,
+            self.paths,
+            self.depth(),
+            self.tir
+        )?;
+        for (kind, n) in &self.tests {
+            write!(f, "\n    {}: {} tests", kind, n)?;
+        }
+        Ok(())
+    }
+}
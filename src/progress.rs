@@ -0,0 +1,273 @@
+use serde_json::{json, Value};
+use std::io::Write;
+use web_time::Instant;
+
+use crate::render::Live;
+
+// the progress of a render, on a bar of pbr, as json lines, to a function by Callback, or
+// nowhere with ()
+pub trait Progress {
+    // the number of the steps, before the first
+    fn start(&mut self, total: u64);
+    fn add(&mut self, n: u64);
+    // done, with the time it took
+    fn finish_print(&mut self, message: &str);
+    // stopped by an error
+    fn finish(&mut self);
+}
+
+impl Progress for () {
+    fn start(&mut self, _total: u64) {}
+    fn add(&mut self, _n: u64) {}
+    fn finish_print(&mut self, _message: &str) {}
+    fn finish(&mut self) {}
+}
+
+impl<P: Progress + ?Sized> Progress for Box<P> {
+    fn start(&mut self, total: u64) {
+        (**self).start(total);
+    }
+
+    fn add(&mut self, n: u64) {
+        (**self).add(n);
+    }
+
+    fn finish_print(&mut self, message: &str) {
+        (**self).finish_print(message);
+    }
+
+    fn finish(&mut self) {
+        (**self).finish();
+    }
+}
+
+impl<P: Progress + ?Sized> Progress for &mut P {
+    fn start(&mut self, total: u64) {
+        (**self).start(total);
+    }
+
+    fn add(&mut self, n: u64) {
+        (**self).add(n);
+    }
+
+    fn finish_print(&mut self, message: &str) {
+        (**self).finish_print(message);
+    }
+
+    fn finish(&mut self) {
+        (**self).finish();
+    }
+}
+
+#[cfg(feature = "pbr")]
+impl<T: Write> Progress for pbr::ProgressBar<T> {
+    fn start(&mut self, total: u64) {
+        self.total = total;
+        self.format("[=>-]");
+    }
+
+    fn add(&mut self, n: u64) {
+        pbr::ProgressBar::add(self, n);
+    }
+
+    fn finish_print(&mut self, message: &str) {
+        pbr::ProgressBar::finish_print(self, message);
+    }
+
+    fn finish(&mut self) {
+        pbr::ProgressBar::finish(self);
+    }
+}
+
+// a json line for every event of the render of out: start, progress at most every half a
+// second, and done or stopped. samples are the ones of a step, when they are known
+pub struct JsonLines<W: Write> {
+    output: W,
+    out: String,
+    samples: Option<u64>,
+    done: u64,
+    total: u64,
+    begin: Instant,
+    last: Instant,
+}
+
+#[allow(dead_code)]
+impl<W: Write> JsonLines<W> {
+    pub fn new(output: W, out: &str, samples: Option<u64>) -> Self {
+        Self {
+            output,
+            out: out.to_string(),
+            samples,
+            done: 0,
+            total: 0,
+            begin: Instant::now(),
+            last: Instant::now(),
+        }
+    }
+
+    fn line(&mut self, event: Value) {
+        // in one write, the renders of --jobs share the output
+        let line = format!("{}\n", event);
+        self.output.write_all(line.as_bytes()).ok();
+        self.output.flush().ok();
+    }
+}
+
+impl<W: Write> Progress for JsonLines<W> {
+    fn start(&mut self, total: u64) {
+        self.done = 0;
+        self.total = total;
+        self.begin = Instant::now();
+        self.last = self.begin;
+        self.line(json!({"event": "start", "out": self.out, "total": total}));
+    }
+
+    fn add(&mut self, n: u64) {
+        self.done += n;
+        let now = Instant::now();
+        if (now - self.last).as_secs_f64() < 0.5 {
+            return;
+        }
+        self.last = now;
+        let seconds = (now - self.begin).as_secs_f64();
+        let (done, total) = (self.done as f64, self.total.max(1) as f64);
+        let eta = seconds * (total - done).max(0.0) / done;
+        let rate = self.samples.map(|s| (s as f64 * done / seconds).round());
+        self.line(json!({
+            "event": "progress",
+            "out": self.out,
+            "done": self.done,
+            "total": self.total,
+            "percent": (1000.0 * done / total).round() / 10.0,
+            "eta": (eta * 10.0).round() / 10.0,
+            "samples_per_sec": rate,
+        }));
+    }
+
+    fn finish_print(&mut self, _message: &str) {
+        let seconds = self.begin.elapsed().as_secs_f64();
+        self.line(json!({"event": "done", "out": self.out, "seconds": seconds}));
+    }
+
+    fn finish(&mut self) {
+        self.line(
+            json!({"event": "stopped", "out": self.out, "done": self.done, "total": self.total}),
+        );
+    }
+}
+
+// how far a render is, given to the function of render_with_progress after every step
+pub struct Status<'a> {
+    pub done: u64,
+    pub total: u64,
+    // since the start
+    pub seconds: f64,
+    live: &'a Live,
+}
+
+#[allow(dead_code)]
+impl Status<'_> {
+    // from 0 to 1
+    pub fn fraction(&self) -> f64 {
+        self.done as f64 / self.total.max(1) as f64
+    }
+
+    // the steps after it are not rendered
+    pub fn cancel(&self) {
+        self.live.stop();
+    }
+}
+
+// gives the status to f, at the start and after every step
+pub struct Callback<'a, F> {
+    live: &'a Live,
+    f: F,
+    done: u64,
+    total: u64,
+    begin: Instant,
+}
+
+#[allow(dead_code)]
+impl<'a, F: FnMut(Status)> Callback<'a, F> {
+    pub fn new(live: &'a Live, f: F) -> Self {
+        Self {
+            live,
+            f,
+            done: 0,
+            total: 0,
+            begin: Instant::now(),
+        }
+    }
+
+    fn call(&mut self) {
+        (self.f)(Status {
+            done: self.done,
+            total: self.total,
+            seconds: self.begin.elapsed().as_secs_f64(),
+            live: self.live,
+        });
+    }
+}
+
+impl<F: FnMut(Status)> Progress for Callback<'_, F> {
+    fn start(&mut self, total: u64) {
+        self.done = 0;
+        self.total = total;
+        self.begin = Instant::now();
+        self.call();
+    }
+
+    fn add(&mut self, n: u64) {
+        self.done += n;
+        self.call();
+    }
+
+    fn finish_print(&mut self, _message: &str) {}
+
+    fn finish(&mut self) {}
+}
+
+// the progress of count renders in a row as the one of p, started by the first and finished by
+// the last with the time of all of them
+pub struct Sequence<P> {
+    p: P,
+    count: u64,
+    started: u64,
+    begin: Instant,
+}
+
+#[allow(dead_code)]
+impl<P: Progress> Sequence<P> {
+    pub fn new(p: P, count: u64) -> Self {
+        Self {
+            p,
+            count,
+            started: 0,
+            begin: Instant::now(),
+        }
+    }
+}
+
+impl<P: Progress> Progress for Sequence<P> {
+    fn start(&mut self, total: u64) {
+        if self.started == 0 {
+            self.begin = Instant::now();
+            self.p.start(total * self.count);
+        }
+        self.started += 1;
+    }
+
+    fn add(&mut self, n: u64) {
+        self.p.add(n);
+    }
+
+    fn finish_print(&mut self, _message: &str) {
+        if self.started == self.count {
+            self.p.finish_print(&format!("{:?}", self.begin.elapsed()));
+        }
+    }
+
+    fn finish(&mut self) {
+        self.p.finish();
+    }
+}
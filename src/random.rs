@@ -0,0 +1,35 @@
+use rand::{
+    distributions::uniform::{SampleRange, SampleUniform},
+    rngs::SmallRng,
+    Rng, SeedableRng,
+};
+use std::cell::RefCell;
+
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+// splitmix64
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// restart the random numbers of this thread from the seed and the keys, like the position of
+// a pixel, so that they don't depend on which thread renders what. nothing without a seed
+pub fn reseed(seed: Option<u64>, keys: &[u64]) {
+    if let Some(seed) = seed {
+        let state = keys.iter().fold(mix(seed), |h, &k| mix(h ^ k));
+        RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(state));
+    }
+}
+
+pub fn gen_range<T: SampleUniform, R: SampleRange<T>>(range: R) -> T {
+    RNG.with(|rng| rng.borrow_mut().gen_range(range))
+}
+
+pub fn gen_bool(p: f64) -> bool {
+    RNG.with(|rng| rng.borrow_mut().gen_bool(p))
+}
@@ -1,73 +1,582 @@
-use image::{ImageBuffer, Rgb, RgbImage};
-use pbr::ProgressBar;
-use rand::Rng;
-use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
-use std::{cmp::min, f64::consts::PI};
-
-use crate::{calculate::distance, element::Color, shapes::*};
-struct EntityIntersection {
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use std::{
+    cmp::min,
+    f64::consts::PI,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+use web_time::Instant;
+
+use crate::{
+    bvh::Bvh,
+    calculate::distance,
+    element::Color,
+    error::Error,
+    film::{Film, Stats},
+    light::Light,
+    material::{Background, Falloff, Material, Profile},
+    medium::Medium,
+    photon::{Photon, PhotonMap},
+    profile,
+    progress::{Callback, Progress, Status},
+    random::{gen_bool, gen_range, reseed},
+    shapes::*,
+};
+#[derive(Clone)]
+struct EntityIntersection<'a> {
     point: (f64, f64),
     normal: (f64, f64),
-    emissive: Color,
-    reflectivity: f64,
-    eta: f64,
-    absorption: Color,
+    // the ray parameter, point = p + t * d
+    t: f64,
+    material: &'a Material,
+    profile: &'a Profile,
+    // the index in the scene
+    entity: usize,
 }
 
 pub struct Entity {
     pub shape: Box<dyn Shape + Sync>,
-    // 放射
-    pub emissive: Color,
-    pub reflectivity: f64,
-    // 折射率
-    pub eta: f64,
-    // 吸收
-    pub absorption: Color,
+    pub material: Material,
+    // the angular distribution of the emission
+    pub profile: Profile,
+    // of the emission with the distance from it
+    pub falloff: Falloff,
+    // the box of the shape, rays missing it are not tested against the shape
+    pub aabb: Option<Aabb>,
+    pub visibility: Visibility,
+    // of the image of the layer, DEFAULT_LAYER without
+    pub layer: Option<String>,
+    // of the surface hit from behind its normal, which is then turned to the ray. for the two
+    // sides of the open shapes, the material on both of them without. the emission of it is
+    // only found by the rays, not by the emitters
+    pub back: Option<Material>,
+}
+
+// the layer of the entities without one, of the rays reaching nothing and of the lights
+pub const DEFAULT_LAYER: &str = "default";
+
+// the rays an entity is seen by, all of them by default
+#[derive(Clone, Copy)]
+pub struct Visibility {
+    // the rays from the pixels, not the ones after a vertex
+    pub camera: bool,
+    // the rays towards the lights and from the light paths, the shadows of the entity
+    pub shadow: bool,
+    // its emission lighting the other surfaces, without it the emission is only seen directly
+    pub light: bool,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self {
+            camera: true,
+            shadow: true,
+            light: true,
+        }
+    }
+}
+
+// what a ray is traced for, the entities it hits are by their visibility
+#[derive(Clone, Copy)]
+enum Sight {
+    Camera,
+    Shadow,
+    Other,
 }
 
 #[allow(dead_code)]
 impl Entity {
-    fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<EntityIntersection> {
-        self.shape
-            .intersect(p, d)
-            .iter()
-            .map(|intersection| EntityIntersection {
-                point: intersection.point,
-                normal: intersection.normal,
-                emissive: self.emissive.clone(),
-                reflectivity: self.reflectivity,
-                eta: self.eta,
-                absorption: self.absorption,
-            })
-            .collect()
+    fn seen_by(&self, sight: Sight) -> bool {
+        match sight {
+            Sight::Camera => self.visibility.camera,
+            Sight::Shadow => self.visibility.shadow,
+            Sight::Other => true,
+        }
+    }
+
+    // the nearest intersection before t_max
+    fn intersect(
+        &self,
+        index: usize,
+        p: (f64, f64),
+        d: (f64, f64),
+        t_max: f64,
+    ) -> Option<EntityIntersection<'_>> {
+        if let Some(aabb) = &self.aabb {
+            if aabb.entry(p, d).is_none_or(|t| t > t_max) {
+                return None;
+            }
+        }
+        profile::tests(self.shape.kind(), 1);
+        let (intersection, t) = self.shape.nearest(p, d, t_max)?;
+        Some(self.intersection(index, intersection, d, t))
+    }
+
+    // intersect for 4 rays from p
+    fn intersect4(
+        &self,
+        index: usize,
+        p: (f64, f64),
+        d: [(f64, f64); 4],
+        mut t_max: [f64; 4],
+    ) -> [Option<EntityIntersection<'_>>; 4] {
+        if let Some(aabb) = &self.aabb {
+            for j in 0..4 {
+                if aabb.entry(p, d[j]).is_none_or(|t| t > t_max[j]) {
+                    // no hit can be before 0
+                    t_max[j] = 0.0;
+                }
+            }
+            if t_max.iter().all(|&t| t <= 0.0) {
+                return [None, None, None, None];
+            }
+        }
+        profile::tests(self.shape.kind(), 4);
+        let hits = self.shape.nearest4([p; 4], d, t_max);
+        std::array::from_fn(|j| {
+            hits[j].map(|(intersection, t)| self.intersection(index, intersection, d[j], t))
+        })
+    }
+
+    // of the ray along d, on the back when it hits it
+    fn intersection(
+        &self,
+        index: usize,
+        intersection: Intersection,
+        (dx, dy): (f64, f64),
+        t: f64,
+    ) -> EntityIntersection<'_> {
+        let (nx, ny) = intersection.normal;
+        let (normal, material) = match &self.back {
+            Some(back) if dx * nx + dy * ny > 0.0 => ((-nx, -ny), back),
+            _ => ((nx, ny), &self.material),
+        };
+        EntityIntersection {
+            point: intersection.point,
+            normal,
+            t,
+            material,
+            profile: &self.profile,
+            entity: index,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Engine {
+    // intersections computed by Shape::intersect
+    #[default]
+    #[serde(rename = "analytic")]
+    Analytic,
+    // intersections found by ray marching on Sdf::sdf
+    #[serde(rename = "sdf")]
+    Sdf,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    #[serde(rename = "normal")]
+    Normal,
+    // paths ending in total internal reflection are magenta
+    #[serde(rename = "debug_tir")]
+    DebugTir,
+    // the shapes filled with the flat colors of their materials, nothing is traced
+    #[serde(rename = "layout")]
+    Layout,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Integrator {
+    // paths from the pixel only
+    #[default]
+    #[serde(rename = "path")]
+    Path,
+    // also paths from the emitters, through specular surfaces to a diffuse one seen by the pixel
+    #[serde(rename = "bidirectional")]
+    Bidirectional,
+    // the light on the diffuse surfaces from the photons nearby
+    #[serde(rename = "photon_map")]
+    PhotonMap,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Fresnel {
+    #[serde(rename = "exact")]
+    Exact,
+    #[default]
+    #[serde(rename = "schlick")]
+    Schlick,
+    // no reflection while refraction is possible
+    #[serde(rename = "none")]
+    None,
+}
+
+impl Fresnel {
+    // the reflectance at a surface from the index etai to etat
+    fn reflectance(&self, cosi: f64, cost: f64, etai: f64, etat: f64) -> f64 {
+        match self {
+            Fresnel::Exact => fresnel(cosi, cost, etai, etat),
+            Fresnel::Schlick => schlick(cosi, cost, etai, etat),
+            Fresnel::None => 0.0,
+        }
     }
 }
 
 pub struct Scene {
     pub entities: Vec<Entity>,
+    pub lights: Vec<Box<dyn Light + Sync>>,
+    pub engine: Engine,
+    pub medium: Option<Medium>,
+    // of the rays hitting nothing, black without
+    pub background: Option<Background>,
+    pub mode: Mode,
+    pub fresnel: Fresnel,
+    pub sampler: Sampler,
+    pub aa: Aa,
+    // next event estimation, directions sampled towards the emitters too
+    pub nee: bool,
+    pub integrator: Integrator,
+    // for the photon map integrator
+    pub photons: Option<PhotonMap>,
+    // the emissive entities with a box, set by Scene::new
+    pub emitters: Vec<usize>,
+    // of the random numbers, the same for every render when set
+    pub seed: Option<u64>,
+    // over the entities, built by Scene::new
+    pub bvh: Bvh,
+    // the light only of the rays from the pixels first reaching the entities of the layer, all
+    // of it without. the images of the layers add up to the whole one
+    pub layer: Option<String>,
 }
 
 impl Scene {
-    fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Option<EntityIntersection> {
-        let mut res: Option<EntityIntersection> = None;
+    // the other fields are defaults, to be set by struct update
+    pub fn new(entities: Vec<Entity>) -> Self {
+        let boxes: Vec<Option<Aabb>> = entities.iter().map(|e| e.aabb).collect();
+        Self {
+            bvh: Bvh::new(&boxes),
+            lights: Vec::new(),
+            engine: Engine::default(),
+            medium: None,
+            background: None,
+            mode: Mode::default(),
+            fresnel: Fresnel::default(),
+            sampler: Sampler::default(),
+            aa: Aa::default(),
+            nee: false,
+            integrator: Integrator::default(),
+            photons: None,
+            emitters: (0..entities.len())
+                .filter(|&i| {
+                    let e = &entities[i];
+                    e.material.emissive() && e.aabb.is_some() && e.visibility.light
+                })
+                .collect(),
+            seed: None,
+            layer: None,
+            entities,
+        }
+    }
+
+    // of the entities, and DEFAULT_LAYER, in the order they first come in
+    pub fn layers(&self) -> Vec<String> {
+        let mut layers = vec![DEFAULT_LAYER.to_string()];
         for e in &self.entities {
-            for item in e.intersect(p, d) {
-                res = match res {
-                    Some(r) => {
-                        if distance(p, r.point) > distance(p, item.point) {
-                            Some(item)
-                        } else {
-                            Some(r)
-                        }
+            if let Some(layer) = &e.layer {
+                if !layers.contains(layer) {
+                    layers.push(layer.clone());
+                }
+            }
+        }
+        layers
+    }
+
+    // whether the light of the entity first reached, or of nothing, is in the layer rendered
+    fn in_layer(&self, entity: Option<usize>) -> bool {
+        let layer = match &self.layer {
+            Some(layer) => layer,
+            None => return true,
+        };
+        let of = entity.and_then(|i| self.entities[i].layer.as_deref());
+        of.unwrap_or(DEFAULT_LAYER) == layer
+    }
+
+    // with the photon map integrator, before the map is built. not for the layout
+    pub fn needs_photons(&self) -> bool {
+        self.integrator == Integrator::PhotonMap
+            && self.mode != Mode::Layout
+            && self.photons.is_none()
+    }
+
+    // whether there are emitters for the light paths
+    fn bidirectional(&self) -> bool {
+        self.integrator == Integrator::Bidirectional && !self.emitters.is_empty()
+    }
+
+    fn dispersive(&self) -> bool {
+        self.entities
+            .iter()
+            .any(|e| e.material.dispersive() || e.back.as_ref().is_some_and(|m| m.dispersive()))
+    }
+
+    // the nearest intersection of the ray from p along d before the ray parameter t_max, with
+    // the entities seen by it
+    fn intersect(
+        &self,
+        p: (f64, f64),
+        d: (f64, f64),
+        t_max: f64,
+        sight: Sight,
+    ) -> Option<EntityIntersection<'_>> {
+        let begin = profile::begin();
+        let hit = match self.engine {
+            Engine::Analytic => self.intersect_analytic(p, d, t_max, sight),
+            Engine::Sdf => self.intersect_sdf(p, d, t_max, sight),
+        };
+        profile::rays(begin, 1);
+        hit
+    }
+
+    fn intersect_analytic(
+        &self,
+        p: (f64, f64),
+        d: (f64, f64),
+        t_max: f64,
+        sight: Sight,
+    ) -> Option<EntityIntersection<'_>> {
+        self.bvh
+            .nearest(p, d, t_max, |i, t_max| {
+                let e = &self.entities[i];
+                if !e.seen_by(sight) {
+                    return None;
+                }
+                e.intersect(i, p, d, t_max).map(|item| {
+                    let t = item.t;
+                    (item, t)
+                })
+            })
+            .map(|(item, _)| item)
+    }
+
+    // intersect for 4 rays from p
+    fn intersect4(
+        &self,
+        p: (f64, f64),
+        d: [(f64, f64); 4],
+        t_max: f64,
+        sight: Sight,
+    ) -> [Option<EntityIntersection<'_>>; 4] {
+        let begin = profile::begin();
+        let hits = match self.engine {
+            Engine::Analytic => self
+                .bvh
+                .nearest4(p, d, t_max, |i, t_max| {
+                    let e = &self.entities[i];
+                    if !e.seen_by(sight) {
+                        return [None, None, None, None];
                     }
-                    None => Some(item),
+                    e.intersect4(i, p, d, t_max).map(|hit| {
+                        hit.map(|item| {
+                            let t = item.t;
+                            (item, t)
+                        })
+                    })
+                })
+                .map(|hit| hit.map(|(item, _)| item)),
+            Engine::Sdf => d.map(|d| self.intersect_sdf(p, d, t_max, sight)),
+        };
+        profile::rays(begin, 4);
+        hits
+    }
+
+    fn intersect_sdf(
+        &self,
+        p: (f64, f64),
+        (dx, dy): (f64, f64),
+        t_max: f64,
+        sight: Sight,
+    ) -> Option<EntityIntersection<'_>> {
+        let len = (dx * dx + dy * dy).sqrt();
+        let d = (dx / len, dy / len);
+        // past the boxes of the entities seen nothing is hit, with an unbounded one the march
+        // is only bounded by MAX_STEP
+        let limit = self
+            .entities
+            .iter()
+            .filter(|e| e.seen_by(sight))
+            .try_fold(0.0, |limit: f64, e| {
+                Some(limit.max(e.aabb?.exit(p, d).unwrap_or(0.0)))
+            })
+            .unwrap_or(f64::INFINITY);
+        // the entities not seen, or without an sdf, are never reached
+        let shapes: Vec<Option<&dyn Sdf>> = self
+            .entities
+            .iter()
+            .map(|e| e.shape.as_sdf().filter(|_| e.seen_by(sight)))
+            .collect();
+        // leave the surface the ray starts from
+        let mut t = SDF_BIAS;
+        let mut prev: Option<(f64, Vec<f64>)> = None;
+        for _ in 0..MAX_STEP {
+            let point = (p.0 + d.0 * t, p.1 + d.1 * t);
+            let ds: Vec<f64> = self
+                .entities
+                .iter()
+                .zip(&shapes)
+                .map(|(e, shape)| match shape {
+                    Some(shape) => {
+                        profile::tests(e.shape.kind(), 1);
+                        shape.sdf(point)
+                    }
+                    None => f64::INFINITY,
+                })
+                .collect();
+            // the nearest boundary crossed, or touched, by this step
+            let mut hit: Option<(usize, &dyn Sdf, f64)> = None;
+            for (i, (&di, shape)) in ds.iter().zip(&shapes).enumerate() {
+                let shape = match shape {
+                    Some(shape) => *shape,
+                    None => continue,
+                };
+                let th = match &prev {
+                    Some((t0, d0)) if d0[i] * di < 0.0 => bisect(shape, p, d, *t0, t),
+                    _ if di.abs() < SDF_EPSILON => t,
+                    _ => continue,
+                };
+                if hit.is_none_or(|(_, _, t)| th < t) {
+                    hit = Some((i, shape, th));
+                }
+            }
+            if let Some((i, shape, th)) = hit {
+                // marched along the unit direction
+                if th / len >= t_max {
+                    return None;
                 }
+                let e = &self.entities[i];
+                let point = (p.0 + d.0 * th, p.1 + d.1 * th);
+                let intersection = Intersection {
+                    point,
+                    normal: shape.normal(point),
+                };
+                return Some(e.intersection(i, intersection, d, th / len));
+            }
+            let step = ds.iter().fold(f64::INFINITY, |a, b| a.min(b.abs()));
+            prev = Some((t, ds));
+            t += step;
+            if t > limit || t / len >= t_max {
+                break;
             }
         }
-        res
+        None
+    }
+}
+
+// the lights seen directly from p, every surface casts a shadow. the shadow ray aims at a random
+// point within the softness of the light, the penumbra is as wide as its disk makes it.
+// on a surface with the normal n, they are weighted by the lambertian cosine
+fn direct_light(scene: &Scene, p: (f64, f64), n: Option<(f64, f64)>) -> Color {
+    scene
+        .lights
+        .iter()
+        .filter_map(|light| {
+            let l = light.position();
+            let l = match light.softness() {
+                s if s > 0.0 => {
+                    let r = s * gen_range(0.0..1.0f64).sqrt();
+                    let a = gen_range(0.0..2.0 * PI);
+                    (l.0 + r * a.cos(), l.1 + r * a.sin())
+                }
+                _ => l,
+            };
+            let d = (l.0 - p.0, l.1 - p.1);
+            let weight = match n {
+                Some((nx, ny)) => (d.0 * nx + d.1 * ny) / distance(p, l) * 0.5,
+                None => 1.0,
+            };
+            if weight <= 0.0 {
+                return None;
+            }
+            let transmittance = scene.medium.as_ref().map_or(1.0, |m| m.transmittance(p, l));
+            // d reaches the light at t = 1
+            match scene.intersect(p, d, 1.0, Sight::Shadow) {
+                Some(_) => None,
+                None => Some(light.illuminate(p) * (weight * transmittance)),
+            }
+        })
+        .sum()
+}
+
+// the angles from a point towards the box of an emitter
+struct Cone {
+    start: f64,
+    width: f64,
+}
+
+impl Cone {
+    fn contains(&self, a: f64) -> bool {
+        (a - self.start).rem_euclid(2.0 * PI) < self.width
     }
 }
 
+// towards every emitter from p, the whole circle for the ones around p
+fn cones(scene: &Scene, p: (f64, f64)) -> Vec<Cone> {
+    scene
+        .emitters
+        .iter()
+        .filter_map(|&i| scene.entities[i].aabb)
+        .map(|b| {
+            if p.0 >= b.min.0 && p.0 <= b.max.0 && p.1 >= b.min.1 && p.1 <= b.max.1 {
+                return Cone {
+                    start: 0.0,
+                    width: 2.0 * PI,
+                };
+            }
+            let center = ((b.min.1 + b.max.1) * 0.5 - p.1).atan2((b.min.0 + b.max.0) * 0.5 - p.0);
+            // the corners relative to the center, within half a circle of it
+            let (lo, hi) = b.corners().iter().fold((0.0f64, 0.0f64), |(lo, hi), c| {
+                let a = ((c.1 - p.1).atan2(c.0 - p.0) - center + PI).rem_euclid(2.0 * PI) - PI;
+                (lo.min(a), hi.max(a))
+            });
+            Cone {
+                start: center + lo,
+                width: hi - lo,
+            }
+        })
+        .collect()
+}
+
+// a random angle in a random one of the cones
+fn sample_cones(cones: &[Cone]) -> f64 {
+    let cone = &cones[gen_range(0..cones.len())];
+    cone.start + gen_range(0.0..1.0) * cone.width
+}
+
+// the density of the angles by sample_cones
+fn cones_pdf(cones: &[Cone], a: f64) -> f64 {
+    let sum: f64 = cones
+        .iter()
+        .filter(|cone| cone.contains(a) && cone.width > 0.0)
+        .map(|cone| 1.0 / cone.width)
+        .sum();
+    sum / cones.len() as f64
+}
+
+// the weights of the angle a sampled uniformly, and towards the emitters, by the balance heuristic
+fn balance(cones: &[Cone], a: f64) -> (f64, f64) {
+    let (uniform, towards) = (0.5 / PI, cones_pdf(cones, a));
+    (uniform / (uniform + towards), towards / (uniform + towards))
+}
+
+// a direction over the half plane of n, with the density of cosine
+fn diffuse(nx: f64, ny: f64) -> (f64, f64) {
+    let sin: f64 = gen_range(-1.0..1.0);
+    let cos = (1.0 - sin * sin).sqrt();
+    (nx * cos - ny * sin, nx * sin + ny * cos)
+}
+
 fn reflect(ix: f64, iy: f64, nx: f64, ny: f64) -> (f64, f64) {
     let dot2 = (ix * nx + iy * ny) * 2.0;
     (ix - dot2 * nx, iy - dot2 * ny)
@@ -83,7 +592,24 @@ fn refract(ix: f64, iy: f64, nx: f64, ny: f64, eta: f64) -> Option<(f64, f64)> {
     Some((eta * ix - a * nx, eta * iy - a * ny))
 }
 
-#[allow(dead_code)]
+// turn d randomly by a cosine power lobe around itself, staying on the side of n
+fn roughen(dx: f64, dy: f64, nx: f64, ny: f64, roughness: f64) -> (f64, f64) {
+    if roughness <= 0.0 {
+        return (dx, dy);
+    }
+    let roughness = roughness.min(1.0);
+    let exponent = 2.0 / (roughness * roughness) - 2.0;
+    let cos = gen_range(0.0f64..1.0).powf(1.0 / (exponent + 1.0));
+    let sin = (1.0 - cos * cos).sqrt() * if gen_bool(0.5) { 1.0 } else { -1.0 };
+    let (rx, ry) = (dx * cos - dy * sin, dx * sin + dy * cos);
+    let dot = rx * nx + ry * ny;
+    if dot < 0.0 {
+        (rx - 2.0 * dot * nx, ry - 2.0 * dot * ny)
+    } else {
+        (rx, ry)
+    }
+}
+
 fn fresnel(cosi: f64, cost: f64, etai: f64, etat: f64) -> f64 {
     let rs = (etat * cosi - etai * cost) / (etat * cosi + etai * cost);
     let rp = (etat * cost - etai * cosi) / (etat * cost + etai * cosi);
@@ -98,6 +624,60 @@ fn schlick(cosi: f64, cost: f64, etai: f64, etat: f64) -> f64 {
     r0 + (1.0 - r0) * aa * aa * a
 }
 
+// of a free film of the index and thickness in μm, at the wavelength in μm, for the cosine of
+// the angle to the normal. by the airy sum of the rays between its two surfaces, for the mean
+// of the two polarizations
+fn thin_film(eta: f64, thickness: f64, wavelength: f64, cosi: f64) -> f64 {
+    let cost = (1.0 - (1.0 - cosi * cosi) / (eta * eta)).max(0.0).sqrt();
+    let phase = (4.0 * PI * eta * thickness * cost / wavelength).cos();
+    let airy = |r: f64| {
+        let r2 = r * r;
+        // all of it at the grazing angles
+        if r2 >= 1.0 {
+            return 1.0;
+        }
+        2.0 * r2 * (1.0 - phase) / (1.0 + r2 * r2 - 2.0 * r2 * phase)
+    };
+    let rs = (cosi - eta * cost) / (cosi + eta * cost);
+    let rp = (eta * cosi - cost) / (eta * cosi + cost);
+    (airy(rs) + airy(rp)) * 0.5
+}
+
+// thin_film for every channel, or for the one of the wavelength
+fn thin_film_color(eta: f64, thickness: f64, wavelength: Option<f64>, cosi: f64) -> Color {
+    let at = |l: f64| thin_film(eta, thickness, l, cosi);
+    match wavelength {
+        Some(l) => Color::grey(at(l)),
+        None => Color {
+            r: at(WAVELENGTHS[0]),
+            g: at(WAVELENGTHS[1]),
+            b: at(WAVELENGTHS[2]),
+        },
+    }
+}
+
+// the direction of a random diffraction order of the grating of the period in μm, for the ray
+// along d hitting it against the normal n. by the grating equation, sin θm = sin θi + m λ / d,
+// the orders past the surface are left out. the light is shared evenly by the others
+fn diffract(
+    (dx, dy): (f64, f64),
+    (nx, ny): (f64, f64),
+    period: f64,
+    wavelength: Option<f64>,
+) -> (f64, f64) {
+    let len = (dx * dx + dy * dy).sqrt();
+    // along the surface
+    let (tx, ty) = (-ny, nx);
+    let sini = ((dx * tx + dy * ty) / len).clamp(-1.0, 1.0);
+    let step = wavelength.unwrap_or(WAVELENGTHS[1]) / period;
+    let lo = ((-1.0 - sini) / step).ceil() as i64;
+    let hi = ((1.0 - sini) / step).floor() as i64;
+    let m = gen_range(lo..=hi);
+    let sinm = (sini + m as f64 * step).clamp(-1.0, 1.0);
+    let cosm = (1.0 - sinm * sinm).sqrt();
+    (tx * sinm + nx * cosm, ty * sinm + ny * cosm)
+}
+
 fn beer_lambert(a: Color, d: f64) -> Color {
     Color {
         r: (-a.r * d).exp(),
@@ -106,86 +686,1599 @@ fn beer_lambert(a: Color, d: f64) -> Color {
     }
 }
 
-fn trace(scene: &Scene, ox: f64, oy: f64, dx: f64, dy: f64, depth: u32) -> Color {
-    if let Some(r) = scene.intersect((ox, oy), (dx, dy)) {
+// wavelengths of the red, green and blue channels, in μm
+const WAVELENGTHS: [f64; 3] = [0.65, 0.55, 0.45];
+
+// of the rays through a gradient index, and the most steps of one before it's dropped
+const GRIN_STEP: f64 = 0.002;
+const GRIN_STEPS: u32 = 4096;
+
+// cauchy's equation, relative to the index at the green wavelength
+fn disperse(eta: f64, dispersion: f64, wavelength: Option<f64>) -> f64 {
+    match wavelength {
+        Some(l) => eta + dispersion * (1.0 / (l * l) - 1.0 / (WAVELENGTHS[1] * WAVELENGTHS[1])),
+        None => eta,
+    }
+}
+
+// the state carried along a path
+#[derive(Clone)]
+struct Path {
+    // with a wavelength, only the channel of it is meaningful in the result
+    wavelength: Option<f64>,
+    // the entities the path is inside, the innermost last
+    inside: Vec<usize>,
+    // still from the pixel, without any vertex
+    eye: bool,
+    // the emission of the emitters reached through specular surfaces only is left to the light
+    // paths, after the first diffuse vertex of a bidirectional path
+    light_traced: bool,
+    // of the emitter a light path leaves, taken at its first surface
+    falloff: Option<Falloff>,
+}
+
+impl Path {
+    fn new(scene: &Scene, p: (f64, f64), wavelength: Option<f64>) -> Self {
+        Self {
+            wavelength,
+            inside: (0..scene.entities.len())
+                .filter(|&i| scene.entities[i].shape.is_inside(p))
+                .collect(),
+            eye: true,
+            light_traced: false,
+            falloff: None,
+        }
+    }
+
+    // from a vertex, scattering the light into any direction when diffuse
+    fn scatter(&self, scene: &Scene, diffuse: bool) -> Self {
+        Self {
+            eye: false,
+            light_traced: diffuse && self.eye && scene.bidirectional(),
+            ..self.clone()
+        }
+    }
+
+    // through the surface of the entity
+    fn cross(&self, entity: usize, entering: bool) -> Self {
+        let mut path = self.clone();
+        path.inside.retain(|&i| i != entity);
+        if entering {
+            path.inside.push(entity);
+        }
+        path
+    }
+}
+
+fn trace(scene: &Scene, ox: f64, oy: f64, dx: f64, dy: f64, depth: u32, path: &Path) -> Color {
+    let sight = if path.eye {
+        Sight::Camera
+    } else {
+        Sight::Other
+    };
+    if let Some(i) = inside_grin(scene, path) {
+        return trace_grin(scene, (ox, oy), (dx, dy), depth, path, sight, i);
+    }
+    let hit = scene.intersect((ox, oy), (dx, dy), f64::INFINITY, sight);
+    trace_hit(scene, (ox, oy), (dx, dy), depth, path, hit)
+}
+
+// the innermost entity of the path, when it has a gradient index
+fn inside_grin(scene: &Scene, path: &Path) -> Option<usize> {
+    let &i = path.inside.last()?;
+    scene.entities[i].material.grin().map(|_| i)
+}
+
+// trace through the gradient index of the entity, the ray bent towards the higher index step
+// by step, by dT/ds = (∇n - (∇n·T) T) / n, until a step reaches a surface
+fn trace_grin(
+    scene: &Scene,
+    mut p: (f64, f64),
+    (dx, dy): (f64, f64),
+    depth: u32,
+    path: &Path,
+    sight: Sight,
+    entity: usize,
+) -> Color {
+    let material = &scene.entities[entity].material;
+    let (eta, grin) = match material.grin() {
+        Some(grin) => grin,
+        None => return Color::black(),
+    };
+    let absorption = material.absorption();
+    let len = (dx * dx + dy * dy).sqrt();
+    let mut d = (dx / len, dy / len);
+    for step in 0..GRIN_STEPS {
+        if let Some(hit) = scene.intersect(p, d, GRIN_STEP, sight) {
+            // the absorption of the last step is by trace_hit
+            let travelled = step as f64 * GRIN_STEP;
+            return trace_hit(scene, p, d, depth, path, Some(hit))
+                * beer_lambert(absorption, travelled);
+        }
+        p = (p.0 + d.0 * GRIN_STEP, p.1 + d.1 * GRIN_STEP);
+        let n = eta + grin.at(p);
+        let (gx, gy) = grin.gradient(p);
+        let along = gx * d.0 + gy * d.1;
+        let tx = d.0 + GRIN_STEP * (gx - along * d.0) / n;
+        let ty = d.1 + GRIN_STEP * (gy - along * d.1) / n;
+        let l = (tx * tx + ty * ty).sqrt();
+        d = (tx / l, ty / l);
+    }
+    Color::black()
+}
+
+// trace, with the first intersection already found
+fn trace_hit(
+    scene: &Scene,
+    (ox, oy): (f64, f64),
+    (dx, dy): (f64, f64),
+    depth: u32,
+    path: &Path,
+    hit: Option<EntityIntersection>,
+) -> Color {
+    let len = (dx * dx + dy * dy).sqrt();
+    if let Some(medium) = &scene.medium {
+        let d = (dx / len, dy / len);
+        let t_max = hit.as_ref().map_or(f64::INFINITY, |r| r.t * len);
+        if let Some(t) = medium.sample((ox, oy), d, t_max) {
+            // scattered, or absorbed, by the medium before reaching the surface
+            if depth == 0 {
+                return Color::black();
+            }
+            let (x, y) = (ox + d.0 * t, oy + d.1 * t);
+            let a = gen_range(0.0..2.0 * PI);
+            let path = &path.scatter(scene, false);
+            let sum = trace(scene, x, y, a.cos(), a.sin(), depth - 1, path)
+                + direct_light(scene, (x, y), None) * (0.5 / PI);
+            return sum * medium.albedo();
+        }
+    }
+    if let Some(r) = hit {
+        profile::bounce();
         let sign = if r.normal.0 * dx + r.normal.1 * dy < 0.0 {
             1.0
         } else {
             -1.0
         };
-        let mut sum = r.emissive;
-        if depth > 0 && (r.reflectivity > 0.0 || r.eta > 0.0) {
-            let mut refl = r.reflectivity;
-            let (x, y) = r.point;
-            let nx = r.normal.0 * sign;
-            let ny = r.normal.1 * sign;
-            if r.eta > 0.0 {
-                let eta = if sign < 0.0 { r.eta } else { 1.0 / r.eta };
-                match refract(dx, dy, nx, ny, eta) {
-                    Some((rx, ry)) => {
-                        let cosi = -(dx * nx + dy * ny);
-                        let cost = -(rx * nx + ry * ny);
-                        refl = if sign < 0.0 {
-                            schlick(cosi, cost, r.eta, 1.0)
-                        } else {
-                            schlick(cosi, cost, 1.0, r.eta)
-                        };
-                        sum = sum + trace(scene, x, y, rx, ry, depth - 1) * (1.0 - refl)
+        let mut sum = shade(scene, r.material, &r, sign, (dx, dy), depth, path);
+        // absorbed by the innermost entity along the way
+        if let Some(&i) = path.inside.last() {
+            let absorption = scene.entities[i].material.absorption();
+            sum = sum * beer_lambert(absorption, r.t * len);
+        }
+        sum
+    } else {
+        scene
+            .background
+            .as_ref()
+            .map_or(Color::black(), |b| b.at((dx, dy)))
+    }
+}
+
+// the light leaving the surface of r against d
+fn shade(
+    scene: &Scene,
+    material: &Material,
+    r: &EntityIntersection,
+    sign: f64,
+    (dx, dy): (f64, f64),
+    depth: u32,
+    path: &Path,
+) -> Color {
+    let (x, y) = r.point;
+    let nx = r.normal.0 * sign;
+    let ny = r.normal.1 * sign;
+    // for the specular surfaces
+    let cleared;
+    let next = if path.eye {
+        cleared = path.scatter(scene, false);
+        &cleared
+    } else {
+        path
+    };
+    match material {
+        Material::Emitter(_) if path.light_traced && scene.entities[r.entity].aabb.is_some() => {
+            Color::black()
+        }
+        Material::Emitter(_) if !path.eye && !scene.entities[r.entity].visibility.light => {
+            Color::black()
+        }
+        Material::Emitter(emission) => {
+            let len = (dx * dx + dy * dy).sqrt();
+            // against the outward normal, whichever side the ray comes from
+            let cos = -(dx * r.normal.0 + dy * r.normal.1) / len;
+            let falloff = scene.entities[r.entity].falloff.factor(r.t * len);
+            emission.at(r.point) * (r.profile.factor(cos) * falloff)
+        }
+        Material::Absorber(_) => Color::black(),
+        Material::Mix(list) => list
+            .iter()
+            .map(|m| shade(scene, m, r, sign, (dx, dy), depth, path))
+            .sum(),
+        _ if depth == 0 => Color::black(),
+        Material::Mirror {
+            reflectivity,
+            roughness,
+        } => {
+            let (rx, ry) = reflect(dx, dy, nx, ny);
+            let (rx, ry) = roughen(rx, ry, nx, ny, *roughness);
+            trace(scene, x, y, rx, ry, depth - 1, next) * *reflectivity
+        }
+        Material::ThinFilm { eta, thickness } => {
+            let cosi = -(dx * nx + dy * ny) / (dx * dx + dy * dy).sqrt();
+            let refl = thin_film_color(*eta, *thickness, path.wavelength, cosi);
+            let through = Color {
+                r: 1.0 - refl.r,
+                g: 1.0 - refl.g,
+                b: 1.0 - refl.b,
+            };
+            let (rx, ry) = reflect(dx, dy, nx, ny);
+            trace(scene, x, y, rx, ry, depth - 1, next) * refl
+                + trace(scene, x, y, dx, dy, depth - 1, next) * through
+        }
+        Material::Grating {
+            period,
+            reflectivity,
+        } => {
+            let (rx, ry) = diffract((dx, dy), (nx, ny), *period, path.wavelength);
+            trace(scene, x, y, rx, ry, depth - 1, next) * *reflectivity
+        }
+        Material::Diffuse(albedo) => {
+            // only the outside of a surface scatters
+            if sign < 0.0 {
+                return Color::black();
+            }
+            if let Some(photons) = &scene.photons {
+                // the irradiance is the flux per length, and the brdf is albedo / 2
+                let mut flux = Color::black();
+                photons.gather(r.point, |photon| {
+                    if photon.normal.0 * nx + photon.normal.1 * ny > 0.0 {
+                        flux = flux + photon.flux;
                     }
-                    None => refl = 1.0,
+                });
+                return (flux * (0.25 / photons.radius)
+                    + direct_light(scene, r.point, Some((nx, ny))))
+                    * *albedo;
+            }
+            let path = &path.scatter(scene, true);
+            let (sx, sy) = diffuse(nx, ny);
+            if !scene.nee || scene.emitters.is_empty() {
+                return (trace(scene, x, y, sx, sy, depth - 1, path)
+                    + direct_light(scene, r.point, Some((nx, ny))))
+                    * *albedo;
+            }
+            // half of the directions towards the emitters, weighted by the density of both
+            let cones = cones(scene, r.point);
+            let (sx, sy) = if gen_bool(0.5) {
+                (sx, sy)
+            } else {
+                let a = sample_cones(&cones);
+                (a.cos(), a.sin())
+            };
+            let cos = sx * nx + sy * ny;
+            let indirect = if cos > 0.0 {
+                let pdf = 0.5 * cos * 0.5 + 0.5 * cones_pdf(&cones, sy.atan2(sx));
+                trace(scene, x, y, sx, sy, depth - 1, path) * (cos * 0.5 / pdf)
+            } else {
+                Color::black()
+            };
+            (indirect + direct_light(scene, r.point, Some((nx, ny)))) * *albedo
+        }
+        Material::Dielectric {
+            eta,
+            dispersion,
+            roughness,
+            grin,
+            ..
+        } => {
+            let mut sum = Color::black();
+            let mut refl = 1.0;
+            let eta = *eta + grin.map_or(0.0, |g| g.at(r.point));
+            let eta = disperse(eta, *dispersion, path.wavelength);
+            let ratio = if sign < 0.0 { eta } else { 1.0 / eta };
+            if let Some((rx, ry)) = refract(dx, dy, nx, ny, ratio) {
+                let cosi = -(dx * nx + dy * ny);
+                let cost = -(rx * nx + ry * ny);
+                refl = if sign < 0.0 {
+                    scene.fresnel.reflectance(cosi, cost, eta, 1.0)
+                } else {
+                    scene.fresnel.reflectance(cosi, cost, 1.0, eta)
+                };
+                let (rx, ry) = roughen(rx, ry, -nx, -ny, *roughness);
+                let through = next.cross(r.entity, sign > 0.0);
+                sum = trace(scene, x, y, rx, ry, depth - 1, &through) * (1.0 - refl);
+            } else {
+                profile::tir();
+                if scene.mode == Mode::DebugTir {
+                    return Color {
+                        r: 1.0,
+                        g: 0.0,
+                        b: 1.0,
+                    };
                 }
             }
             if refl > 0.0 {
                 let (rx, ry) = reflect(dx, dy, nx, ny);
-                sum = sum + trace(scene, x, y, rx, ry, depth - 1) * refl;
+                let (rx, ry) = roughen(rx, ry, nx, ny, *roughness);
+                sum = sum + trace(scene, x, y, rx, ry, depth - 1, next) * refl;
             }
+            sum
         }
-        if sign < 0.0 {
-            sum = sum * beer_lambert(r.absorption, distance((ox, oy), r.point));
+    }
+}
+
+// one path for every channel if they can split up
+fn paths(scene: &Scene, point: (f64, f64)) -> Vec<Path> {
+    if scene.dispersive() {
+        WAVELENGTHS
+            .iter()
+            .map(|&l| Path::new(scene, point, Some(l)))
+            .collect()
+    } else {
+        vec![Path::new(scene, point, None)]
+    }
+}
+
+// the light coming to the point from the angle a. with next event estimation, another
+// direction is sampled towards the emitters, and the two are weighted by the balance heuristic
+// a sample for every angle
+fn samples(
+    scene: &Scene,
+    max_depth: u32,
+    point: (f64, f64),
+    paths: &[Path],
+    angles: &[f64],
+) -> Vec<Color> {
+    let mut result = nee(scene, max_depth, point, paths, angles);
+    if scene.bidirectional() {
+        for c in result.iter_mut() {
+            *c = *c + light_path(scene, max_depth, point);
         }
-        sum
+    }
+    result
+}
+
+fn nee(
+    scene: &Scene,
+    max_depth: u32,
+    point: (f64, f64),
+    paths: &[Path],
+    angles: &[f64],
+) -> Vec<Color> {
+    if !scene.nee || scene.emitters.is_empty() {
+        return radiances(scene, max_depth, point, paths, angles);
+    }
+    let cones = cones(scene, point);
+    // every angle paired with one towards the emitters
+    let mut all = angles.to_vec();
+    all.extend(angles.iter().map(|_| sample_cones(&cones)));
+    let colors = radiances(scene, max_depth, point, paths, &all);
+    // the radiances are over the uniform density, the weight over the density of the angle is
+    // the same for both ways of sampling it
+    let weight = |i: usize| colors[i] * balance(&cones, all[i]).0;
+    (0..angles.len())
+        .map(|i| weight(i) + weight(i + angles.len()))
+        .collect()
+}
+
+// the light coming to the point from the angles, their first intersections found 4 at a time
+fn radiances(
+    scene: &Scene,
+    max_depth: u32,
+    point: (f64, f64),
+    paths: &[Path],
+    angles: &[f64],
+) -> Vec<Color> {
+    // bent by the gradient index the point is in, traced one by one
+    if let Some(i) = inside_grin(scene, &paths[0]) {
+        if !scene.in_layer(Some(i)) {
+            return vec![Color::black(); angles.len()];
+        }
+        let one = |d: (f64, f64), path: &Path| {
+            profile::paths(1);
+            trace(scene, point.0, point.1, d.0, d.1, max_depth, path)
+        };
+        return angles
+            .iter()
+            .map(|a| {
+                let d = (a.cos(), a.sin());
+                match paths {
+                    [r, g, b] => Color {
+                        r: one(d, r).r,
+                        g: one(d, g).g,
+                        b: one(d, b).b,
+                    },
+                    _ => one(d, &paths[0]),
+                }
+            })
+            .collect();
+    }
+    let mut result = Vec::with_capacity(angles.len());
+    for chunk in angles.chunks(4) {
+        // the missing lanes repeat the first angle
+        let d: [(f64, f64); 4] = std::array::from_fn(|i| {
+            let a = chunk.get(i).unwrap_or(&chunk[0]);
+            (a.cos(), a.sin())
+        });
+        let sight = if paths[0].eye {
+            Sight::Camera
+        } else {
+            Sight::Other
+        };
+        let hits = scene.intersect4(point, d, f64::INFINITY, sight);
+        profile::paths((chunk.len() * paths.len()) as u64);
+        for (&d, hit) in d.iter().zip(hits).take(chunk.len()) {
+            if !scene.in_layer(hit.as_ref().map(|r| r.entity)) {
+                result.push(Color::black());
+                continue;
+            }
+            result.push(match paths {
+                [r, g, b] => Color {
+                    r: trace_hit(scene, point, d, max_depth, r, hit.clone()).r,
+                    g: trace_hit(scene, point, d, max_depth, g, hit.clone()).g,
+                    b: trace_hit(scene, point, d, max_depth, b, hit).b,
+                },
+                _ => trace_hit(scene, point, d, max_depth, &paths[0], hit),
+            });
+        }
+    }
+    result
+}
+
+// a random point and direction of the light leaving the emitters, by a random line across the
+// box of a random emitter. where it leaves the emitter is distributed by the emitted flux,
+// cos dA dω, by crofton's formula. the flux is estimated for every crossing, in one channel if
+// they can split up
+fn emit(scene: &Scene) -> Vec<(Ray, Color, Path)> {
+    let n = scene.emitters.len();
+    let e = &scene.entities[scene.emitters[gen_range(0..n)]];
+    let b = e.aabb.unwrap();
+    let a = gen_range(0.0..2.0 * PI);
+    let d = (a.cos(), a.sin());
+    let (hx, hy) = ((b.max.0 - b.min.0) * 0.5, (b.max.1 - b.min.1) * 0.5);
+    let (cx, cy) = (b.min.0 + hx, b.min.1 + hy);
+    let width = 2.0 * (hx * d.1.abs() + hy * d.0.abs());
+    let s = gen_range(-0.5..0.5) * width;
+    let back = (hx * hx + hy * hy).sqrt() + 1.0;
+    let o = (cx - d.1 * s - d.0 * back, cy + d.0 * s - d.1 * back);
+    let (channel, mask) = if scene.dispersive() {
+        let c = gen_range(0..3);
+        let mut mask = [0.0; 3];
+        mask[c] = 3.0;
+        let [r, g, b] = mask;
+        (Some(c), Color { r, g, b })
     } else {
-        Color::black()
+        (None, Color::grey(1.0))
+    };
+    e.shape
+        .intersect(o, d)
+        .into_iter()
+        .filter_map(|z| {
+            let cos = z.normal.0 * d.0 + z.normal.1 * d.1;
+            if cos <= 0.0 {
+                return None;
+            }
+            let flux = e.material.emission(z.point)
+                * mask
+                * (e.profile.factor(cos) * 2.0 * PI * width * n as f64);
+            if flux.is_black() {
+                return None;
+            }
+            let start = (z.point.0 + d.0 * SDF_BIAS, z.point.1 + d.1 * SDF_BIAS);
+            let path = Path {
+                falloff: Some(e.falloff),
+                ..Path::new(scene, start, channel.map(|c| WAVELENGTHS[c]))
+            };
+            Some(((z.point, d), flux, path))
+        })
+        .collect()
+}
+
+// the light of a path from a random emitter, through specular surfaces to a diffuse one,
+// connected to the point
+fn light_path(scene: &Scene, max_depth: u32, point: (f64, f64)) -> Color {
+    let mut sum = Color::black();
+    for (ray, flux, path) in emit(scene) {
+        walk(
+            scene,
+            max_depth,
+            ray,
+            flux,
+            path,
+            false,
+            |r, (nx, ny), albedo, flux, path| {
+                let (tx, ty) = (point.0 - r.point.0, point.1 - r.point.1);
+                let len = (tx * tx + ty * ty).sqrt();
+                let cos = (tx * nx + ty * ny) / len;
+                if cos <= 0.0 || !scene.in_layer(Some(r.entity)) {
+                    return None;
+                }
+                // 1 - SDF_BIAS, not to hit the surface itself
+                if scene
+                    .intersect(point, (-tx, -ty), 1.0 - SDF_BIAS, Sight::Shadow)
+                    .is_some()
+                {
+                    return None;
+                }
+                let transmittance = scene
+                    .medium
+                    .as_ref()
+                    .map_or(1.0, |m| m.transmittance(point, r.point));
+                let absorption = path
+                    .inside
+                    .last()
+                    .map_or(Color::black(), |&i| scene.entities[i].material.absorption());
+                // the lambertian brdf is albedo / 2, and the angle at the point is cos / len of the
+                // length on the surface, averaged over the circle
+                sum = sum
+                    + *flux
+                        * albedo
+                        * beer_lambert(absorption, len)
+                        * (0.5 * cos / len * transmittance * 0.5 / PI);
+                None
+            },
+        );
+    }
+    sum
+}
+
+// from p along the unit direction d
+type Ray = ((f64, f64), (f64, f64));
+
+// follow the flux along the ray, choosing between reflection and refraction, and scattering or
+// stopping in the medium. at a diffuse surface hit from outside, diffuse is called with the
+// intersection, the normal towards the outside, and the albedo, it returns the next direction,
+// or None to stop
+fn walk<F>(
+    scene: &Scene,
+    max_depth: u32,
+    (mut p, mut d): Ray,
+    mut flux: Color,
+    mut path: Path,
+    scatter: bool,
+    mut diffuse: F,
+) where
+    F: FnMut(&EntityIntersection, (f64, f64), Color, &mut Color, &Path) -> Option<(f64, f64)>,
+{
+    for _ in 0..max_depth {
+        let r = match scene.intersect(p, d, f64::INFINITY, Sight::Other) {
+            Some(r) => r,
+            None => return,
+        };
+        // along the unit direction from the emitter
+        if let Some(falloff) = path.falloff.take() {
+            flux = flux * falloff.factor(r.t);
+        }
+        if let Some(medium) = &scene.medium {
+            if let Some(t) = medium.sample(p, d, r.t) {
+                if !scatter {
+                    return;
+                }
+                flux = flux * medium.albedo();
+                p = (p.0 + d.0 * t, p.1 + d.1 * t);
+                let a = gen_range(0.0..2.0 * PI);
+                d = (a.cos(), a.sin());
+                continue;
+            }
+        }
+        if let Some(&i) = path.inside.last() {
+            flux = flux * beer_lambert(scene.entities[i].material.absorption(), r.t);
+        }
+        let sign = if r.normal.0 * d.0 + r.normal.1 * d.1 < 0.0 {
+            1.0
+        } else {
+            -1.0
+        };
+        let (nx, ny) = (r.normal.0 * sign, r.normal.1 * sign);
+        // one of the mixed materials
+        let mut material = r.material;
+        while let Material::Mix(list) = material {
+            if list.is_empty() {
+                return;
+            }
+            flux = flux * list.len() as f64;
+            material = &list[gen_range(0..list.len())];
+        }
+        d = match material {
+            Material::Diffuse(albedo) if sign > 0.0 => {
+                match diffuse(&r, (nx, ny), *albedo, &mut flux, &path) {
+                    Some(d) => d,
+                    None => return,
+                }
+            }
+            Material::Mirror {
+                reflectivity,
+                roughness,
+            } => {
+                let (rx, ry) = reflect(d.0, d.1, nx, ny);
+                flux = flux * *reflectivity;
+                roughen(rx, ry, nx, ny, *roughness)
+            }
+            // reflected or through by the chance of the mean of the channels
+            Material::ThinFilm { eta, thickness } => {
+                let cosi = -(d.0 * nx + d.1 * ny);
+                let refl = thin_film_color(*eta, *thickness, path.wavelength, cosi);
+                let chance = ((refl.r + refl.g + refl.b) / 3.0).clamp(0.01, 0.99);
+                if gen_range(0.0..1.0) < chance {
+                    flux = flux * refl * (1.0 / chance);
+                    reflect(d.0, d.1, nx, ny)
+                } else {
+                    let through = Color {
+                        r: 1.0 - refl.r,
+                        g: 1.0 - refl.g,
+                        b: 1.0 - refl.b,
+                    };
+                    flux = flux * through * (1.0 / (1.0 - chance));
+                    d
+                }
+            }
+            Material::Grating {
+                period,
+                reflectivity,
+            } => {
+                flux = flux * *reflectivity;
+                diffract(d, (nx, ny), *period, path.wavelength)
+            }
+            Material::Dielectric {
+                eta,
+                dispersion,
+                roughness,
+                grin,
+                ..
+            } => {
+                let eta = *eta + grin.map_or(0.0, |g| g.at(r.point));
+                let eta = disperse(eta, *dispersion, path.wavelength);
+                let ratio = if sign < 0.0 { eta } else { 1.0 / eta };
+                let refracted = refract(d.0, d.1, nx, ny, ratio).filter(|&(rx, ry)| {
+                    let cosi = -(d.0 * nx + d.1 * ny);
+                    let cost = -(rx * nx + ry * ny);
+                    let refl = if sign < 0.0 {
+                        scene.fresnel.reflectance(cosi, cost, eta, 1.0)
+                    } else {
+                        scene.fresnel.reflectance(cosi, cost, 1.0, eta)
+                    };
+                    gen_range(0.0..1.0) >= refl
+                });
+                match refracted {
+                    Some((rx, ry)) => {
+                        path = path.cross(r.entity, sign > 0.0);
+                        roughen(rx, ry, -nx, -ny, *roughness)
+                    }
+                    None => {
+                        let (rx, ry) = reflect(d.0, d.1, nx, ny);
+                        roughen(rx, ry, nx, ny, *roughness)
+                    }
+                }
+            }
+            _ => return,
+        };
+        let len = (d.0 * d.0 + d.1 * d.1).sqrt();
+        d = (d.0 / len, d.1 / len);
+        p = r.point;
     }
 }
 
-fn render_point(scene: &Scene, stratification: u32, max_depth: u32, point: (f64, f64)) -> Color {
-    let sum: Color = (0..stratification)
-        .map(|i| {
-            2.0 * PI * (i as f64 + rand::thread_rng().gen_range(0.0..1.0)) / stratification as f64
+// emit the photons, and store where they hit the diffuse surfaces from outside
+pub fn photon_map(scene: &Scene, count: u32, max_depth: u32, radius: f64) -> PhotonMap {
+    let photons: Vec<Photon> = (0..count)
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            reseed(scene.seed, &[i as u64]);
+            let mut photons: Vec<Photon> = Vec::new();
+            if scene.emitters.is_empty() {
+                return photons;
+            }
+            for (ray, flux, path) in emit(scene) {
+                let flux = flux * (1.0 / count as f64);
+                walk(
+                    scene,
+                    max_depth,
+                    ray,
+                    flux,
+                    path,
+                    true,
+                    |r, (nx, ny), albedo, flux, _| {
+                        photons.push(Photon {
+                            point: r.point,
+                            normal: (nx, ny),
+                            flux: *flux,
+                        });
+                        // lambertian, the cosine in the density cancels the one of the brdf
+                        *flux = *flux * albedo;
+                        Some(diffuse(nx, ny))
+                    },
+                );
+            }
+            photons
         })
-        .collect::<Vec<f64>>()
-        .par_iter()
-        .map(|a| trace(scene, point.0, point.1, a.cos(), a.sin(), max_depth))
-        .sum();
-    sum * (1.0 / stratification as f64)
+        .collect();
+    PhotonMap::new(photons, radius)
+}
+
+// 1 / φ
+const GOLDEN: f64 = 0.618_033_988_749_895;
+
+// how the directions from a pixel are spread over the circle
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Sampler {
+    // a random angle in each of the n equal sectors
+    #[default]
+    #[serde(rename = "stratified")]
+    Stratified,
+    // the van der Corput sequence in base 2, the same as the first dimension of halton and sobol
+    #[serde(rename = "halton")]
+    Halton,
+    // the angle steps by the golden ratio of the circle
+    #[serde(rename = "golden")]
+    Golden,
+}
+
+impl Sampler {
+    // the angle of the k-th of n samples, the sequences are rotated by the offset of the pixel,
+    // from 0 to 1, so that the pixels don't share their directions
+    fn angle(&self, k: u32, n: u32, offset: f64) -> f64 {
+        let u = match self {
+            Sampler::Stratified => ((k % n) as f64 + gen_range(0.0..1.0)) / n as f64,
+            Sampler::Halton => (k.reverse_bits() as f64 / (1u64 << 32) as f64 + offset).fract(),
+            Sampler::Golden => (k as f64 * GOLDEN + offset).fract(),
+        };
+        2.0 * PI * u
+    }
 }
 
-pub fn render(
+// the color of the point, with the stats of its samples
+fn render_point(
     scene: &Scene,
-    (width, height): (u32, u32),
     stratification: u32,
     max_depth: u32,
-) -> RgbImage {
-    let mut pb = ProgressBar::new(width as u64 * height as u64);
-    pb.format("[=>-]");
-    let begin = time::Instant::now();
-    let mut img = ImageBuffer::from_pixel(width, height, Rgb([0u8, 0u8, 0u8]));
-    let min_edge = min(width, height);
-    for x in 0..width {
-        for y in 0..height {
-            let xx = x as f64 / min_edge as f64;
-            let yy = y as f64 / min_edge as f64;
-            let color = render_point(&scene, stratification, max_depth, (xx, yy));
-            let r = min((color.r * 255.0) as u32, 255) as u8;
-            let g = min((color.g * 255.0) as u32, 255) as u8;
-            let b = min((color.b * 255.0) as u32, 255) as u8;
-            img.put_pixel(x, y, Rgb([r, g, b]));
-            pb.inc();
-        }
-    }
-    pb.finish();
-    let end = time::Instant::now();
-    println!("{:?}", end - begin);
-    img
+    point: (f64, f64),
+) -> (Color, Stats) {
+    let paths = paths(scene, point);
+    let offset = gen_range(0.0..1.0);
+    let angles: Vec<f64> = (0..stratification)
+        .map(|i| scene.sampler.angle(i, stratification, offset))
+        .collect();
+    let mut stats = Stats::default();
+    let sum: Color = samples(scene, max_depth, point, &paths, &angles)
+        .into_iter()
+        .inspect(|&color| stats.add(color))
+        .sum();
+    (
+        sum * (1.0 / stratification as f64) + lights(scene, point),
+        stats,
+    )
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Adaptive {
+    pub min_samples: u32,
+    pub max_samples: u32,
+    // of the mean luminance, the pixel takes more samples while it's above
+    pub variance_threshold: f64,
+}
+
+impl Adaptive {
+    // whether a pixel stops after count samples, of the luminances with the sum lum and the sum
+    // of the squares lum2, before the next batch
+    fn stops(&self, count: u32, batch: u32, lum: f64, lum2: f64) -> bool {
+        let n = count as f64;
+        let variance = (lum2 - lum * lum / n) / (n - 1.0);
+        count + batch > self.max_samples || variance / n <= self.variance_threshold
+    }
+}
+
+// batches of min_samples samples, until the estimated variance of the mean is below
+// the threshold, or max_samples are taken
+fn render_point_adaptive(
+    scene: &Scene,
+    adaptive: Adaptive,
+    max_depth: u32,
+    point: (f64, f64),
+) -> (Color, Stats) {
+    let paths = paths(scene, point);
+    let batch = adaptive.min_samples.max(2);
+    let mut sum = Color::black();
+    let (mut lum, mut lum2) = (0.0, 0.0);
+    let mut count = 0;
+    let offset = gen_range(0.0..1.0);
+    loop {
+        let angles: Vec<f64> = (0..batch)
+            .map(|i| scene.sampler.angle(count + i, batch, offset))
+            .collect();
+        for color in samples(scene, max_depth, point, &paths, &angles) {
+            sum = sum + color;
+            lum += color.luminance();
+            lum2 += color.luminance() * color.luminance();
+        }
+        count += batch;
+        if adaptive.stops(count, batch, lum, lum2) {
+            break;
+        }
+    }
+    let stats = Stats {
+        count,
+        sum: lum,
+        sum2: lum2,
+    };
+    (sum * (1.0 / count as f64) + lights(scene, point), stats)
+}
+
+// the light of the lights at the point itself, in DEFAULT_LAYER
+fn lights(scene: &Scene, point: (f64, f64)) -> Color {
+    match scene.in_layer(None) {
+        true => direct_light(scene, point, None),
+        false => Color::black(),
+    }
+}
+
+// anti-aliasing, by positions spread over the pixel instead of its top left corner
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Aa {
+    // rounded to a square grid, the samples of the pixel are split among them
+    pub samples: u32,
+}
+
+impl Default for Aa {
+    fn default() -> Self {
+        Self { samples: 1 }
+    }
+}
+
+impl Aa {
+    fn grid(&self) -> u32 {
+        ((self.samples as f64).sqrt().round() as u32).max(1)
+    }
+
+    fn positions(&self) -> u32 {
+        self.grid() * self.grid()
+    }
+
+    // the k-th position in the pixel at (x, y), at a random place in its cell of the grid,
+    // in the world
+    fn position(&self, k: u32, (x, y): (u32, u32), settings: &RenderSettings) -> (f64, f64) {
+        let n = self.grid();
+        let (u, v) = if n == 1 {
+            (0.0, 0.0)
+        } else {
+            (
+                ((k % n) as f64 + gen_range(0.0..1.0)) / n as f64,
+                ((k / n % n) as f64 + gen_range(0.0..1.0)) / n as f64,
+            )
+        };
+        settings.to_world((x as f64 + u, y as f64 + v))
+    }
+}
+
+// where the image is in the world: the middle of the image at center, zoom times as large as
+// without it, turned by rotation in degrees, clockwise on the image
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Camera {
+    pub center: (f64, f64),
+    #[serde(default = "default_zoom")]
+    pub zoom: f64,
+    #[serde(default)]
+    pub rotation: f64,
+}
+
+fn default_zoom() -> f64 {
+    1.0
+}
+
+// how the world is put in an image of another aspect than it
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Fit {
+    // all of the world is in the image, with bands beside it
+    #[default]
+    #[serde(rename = "contain")]
+    Contain,
+    // the world fills the image, cut at its longer sides
+    #[serde(rename = "cover")]
+    Cover,
+    // the world fills the image, scaled more along one of the axes
+    #[serde(rename = "stretch")]
+    Stretch,
+}
+
+// the rectangle of the world from 0 that is in the image, whatever its size. without one of the
+// edges, the other is fit and the image is as long as it is on that axis
+#[derive(Clone, Copy)]
+pub struct World {
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+    pub fit: Fit,
+}
+
+// of render and render_progressive, the size of the image and how it's sampled
+#[derive(Clone, Copy)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    // the samples of every pixel
+    pub stratification: u32,
+    pub max_depth: u32,
+    // of the square tiles rendered in parallel, in pixels
+    pub tile: u32,
+    // more samples for the noisy pixels, instead of the stratification for all
+    pub adaptive: Option<Adaptive>,
+    // without one, the world is from 0 on, the shorter edge of the image 1 long
+    pub camera: Option<Camera>,
+    // instead of the shorter edge, the same world in images of every size
+    pub world: Option<World>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 512,
+            height: 512,
+            stratification: 512,
+            max_depth: 3,
+            tile: 32,
+            adaptive: None,
+            camera: None,
+            world: None,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl RenderSettings {
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn stratification(mut self, stratification: u32) -> Self {
+        self.stratification = stratification;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn tile(mut self, tile: u32) -> Self {
+        self.tile = tile;
+        self
+    }
+
+    pub fn adaptive(mut self, adaptive: Adaptive) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    pub fn camera(mut self, camera: Camera) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    pub fn world(mut self, world: World) -> Self {
+        self.world = Some(world);
+        self
+    }
+
+    // the pixels of a length of 1 in the world along x and y, and the point of the world in the
+    // middle of the image
+    fn frame(&self) -> ((f64, f64), (f64, f64)) {
+        let (width, height) = (self.width as f64, self.height as f64);
+        let min_edge = width.min(height);
+        let (sx, sy) = match self.world {
+            Some(World {
+                width: Some(w),
+                height: Some(h),
+                fit,
+            }) => {
+                let (sx, sy) = (width / w, height / h);
+                match fit {
+                    Fit::Contain => (sx.min(sy), sx.min(sy)),
+                    Fit::Cover => (sx.max(sy), sx.max(sy)),
+                    Fit::Stretch => (sx, sy),
+                }
+            }
+            Some(World { width: Some(w), .. }) => (width / w, width / w),
+            Some(World {
+                height: Some(h), ..
+            }) => (height / h, height / h),
+            _ => (min_edge, min_edge),
+        };
+        let world = self.world.unwrap_or(World {
+            width: None,
+            height: None,
+            fit: Fit::Contain,
+        });
+        let center = (
+            world.width.unwrap_or(width / sx) / 2.0,
+            world.height.unwrap_or(height / sy) / 2.0,
+        );
+        match self.camera {
+            Some(camera) => ((sx * camera.zoom, sy * camera.zoom), camera.center),
+            None => ((sx, sy), center),
+        }
+    }
+
+    // the point of the world at (x, y) in the image, in pixels from its top left corner
+    pub fn to_world(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        if let (None, None) = (self.camera, self.world) {
+            let min_edge = min(self.width, self.height) as f64;
+            return (x / min_edge, y / min_edge);
+        }
+        let ((sx, sy), (cx, cy)) = self.frame();
+        let dx = (x - self.width as f64 / 2.0) / sx;
+        let dy = (y - self.height as f64 / 2.0) / sy;
+        let (sin, cos) = self.rotation().sin_cos();
+        (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos)
+    }
+
+    // the inverse of to_world
+    pub fn to_pixel(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        if let (None, None) = (self.camera, self.world) {
+            let min_edge = min(self.width, self.height) as f64;
+            return (x * min_edge, y * min_edge);
+        }
+        let ((sx, sy), (cx, cy)) = self.frame();
+        let (dx, dy) = (x - cx, y - cy);
+        let (sin, cos) = self.rotation().sin_cos();
+        (
+            (dx * cos + dy * sin) * sx + self.width as f64 / 2.0,
+            (dy * cos - dx * sin) * sy + self.height as f64 / 2.0,
+        )
+    }
+
+    // of the camera, in radians
+    fn rotation(&self) -> f64 {
+        self.camera.map_or(0.0, |c| c.rotation.to_radians())
+    }
+
+    // the length of a pixel in the world, the longer one when it is stretched
+    pub fn pixel_size(&self) -> f64 {
+        let ((sx, sy), _) = self.frame();
+        1.0 / sx.min(sy)
+    }
+}
+
+// the film while it is rendered, for a window showing it as it fills, and the flag to stop
+// the render early, both shared with the thread of the window
+pub struct Live {
+    pub film: Mutex<Film>,
+    stopped: AtomicBool,
+}
+
+#[allow(dead_code)]
+impl Live {
+    pub fn new() -> Self {
+        Self {
+            film: Mutex::new(Film::new(0, 0)),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    // the tiles or passes not started yet are left out
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Live {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// renders scenes made without a config
+pub struct Renderer {
+    pub settings: RenderSettings,
+    // emitted for the photon map, and the radius they are gathered in
+    pub photons: u32,
+    pub photon_radius: f64,
+    // a progress bar on stdout, with the cli feature
+    pub progress: bool,
+}
+
+#[allow(dead_code)]
+impl Renderer {
+    pub fn new(settings: RenderSettings) -> Self {
+        Self {
+            settings,
+            photons: 100_000,
+            photon_radius: 0.01,
+            progress: false,
+        }
+    }
+
+    pub fn photons(mut self, photons: u32, radius: f64) -> Self {
+        self.photons = photons;
+        self.photon_radius = radius;
+        self
+    }
+
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn render(&self, scene: &mut Scene) -> Film {
+        self.build_photons(scene);
+        let live = Live::new();
+        #[cfg(feature = "pbr")]
+        if self.progress {
+            return render(scene, &self.settings, &live, pbr::ProgressBar::new(0));
+        }
+        render(scene, &self.settings, &live, ())
+    }
+
+    // like render, see render_with_progress
+    pub fn render_with_progress<F: FnMut(Status) + Send>(
+        &self,
+        scene: &mut Scene,
+        progress: F,
+    ) -> Film {
+        self.build_photons(scene);
+        render_with_progress(scene, &self.settings, progress)
+    }
+
+    // with the photon map integrator, the map is built by the first render and kept
+    fn build_photons(&self, scene: &mut Scene) {
+        if scene.needs_photons() {
+            scene.photons = Some(photon_map(
+                scene,
+                self.photons,
+                self.settings.max_depth,
+                self.photon_radius,
+            ));
+        }
+    }
+}
+
+// the pixels of the image, tile by tile, in row order within a tile
+fn tiles((width, height): (u32, u32), tile: u32) -> Vec<Vec<(u32, u32)>> {
+    let tile = tile.max(1);
+    (0..height)
+        .step_by(tile as usize)
+        .flat_map(|y0| {
+            (0..width).step_by(tile as usize).map(move |x0| {
+                let (x1, y1) = (min(x0 + tile, width), min(y0 + tile, height));
+                (y0..y1)
+                    .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+// the image is split into tiles of tile × tile pixels, rendered in parallel.
+// with adaptive, the stratification is not used. pb is replaced by the time taken at the end.
+// the tiles are put into the film of live as they are done, the ones after a stop are black
+pub fn render<P: Progress + Send>(
+    scene: &Scene,
+    settings: &RenderSettings,
+    live: &Live,
+    mut pb: P,
+) -> Film {
+    let RenderSettings {
+        width,
+        height,
+        stratification,
+        max_depth,
+        tile,
+        adaptive,
+        ..
+    } = *settings;
+    if scene.mode == Mode::Layout {
+        return layout(scene, settings, live, pb);
+    }
+    pb.start(width as u64 * height as u64);
+    let pb = Mutex::new(pb);
+    let begin = Instant::now();
+    *live.film.lock().unwrap() = Film::new(width, height);
+    tiles((width, height), tile)
+        .into_par_iter()
+        .for_each(|pixels| {
+            if live.is_stopped() {
+                return;
+            }
+            let colors: Vec<(u32, u32, Color, Stats)> = pixels
+                .iter()
+                .map(|&(x, y)| {
+                    reseed(scene.seed, &[x as u64, y as u64]);
+                    let m = scene.aa.positions();
+                    let (sum, stats) = (0..m)
+                        .map(|k| {
+                            let point = scene.aa.position(k, (x, y), settings);
+                            match adaptive {
+                                Some(adaptive) => {
+                                    let adaptive = Adaptive {
+                                        min_samples: adaptive.min_samples / m,
+                                        max_samples: adaptive.max_samples / m,
+                                        ..adaptive
+                                    };
+                                    render_point_adaptive(scene, adaptive, max_depth, point)
+                                }
+                                None => render_point(
+                                    scene,
+                                    (stratification / m).max(1),
+                                    max_depth,
+                                    point,
+                                ),
+                            }
+                        })
+                        .fold(
+                            (Color::black(), Stats::default()),
+                            |(c, s), (color, stats)| (c + color, s + stats),
+                        );
+                    (x, y, sum * (1.0 / m as f64), stats)
+                })
+                .collect();
+            let mut film = live.film.lock().unwrap();
+            for (x, y, color, stats) in colors {
+                film.put(x, y, color);
+                film.put_stats(x, y, stats);
+            }
+            pb.lock().unwrap().add(pixels.len() as u64);
+        });
+    let end = Instant::now();
+    pb.into_inner()
+        .unwrap()
+        .finish_print(&format!("{:?}", end - begin));
+    let film = live.film.lock().unwrap().clone();
+    film
+}
+
+// renders like render, with the status of the pixels given to progress at the start and after
+// every tile, on the thread of the tile. a cancel of the status leaves the tiles after it black
+pub fn render_with_progress<F: FnMut(Status) + Send>(
+    scene: &Scene,
+    settings: &RenderSettings,
+    progress: F,
+) -> Film {
+    let live = Live::new();
+    render(scene, settings, &live, Callback::new(&live, progress))
+}
+
+// every pixel in the flat color of the last entity it is in, of Material::flat, black outside
+// and for the entities of the other layers
+fn layout<P: Progress>(scene: &Scene, settings: &RenderSettings, live: &Live, mut pb: P) -> Film {
+    let (width, height) = (settings.width, settings.height);
+    pb.start(width as u64 * height as u64);
+    let begin = Instant::now();
+    let colors: Vec<(u32, u32, Color)> = (0..height)
+        .into_par_iter()
+        .flat_map_iter(|y| {
+            (0..width).map(move |x| {
+                let p = settings.to_world((x as f64, y as f64));
+                let inside = (0..scene.entities.len())
+                    .rev()
+                    .find(|&i| scene.entities[i].shape.is_inside(p));
+                let color = match inside {
+                    Some(i) if scene.in_layer(Some(i)) => scene.entities[i].material.flat(p),
+                    _ => Color::black(),
+                };
+                (x, y, color)
+            })
+        })
+        .collect();
+    let mut film = Film::new(width, height);
+    for (x, y, color) in colors {
+        film.put(x, y, color);
+    }
+    *live.film.lock().unwrap() = film.clone();
+    pb.add(width as u64 * height as u64);
+    pb.finish_print(&format!("{:?}", Instant::now() - begin));
+    film
+}
+
+// the passes of the nearest surface to every pixel, the aovs
+pub struct Aovs {
+    // of the entity with the nearest boundary, (nx, ny, 0) from 0 to 1
+    pub normal: Film,
+    // to the nearest boundary
+    pub depth: Film,
+    // the entity the pixel is in, by a color of its index. black outside
+    pub id: Film,
+}
+
+// the aovs at the pixels, at the top left corners like the render without aa. when the pixel
+// is in more than one entity, the one with the nearest boundary
+pub fn aovs(scene: &Scene, settings: &RenderSettings) -> Aovs {
+    let (width, height) = (settings.width, settings.height);
+    let pixels: Vec<(u32, u32, Color, f64, Option<usize>)> = (0..height)
+        .into_par_iter()
+        .flat_map_iter(|y| {
+            (0..width).map(move |x| {
+                let p = settings.to_world((x as f64, y as f64));
+                let distances = scene
+                    .entities
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, e)| {
+                        let shape = e.shape.as_sdf()?;
+                        Some((i, shape, shape.sdf(p)))
+                    })
+                    .filter(|(_, _, d)| d.is_finite());
+                let nearest = distances
+                    .clone()
+                    .min_by(|a, b| a.2.abs().total_cmp(&b.2.abs()));
+                let inside = distances
+                    .filter(|&(_, _, d)| d < 0.0)
+                    .max_by(|a, b| a.2.total_cmp(&b.2))
+                    .map(|(i, _, _)| i);
+                let (normal, depth) = match nearest {
+                    Some((_, shape, d)) => {
+                        let (nx, ny) = sdf_normal(shape, p);
+                        let normal = Color {
+                            r: (nx + 1.0) / 2.0,
+                            g: (ny + 1.0) / 2.0,
+                            b: 0.0,
+                        };
+                        (normal, d.abs())
+                    }
+                    None => (Color::black(), f64::INFINITY),
+                };
+                (x, y, normal, depth, inside)
+            })
+        })
+        .collect();
+    let mut aovs = Aovs {
+        normal: Film::new(width, height),
+        depth: Film::new(width, height),
+        id: Film::new(width, height),
+    };
+    for (x, y, normal, depth, inside) in pixels {
+        aovs.normal.put(x, y, normal);
+        aovs.depth.put(x, y, Color::grey(depth));
+        aovs.id.put(x, y, inside.map_or(Color::black(), id_color));
+    }
+    aovs
+}
+
+// the hues of the indexes next to each other far apart
+fn id_color(i: usize) -> Color {
+    let h = (i as f64 * GOLDEN).fract();
+    let c = |offset: f64| 0.5 + 0.5 * (2.0 * PI * (h + offset)).cos();
+    Color {
+        r: c(0.0),
+        g: c(1.0 / 3.0),
+        b: c(2.0 / 3.0),
+    }
+}
+
+// ambient occlusion, of the surfaces near the pixels whatever their materials
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Ao {
+    // of the rays, the surfaces further away don't occlude
+    pub distance: f64,
+    #[serde(default = "default_ao_samples")]
+    pub samples: u32,
+    // the image of out darkened by it, besides out_ao
+    #[serde(default)]
+    pub multiply: bool,
+}
+
+fn default_ao_samples() -> u32 {
+    64
+}
+
+// the share of the rays from every pixel, at its top left corner like the aovs, reaching no
+// surface within the distance of ao. from black for the pixels in a corner to white
+pub fn ambient_occlusion(scene: &Scene, settings: &RenderSettings, ao: Ao) -> Film {
+    let (width, height) = (settings.width, settings.height);
+    let samples = ao.samples.max(1);
+    let pixels: Vec<(u32, u32, f64)> = (0..height)
+        .into_par_iter()
+        .flat_map_iter(|y| {
+            (0..width).map(move |x| {
+                reseed(scene.seed, &[x as u64, y as u64]);
+                let p = settings.to_world((x as f64, y as f64));
+                let offset = gen_range(0.0..1.0);
+                let open = (0..samples)
+                    .filter(|&k| {
+                        let a = scene.sampler.angle(k, samples, offset);
+                        let d = (a.cos(), a.sin());
+                        scene.intersect(p, d, ao.distance, Sight::Shadow).is_none()
+                    })
+                    .count();
+                (x, y, open as f64 / samples as f64)
+            })
+        })
+        .collect();
+    let mut film = Film::new(width, height);
+    for (x, y, open) in pixels {
+        film.put(x, y, Color::grey(open));
+    }
+    film
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Progressive {
+    // stop after the pass that runs out of the time, in seconds
+    #[serde(default)]
+    pub seconds: Option<f64>,
+}
+
+// a step through n strata visiting all of them, about n / φ so that the strata visited so far
+// are spread over the whole circle
+fn golden_step(n: u32) -> u32 {
+    let gcd = |mut a: u32, mut b: u32| {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+    let mut step = ((n as f64 * GOLDEN).round() as u32).max(1);
+    while gcd(step, n) != 1 {
+        step += 1;
+    }
+    step
+}
+
+// render in passes of one sample per pixel, until all the n samples are taken or the time is
+// out, calling save with the film so far after every pass, returns the last film, or the first
+// error of save. with the stratified sampler, every pixel steps through the strata from its own
+// random one on. the film of live is the one of the last pass, no passes are started after a stop
+pub fn render_progressive<F: FnMut(&Film) -> Result<(), Error>, P: Progress>(
+    scene: &Scene,
+    settings: &RenderSettings,
+    progressive: Progressive,
+    live: &Live,
+    mut save: F,
+    mut pb: P,
+) -> Result<Film, Error> {
+    let RenderSettings {
+        width,
+        height,
+        stratification,
+        max_depth,
+        tile,
+        ..
+    } = *settings;
+    if scene.mode == Mode::Layout {
+        let film = layout(scene, settings, live, pb);
+        save(&film)?;
+        return Ok(film);
+    }
+    let n = stratification.max(1);
+    let step = golden_step(n);
+    pb.start(n as u64);
+    let begin = Instant::now();
+    let tiles = tiles((width, height), tile);
+    // the sum of the samples, the offset of the sampler, and the sum of the direct light at the
+    // anti-aliasing positions, of every pixel
+    let mut sums = vec![Color::black(); (width * height) as usize];
+    reseed(scene.seed, &[]);
+    let offsets: Vec<f64> = (0..width * height).map(|_| gen_range(0.0..1.0)).collect();
+    let mut direct = vec![Color::black(); (width * height) as usize];
+    let mut stats = vec![Stats::default(); (width * height) as usize];
+    *live.film.lock().unwrap() = Film::new(width, height);
+    let m = scene.aa.positions();
+    for pass in 0..n {
+        if live.is_stopped() {
+            break;
+        }
+        let colors: Vec<(u32, u32, Color, Color)> = tiles
+            .par_iter()
+            .flat_map_iter(|pixels| {
+                pixels.iter().map(|&(x, y)| {
+                    reseed(scene.seed, &[x as u64, y as u64, pass as u64]);
+                    let i = (y * width + x) as usize;
+                    let point = scene.aa.position(pass % m, (x, y), settings);
+                    let paths = paths(scene, point);
+                    let k = match scene.sampler {
+                        Sampler::Stratified => {
+                            let first = (offsets[i] * n as f64) as u64;
+                            ((first + pass as u64 * step as u64) % n as u64) as u32
+                        }
+                        _ => pass,
+                    };
+                    let a = scene.sampler.angle(k, n, offsets[i]);
+                    let color = samples(scene, max_depth, point, &paths, &[a])[0];
+                    // every position once
+                    let light = if pass < m {
+                        direct_light(scene, point, None)
+                    } else {
+                        Color::black()
+                    };
+                    (x, y, color, light)
+                })
+            })
+            .collect();
+        // saved without the lock, which the window takes to show the film
+        let film = {
+            let mut film = live.film.lock().unwrap();
+            for (x, y, color, light) in colors {
+                let i = (y * width + x) as usize;
+                sums[i] = sums[i] + color;
+                stats[i].add(color);
+                direct[i] = direct[i] + light;
+                let light = direct[i] * (1.0 / min(pass + 1, m) as f64);
+                film.put(x, y, sums[i] * (1.0 / (pass + 1) as f64) + light);
+                film.put_stats(x, y, stats[i]);
+            }
+            film.clone()
+        };
+        if let Err(e) = save(&film) {
+            pb.finish();
+            return Err(e);
+        }
+        pb.add(1);
+        if let Some(seconds) = progressive.seconds {
+            if (Instant::now() - begin).as_secs_f64() >= seconds {
+                break;
+            }
+        }
+    }
+    let end = Instant::now();
+    pb.finish_print(&format!("{:?}", end - begin));
+    let film = live.film.lock().unwrap().clone();
+    Ok(film)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the sums of the luminances, and of their squares
+    fn sums(lums: &[f64]) -> (f64, f64) {
+        (lums.iter().sum(), lums.iter().map(|l| l * l).sum())
+    }
+
+    #[test]
+    fn adaptive_stops() {
+        let adaptive = Adaptive {
+            min_samples: 4,
+            max_samples: 16,
+            variance_threshold: 0.001,
+        };
+        // without any variance after the first batch
+        let (lum, lum2) = sums(&[0.5; 4]);
+        assert!(adaptive.stops(4, 4, lum, lum2));
+        // the variance of the mean is 1 / 3 / 4, then 2 / 7 / 8
+        let (lum, lum2) = sums(&[0.0, 1.0, 0.0, 1.0]);
+        assert!(!adaptive.stops(4, 4, lum, lum2));
+        let (lum, lum2) = sums(&[0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+        assert!(!adaptive.stops(8, 4, lum, lum2));
+        // the next batch would take more than max_samples
+        let (lum, lum2) = sums(&[0.0, 1.0].repeat(7));
+        assert!(adaptive.stops(14, 4, lum, lum2));
+        let loose = Adaptive {
+            variance_threshold: 0.05,
+            ..adaptive
+        };
+        assert!(!loose.stops(4, 4, 2.0, 2.0));
+        assert!(loose.stops(8, 4, 4.0, 4.0));
+    }
+
+    #[test]
+    fn balance_weights_sum_to_1() {
+        let cones = [
+            Cone {
+                start: 0.3,
+                width: 0.5,
+            },
+            Cone {
+                start: 0.6,
+                width: 1.0,
+            },
+        ];
+        for k in 0..100 {
+            let a = k as f64 * 0.0628;
+            let (uniform, towards) = balance(&cones, a);
+            assert!((uniform + towards - 1.0).abs() < 1e-12, "at {}", a);
+            assert_eq!(towards == 0.0, cones_pdf(&cones, a) == 0.0, "at {}", a);
+        }
+        // the weighted estimates of a constant radiance over both ways of sampling add up to it
+        reseed(Some(1), &[]);
+        let n = 100_000;
+        let sum: f64 = (0..n)
+            .map(|k| {
+                let a = (k as f64 + 0.5) / n as f64 * 2.0 * PI;
+                balance(&cones, a).0 + balance(&cones, sample_cones(&cones)).0
+            })
+            .sum();
+        assert!((sum / n as f64 - 1.0).abs() < 0.01, "{}", sum / n as f64);
+    }
 }
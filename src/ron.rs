@@ -0,0 +1,559 @@
+use serde_json::{Map, Value};
+
+// reads the rusty object notation into the json of the configs. a variant is written by its
+// name, in snake or camel case, like Circle(cx: .5, cy: .5, r: .1) for {"circle": {...}}:
+// fields in parentheses become an object, one value is the value itself, more a list.
+// a unit variant is its name, Some(x) is x and None is null. structs are written without a
+// name, as (enable: true, ...). comments and trailing commas are allowed
+pub fn parse(s: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: s.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.skip();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("the end"));
+    }
+    Ok(value)
+}
+
+// the json written as rusty object notation, read back by parse into the same json.
+// an object of one field is written as a variant, like Circle(cx: .5, ...)
+pub fn to_string(value: &Value) -> String {
+    let mut s = String::new();
+    write(value, 0, &mut s);
+    s.push('\n');
+    s
+}
+
+fn write(value: &Value, indent: usize, s: &mut String) {
+    match value {
+        Value::Null => s.push_str("None"),
+        Value::Bool(b) => s.push_str(&b.to_string()),
+        Value::Number(n) => {
+            let text = n.to_string();
+            s.push_str(&text);
+            // read back as a float
+            if n.is_f64() && !text.contains(['.', 'e', 'E']) {
+                s.push_str(".0");
+            }
+        }
+        Value::String(text) => string(text, s),
+        Value::Array(list) => {
+            let items = list.iter().map(|v| (None, v));
+            write_items(items, ('[', ']'), indent, short(value), s)
+        }
+        Value::Object(map) => {
+            if let Some((key, inner)) = map.iter().next().filter(|_| map.len() == 1) {
+                if let Some(name) = variant(key) {
+                    s.push_str(&name);
+                    match inner {
+                        // the fields go into the parentheses of the variant, a single one
+                        // when it isn't a variant itself
+                        Value::Object(fields)
+                            if is_struct(fields)
+                                && !fields.is_empty()
+                                && (fields.len() > 1
+                                    || fields.values().all(|v| !v.is_object())) =>
+                        {
+                            let items = fields.iter().map(|(k, v)| (Some(k.as_str()), v));
+                            write_items(items, ('(', ')'), indent, false, s)
+                        }
+                        _ => {
+                            s.push('(');
+                            write(inner, indent, s);
+                            s.push(')');
+                        }
+                    }
+                    return;
+                }
+            }
+            let items = map.iter().map(|(k, v)| (Some(k.as_str()), v));
+            if is_struct(map) && !map.is_empty() {
+                write_items(items, ('(', ')'), indent, false, s)
+            } else {
+                // a map, the keys quoted
+                write_items(items, ('{', '}'), indent, map.is_empty(), s)
+            }
+        }
+    }
+}
+
+// the values, with their keys when there are, one on a line unless short
+fn write_items<'a, I: Iterator<Item = (Option<&'a str>, &'a Value)>>(
+    items: I,
+    (open, close): (char, char),
+    indent: usize,
+    short: bool,
+    s: &mut String,
+) {
+    s.push(open);
+    let mut first = true;
+    for (key, value) in items {
+        if short {
+            if !first {
+                s.push_str(", ");
+            }
+        } else {
+            s.push('\n');
+            s.push_str(&"    ".repeat(indent + 1));
+        }
+        first = false;
+        match key {
+            Some(key) if open == '{' => {
+                string(key, s);
+                s.push_str(": ");
+            }
+            Some(key) => {
+                s.push_str(key);
+                s.push_str(": ");
+            }
+            None => {}
+        }
+        write(value, indent + 1, s);
+        if !short {
+            s.push(',');
+        }
+    }
+    if !short && !first {
+        s.push('\n');
+        s.push_str(&"    ".repeat(indent));
+    }
+    s.push(close);
+}
+
+fn string(text: &str, s: &mut String) {
+    s.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\t' => s.push_str("\\t"),
+            '\r' => s.push_str("\\r"),
+            '\0' => s.push_str("\\0"),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
+
+// on one line: the numbers and such, lists of them, and the variants of them
+fn short(value: &Value) -> bool {
+    match value {
+        Value::Array(list) => {
+            list.len() <= 8 && list.iter().all(|v| !v.is_array() && !v.is_object())
+        }
+        Value::Object(map) => map.len() == 1 && map.values().all(short),
+        _ => true,
+    }
+}
+
+// the keys can be written without quotes
+fn is_struct(map: &Map<String, Value>) -> bool {
+    map.keys().all(|key| is_name(key))
+}
+
+// none of them read as something else
+fn is_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && !matches!(key, "true" | "false" | "None" | "Some")
+}
+
+// smooth_union to SmoothUnion, none when it wouldn't be read back as the key
+fn variant(key: &str) -> Option<String> {
+    if !is_name(key) {
+        return None;
+    }
+    let mut name = String::new();
+    let mut upper = true;
+    for c in key.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            name.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            name.push(c);
+        }
+    }
+    Some(name).filter(|name| snake_case(name) == key && is_name(name))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    // spaces and comments
+    fn skip(&mut self) {
+        loop {
+            while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+                self.pos += 1;
+            }
+            if self.starts_with("//") {
+                while self.pos < self.chars.len() && self.chars[self.pos] != '\n' {
+                    self.pos += 1;
+                }
+            } else if self.starts_with("/*") {
+                self.pos += 2;
+                while self.pos < self.chars.len() && !self.starts_with("*/") {
+                    self.pos += 1;
+                }
+                self.pos = (self.pos + 2).min(self.chars.len());
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // with the line and the column
+    fn error(&self, expected: &str) -> String {
+        let before = &self.chars[..self.pos.min(self.chars.len())];
+        let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        match self.chars.get(self.pos) {
+            Some(c) => format!("{}:{}: expected {}, found {:?}", line, column, expected, c),
+            None => format!("{}:{}: expected {}, found the end", line, column, expected),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('[') => {
+                self.pos += 1;
+                Ok(Value::Array(self.list(']')?))
+            }
+            Some('{') => {
+                self.pos += 1;
+                self.map()
+            }
+            Some('(') => {
+                self.pos += 1;
+                self.parenthesized()
+            }
+            Some('"') => self.string().map(Value::String),
+            Some(c) if c.is_ascii_digit() || c == '.' || c == '-' || c == '+' => self.number(),
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.name();
+                match name.as_str() {
+                    "true" => return Ok(Value::Bool(true)),
+                    "false" => return Ok(Value::Bool(false)),
+                    "None" => return Ok(Value::Null),
+                    _ => {}
+                }
+                if !self.eat('(') {
+                    return Ok(Value::String(snake_case(&name)));
+                }
+                let inner = self.parenthesized()?;
+                if name == "Some" {
+                    return Ok(inner);
+                }
+                let mut map = Map::new();
+                map.insert(snake_case(&name), inner);
+                Ok(Value::Object(map))
+            }
+            _ => Err(self.error("a value")),
+        }
+    }
+
+    // the values up to the end, separated by commas
+    fn list(&mut self, end: char) -> Result<Vec<Value>, String> {
+        let mut list = Vec::new();
+        loop {
+            if self.eat(end) {
+                return Ok(list);
+            }
+            list.push(self.value()?);
+            if !self.eat(',') && self.peek() != Some(end) {
+                return Err(self.error(&format!("',' or '{}'", end)));
+            }
+        }
+    }
+
+    fn map(&mut self) -> Result<Value, String> {
+        let mut map = Map::new();
+        loop {
+            if self.eat('}') {
+                return Ok(Value::Object(map));
+            }
+            let key = match self.peek() {
+                Some('"') => self.string()?,
+                _ => self.name(),
+            };
+            if key.is_empty() || !self.eat(':') {
+                return Err(self.error("a key and ':'"));
+            }
+            map.insert(key, self.value()?);
+            if !self.eat(',') && self.peek() != Some('}') {
+                return Err(self.error("',' or '}'"));
+            }
+        }
+    }
+
+    // after the '(', the fields of a struct, or the values of a tuple
+    fn parenthesized(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        if matches!(self.peek(), Some(c) if c.is_alphabetic() || c == '_') {
+            self.name();
+            if self.eat(':') {
+                self.pos = start;
+                return self.fields();
+            }
+            self.pos = start;
+        }
+        let mut list = self.list(')')?;
+        Ok(match list.len() {
+            0 => Value::Null,
+            1 => list.pop().unwrap(),
+            _ => Value::Array(list),
+        })
+    }
+
+    fn fields(&mut self) -> Result<Value, String> {
+        let mut map = Map::new();
+        loop {
+            if self.eat(')') {
+                return Ok(Value::Object(map));
+            }
+            self.skip();
+            let key = self.name();
+            if key.is_empty() || !self.eat(':') {
+                return Err(self.error("a field and ':'"));
+            }
+            map.insert(key, self.value()?);
+            if !self.eat(',') && self.peek() != Some(')') {
+                return Err(self.error("',' or ')'"));
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let start = self.pos;
+        while self.pos < self.chars.len()
+            && (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_')
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        // the opening quote
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let c = match self.chars.get(self.pos) {
+                Some(c) => *c,
+                None => return Err(self.error("'\"'")),
+            };
+            self.pos += 1;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let e = self.chars.get(self.pos).copied();
+                    self.pos += 1;
+                    s.push(match e {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some(c) => c,
+                        None => return Err(self.error("an escaped character")),
+                    });
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        if matches!(self.chars[self.pos], '-' | '+') {
+            self.pos += 1;
+        }
+        while self.pos < self.chars.len() {
+            let c = self.chars[self.pos];
+            let exponent_sign =
+                matches!(c, '-' | '+') && matches!(self.chars[self.pos - 1], 'e' | 'E');
+            if !(c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '_') || exponent_sign) {
+                break;
+            }
+            self.pos += 1;
+        }
+        let s: String = self.chars[start..self.pos]
+            .iter()
+            .filter(|&&c| c != '_' && c != '+')
+            .collect();
+        if let Ok(i) = s.parse::<i64>() {
+            return Ok(Value::from(i));
+        }
+        match s.parse::<f64>() {
+            Ok(f) => Ok(Value::from(f)),
+            Err(_) => {
+                self.pos = start;
+                Err(self.error("a number"))
+            }
+        }
+    }
+}
+
+// SmoothUnion to smooth_union, the snake case names are kept
+fn snake_case(name: &str) -> String {
+    let mut s = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                s.push('_');
+            }
+            s.extend(c.to_lowercase());
+        } else {
+            s.push(c);
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn variants() {
+        assert_eq!(
+            parse("Circle(cx: .5, cy: 0.5, r: 1)").unwrap(),
+            json!({"circle": {"cx": 0.5, "cy": 0.5, "r": 1}})
+        );
+        assert_eq!(
+            parse("SmoothUnion(k: 0.1)").unwrap(),
+            json!({"smooth_union": {"k": 0.1}})
+        );
+        assert_eq!(
+            parse("smooth_union(k: 0.1)").unwrap(),
+            parse("SmoothUnion(k: 0.1)").unwrap()
+        );
+        // one value is the value, more a list, none null
+        assert_eq!(parse("Grey(2)").unwrap(), json!({"grey": 2}));
+        assert_eq!(parse("Rgb(1, 2, 3)").unwrap(), json!({"rgb": [1, 2, 3]}));
+        assert_eq!(parse("Empty()").unwrap(), json!({"empty": null}));
+        assert_eq!(
+            parse("ReinhardExtended").unwrap(),
+            json!("reinhard_extended")
+        );
+    }
+
+    #[test]
+    fn options_and_literals() {
+        assert_eq!(parse("Some(3)").unwrap(), json!(3));
+        assert_eq!(parse("None").unwrap(), json!(null));
+        assert_eq!(parse("[true, false]").unwrap(), json!([true, false]));
+        assert_eq!(parse(r#""a\"b\n""#).unwrap(), json!("a\"b\n"));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse("1_000").unwrap(), json!(1000));
+        assert_eq!(parse("-2.5e-3").unwrap(), json!(-0.0025));
+        assert_eq!(parse("+3").unwrap(), json!(3));
+        assert_eq!(parse("1E2").unwrap(), json!(100.0));
+    }
+
+    #[test]
+    fn structs_maps_comments_and_trailing_commas() {
+        let text = "
+            // a config
+            (
+                enable: true, /* on */
+                scenes: [
+                    (shape: Circle(cx: 0, cy: 0, r: 1),),
+                ],
+                colors: {\"a b\": 1, c: 2,},
+            )
+        ";
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({
+                "enable": true,
+                "scenes": [{"shape": {"circle": {"cx": 0, "cy": 0, "r": 1}}}],
+                "colors": {"a b": 1, "c": 2},
+            })
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse("").unwrap_err(),
+            "1:1: expected a value, found the end"
+        );
+        assert_eq!(
+            parse("[1 2]").unwrap_err(),
+            "1:4: expected ',' or ']', found '2'"
+        );
+        assert_eq!(
+            parse("(a: 1").unwrap_err(),
+            "1:6: expected ',' or ')', found the end"
+        );
+        assert_eq!(
+            parse("{: 1}").unwrap_err(),
+            "1:2: expected a key and ':', found ':'"
+        );
+        assert_eq!(
+            parse("1\n  2").unwrap_err(),
+            "2:3: expected the end, found '2'"
+        );
+        assert_eq!(
+            parse("\"open").unwrap_err(),
+            "1:6: expected '\"', found the end"
+        );
+        assert_eq!(
+            parse("1.2.3").unwrap_err(),
+            "1:1: expected a number, found '1'"
+        );
+        assert_eq!(parse("#").unwrap_err(), "1:1: expected a value, found '#'");
+    }
+
+    #[test]
+    fn written_and_read_back() {
+        let value = json!([{
+            "enable": true,
+            "out": "a \"b\".png",
+            "scenes": [
+                {"shape": {"smooth_union": {"k": 0.1, "shapes": []}}},
+                {"shape": {"polygon": {"regular": {"cx": 0.5, "n": 6}}}},
+            ],
+            "emissive": {"rgb": [1.0, 0.5, -2]},
+            "tone_map": "aces",
+            "medium": null,
+            "keys": {"not a name": 1, "None": 2, "true": 3, "Upper": 4},
+            "empty": {},
+            "list": [],
+        }]);
+        assert_eq!(parse(&to_string(&value)).unwrap(), value);
+    }
+}
@@ -1,8 +1,18 @@
 use std::f64::consts::PI;
+use wide::f64x4;
+
+use crate::{
+    calculate::{closest_on_segment, distance},
+    error::Error,
+};
 
 const EPSILON: f64 = 1e-6;
 const WHOLE_ANGLE: f64 = 360.0;
 
+// for ray marching
+pub(crate) const MAX_STEP: u32 = 128;
+pub(crate) const SDF_EPSILON: f64 = 1e-6;
+pub(crate) const SDF_BIAS: f64 = 1e-4;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Intersection {
@@ -10,9 +20,282 @@ pub struct Intersection {
     pub normal: (f64, f64),
 }
 
+// an axis aligned bounding box
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: (f64, f64),
+    pub max: (f64, f64),
+}
+
+impl Aabb {
+    pub fn from_points<I: IntoIterator<Item = (f64, f64)>>(points: I) -> Option<Self> {
+        points.into_iter().fold(None, |b: Option<Aabb>, (x, y)| {
+            Some(match b {
+                Some(b) => Aabb {
+                    min: (b.min.0.min(x), b.min.1.min(y)),
+                    max: (b.max.0.max(x), b.max.1.max(y)),
+                },
+                None => Aabb {
+                    min: (x, y),
+                    max: (x, y),
+                },
+            })
+        })
+    }
+
+    fn around(cx: f64, cy: f64, hx: f64, hy: f64) -> Self {
+        Aabb {
+            min: (cx - hx, cy - hy),
+            max: (cx + hx, cy + hy),
+        }
+    }
+
+    pub fn corners(&self) -> [(f64, f64); 4] {
+        [
+            self.min,
+            (self.max.0, self.min.1),
+            self.max,
+            (self.min.0, self.max.1),
+        ]
+    }
+
+    pub fn expand(&self, k: f64) -> Self {
+        Aabb {
+            min: (self.min.0 - k, self.min.1 - k),
+            max: (self.max.0 + k, self.max.1 + k),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Aabb {
+            min: (self.min.0.min(other.min.0), self.min.1.min(other.min.1)),
+            max: (self.max.0.max(other.max.0), self.max.1.max(other.max.1)),
+        }
+    }
+
+    fn intersection(&self, other: &Aabb) -> Self {
+        Aabb {
+            min: (self.min.0.max(other.min.0), self.min.1.max(other.min.1)),
+            max: (self.max.0.min(other.max.0), self.max.1.min(other.max.1)),
+        }
+    }
+
+    // whether the ray from p along d passes through the box
+    pub fn hit(&self, p: (f64, f64), d: (f64, f64)) -> bool {
+        self.entry(p, d).is_some()
+    }
+
+    // the ray parameter where the ray from p along d enters the box, slab by slab.
+    // 0 if p is inside
+    pub fn entry(&self, p: (f64, f64), d: (f64, f64)) -> Option<f64> {
+        self.span(p, d).map(|(t0, _)| t0)
+    }
+
+    // the ray parameter where the ray leaves the box, none when it misses it
+    pub fn exit(&self, p: (f64, f64), d: (f64, f64)) -> Option<f64> {
+        self.span(p, d).map(|(_, t1)| t1)
+    }
+
+    // of the entry and the exit
+    fn span(&self, p: (f64, f64), d: (f64, f64)) -> Option<(f64, f64)> {
+        let mut t0: f64 = 0.0;
+        let mut t1 = f64::INFINITY;
+        for (o, dd, lo, hi) in [
+            (p.0, d.0, self.min.0 - EPSILON, self.max.0 + EPSILON),
+            (p.1, d.1, self.min.1 - EPSILON, self.max.1 + EPSILON),
+        ] {
+            if dd.abs() < EPSILON * EPSILON {
+                if o < lo || o > hi {
+                    return None;
+                }
+            } else {
+                let a = (lo - o) / dd;
+                let b = (hi - o) / dd;
+                t0 = t0.max(a.min(b));
+                t1 = t1.min(a.max(b));
+            }
+        }
+        if t0 <= t1 {
+            Some((t0, t1))
+        } else {
+            None
+        }
+    }
+}
+
+// the union of the boxes, None if any of them is unbounded
+fn union_aabb(c: &[Box<dyn Shape + Sync>]) -> Option<Aabb> {
+    c.iter().try_fold(None, |b: Option<Aabb>, item| {
+        let a = item.aabb()?;
+        Some(Some(match b {
+            Some(b) => b.union(&a),
+            None => a,
+        }))
+    })?
+}
+
+// the ray parameter of a point on the ray from p along d
+pub fn ray_t((px, py): (f64, f64), (dx, dy): (f64, f64), (x, y): (f64, f64)) -> f64 {
+    ((x - px) * dx + (y - py) * dy) / (dx * dx + dy * dy)
+}
+
+// the nearest intersection of the shape before t_max that is kept, the others are tested
+// from the nearest on, so that keep is called as few times as possible
+fn first_hit<S: Shape + ?Sized, F: Fn(&Intersection) -> bool>(
+    shape: &S,
+    p: (f64, f64),
+    d: (f64, f64),
+    t_max: f64,
+    keep: F,
+) -> Option<(Intersection, f64)> {
+    let mut hits: Vec<(Intersection, f64)> = shape
+        .intersect(p, d)
+        .into_iter()
+        .map(|item| {
+            let t = ray_t(p, d, item.point);
+            (item, t)
+        })
+        .filter(|(_, t)| *t < t_max)
+        .collect();
+    hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+    hits.into_iter().find(|(item, _)| keep(item))
+}
+
+pub type Hit = Option<(Intersection, f64)>;
+
+// the lanes of 4 points, or directions
+fn lanes(v: [(f64, f64); 4]) -> (f64x4, f64x4) {
+    (
+        f64x4::from(v.map(|(x, _)| x)),
+        f64x4::from(v.map(|(_, y)| y)),
+    )
+}
+
+// signed distance to the boundary, negative inside
+pub trait Sdf {
+    fn sdf(&self, p: (f64, f64)) -> f64;
+    // of the boundary at the point on it, for the sdf engine. the open shapes give the one of
+    // their front, the gradient of their sdf is towards the ray
+    fn normal(&self, p: (f64, f64)) -> (f64, f64) {
+        sdf_normal(self, p)
+    }
+}
+
+// the sdf of a shape of a composite one, the ones without a place in space are never near
+fn sdf_of(shape: &(dyn Shape + Sync), p: (f64, f64)) -> f64 {
+    shape.as_sdf().map_or(f64::INFINITY, |item| item.sdf(p))
+}
+
+// whether all the shapes have an sdf, a composite one only has one then
+fn have_sdf(shapes: &[Box<dyn Shape + Sync>]) -> bool {
+    shapes.iter().all(|item| item.as_sdf().is_some())
+}
+
+// the gradient of the sdf, by central differences
+pub fn sdf_normal<S: Sdf + ?Sized>(shape: &S, (x, y): (f64, f64)) -> (f64, f64) {
+    let nx = shape.sdf((x + SDF_BIAS, y)) - shape.sdf((x - SDF_BIAS, y));
+    let ny = shape.sdf((x, y + SDF_BIAS)) - shape.sdf((x, y - SDF_BIAS));
+    let len = (nx * nx + ny * ny).sqrt();
+    (nx / len, ny / len)
+}
+
+// the zero of the sdf between t0 and t1 along the ray, where the sign changes
+pub fn bisect<S: Sdf + ?Sized>(
+    shape: &S,
+    (px, py): (f64, f64),
+    (dx, dy): (f64, f64),
+    mut t0: f64,
+    mut t1: f64,
+) -> f64 {
+    let d0 = shape.sdf((px + dx * t0, py + dy * t0));
+    for _ in 0..32 {
+        let t = (t0 + t1) * 0.5;
+        if shape.sdf((px + dx * t, py + dy * t)) * d0 > 0.0 {
+            t0 = t;
+        } else {
+            t1 = t;
+        }
+    }
+    (t0 + t1) * 0.5
+}
+
+// all the intersections of a ray with the boundary of the shape, found by ray marching.
+// the sdf may overestimate the distance (smooth blending), so crossings are also detected
+// by a change of sign. past the box of the shape nothing is hit, an unbounded one is marched
+// for MAX_STEP steps
+pub fn march<S: Sdf + Shape + ?Sized>(
+    shape: &S,
+    (px, py): (f64, f64),
+    (dx, dy): (f64, f64),
+) -> Vec<Intersection> {
+    let mut result: Vec<Intersection> = Vec::new();
+    let len = (dx * dx + dy * dy).sqrt();
+    let (dx, dy) = (dx / len, dy / len);
+    let limit = match shape.aabb() {
+        Some(b) => match b.exit((px, py), (dx, dy)) {
+            Some(t) => t,
+            None => return result,
+        },
+        None => f64::INFINITY,
+    };
+    let mut t = SDF_BIAS;
+    let mut last = f64::NEG_INFINITY;
+    let mut prev: Option<(f64, f64)> = None;
+    for _ in 0..MAX_STEP {
+        let d = shape.sdf((px + dx * t, py + dy * t));
+        let hit = match prev {
+            Some((t0, d0)) if d0 * d < 0.0 => Some(bisect(shape, (px, py), (dx, dy), t0, t)),
+            _ if d.abs() < SDF_EPSILON => Some(t),
+            _ => None,
+        };
+        if let Some(th) = hit {
+            if th - last > SDF_BIAS {
+                let point = (px + dx * th, py + dy * th);
+                result.push(Intersection {
+                    point,
+                    normal: sdf_normal(shape, point),
+                });
+                last = th;
+            }
+        }
+        prev = Some((t, d));
+        t += if d.abs() < SDF_EPSILON {
+            SDF_BIAS
+        } else {
+            d.abs()
+        };
+        if t > limit {
+            break;
+        }
+    }
+    result
+}
+
 pub trait Shape {
     fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection>;
+    // the nearest intersection, with its ray parameter t (at p + t * d), before t_max
+    fn nearest(&self, p: (f64, f64), d: (f64, f64), t_max: f64) -> Option<(Intersection, f64)> {
+        first_hit(self, p, d, t_max, |_| true)
+    }
+    // nearest for 4 rays at once
+    fn nearest4(&self, p: [(f64, f64); 4], d: [(f64, f64); 4], t_max: [f64; 4]) -> [Hit; 4] {
+        std::array::from_fn(|i| self.nearest(p[i], d[i], t_max[i]))
+    }
     fn is_inside(&self, p: (f64, f64)) -> bool;
+    // the shape as an sdf, for the sdf engine and the images of the distances. None for the
+    // ones without a place in space, like a directional light
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        None
+    }
+    // a box around the boundary, None when the shape is unbounded
+    fn aabb(&self) -> Option<Aabb> {
+        None
+    }
+    // the name of the type, for the counts of profile
+    fn kind(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
 }
 
 #[allow(dead_code)]
@@ -46,6 +329,166 @@ impl Shape for DirectionalLight {
     }
 }
 
+// a segment without interior, it can be hit from both sides
+// the parameter t where the ray p + t * d crosses the segment a-b
+fn ray_segment(
+    (px, py): (f64, f64),
+    (dx, dy): (f64, f64),
+    a: (f64, f64),
+    b: (f64, f64),
+) -> Option<f64> {
+    let ax = a.0 - px;
+    let ay = a.1 - py;
+    let bx = b.0 - px;
+    let by = b.1 - py;
+    let product1 = ax * dy - dx * ay;
+    let product2 = bx * dy - dx * by;
+    if product1 * product2 > 0.0 {
+        return None;
+    }
+    let nx = a.1 - b.1;
+    let ny = b.0 - a.0;
+    let c1 = dx * nx + dy * ny;
+    if c1.abs() < EPSILON {
+        return None;
+    }
+    let t = (ax * nx + ay * ny) / c1;
+    if t > EPSILON {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+#[allow(dead_code)]
+pub struct SegmentLight {
+    pub x1: f64,
+    pub y1: f64,
+    pub x2: f64,
+    pub y2: f64,
+}
+
+impl Sdf for SegmentLight {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        distance(
+            p,
+            closest_on_segment(p, (self.x1, self.y1), (self.x2, self.y2)),
+        )
+    }
+}
+
+impl Shape for SegmentLight {
+    fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points([(self.x1, self.y1), (self.x2, self.y2)])
+    }
+
+    fn intersect(&self, p: (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
+        let mut result: Vec<Intersection> = Vec::new();
+        let nx = self.y1 - self.y2;
+        let ny = self.x2 - self.x1;
+        let len = (nx * nx + ny * ny).sqrt();
+        if len < EPSILON {
+            return result;
+        }
+        if let Some(t) = ray_segment(p, (dx, dy), (self.x1, self.y1), (self.x2, self.y2)) {
+            // always face the ray
+            let sign = if dx * nx + dy * ny > 0.0 { -1.0 } else { 1.0 };
+            result.push(Intersection {
+                point: (p.0 + dx * t, p.1 + dy * t),
+                normal: (sign * nx / len, sign * ny / len),
+            });
+        }
+        result
+    }
+
+    fn is_inside(&self, _: (f64, f64)) -> bool {
+        false
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
+// an open curve through the points, without an inside. its front is on the left of the way
+// from the first point to the last, the normals are towards it
+pub struct Polyline {
+    points: Vec<(f64, f64)>,
+}
+
+#[allow(dead_code)]
+impl Polyline {
+    pub fn new(points: Vec<(f64, f64)>) -> Result<Self, Error> {
+        if points.len() < 2 {
+            return Err(Error::Invalid(format!(
+                "too few points for a polyline, {}",
+                points.len()
+            )));
+        }
+        Ok(Self { points })
+    }
+
+    // of the segment from a to b
+    fn segment_normal(a: (f64, f64), b: (f64, f64)) -> Option<(f64, f64)> {
+        let nx = a.1 - b.1;
+        let ny = b.0 - a.0;
+        let len = (nx * nx + ny * ny).sqrt();
+        if len < EPSILON {
+            return None;
+        }
+        Some((nx / len, ny / len))
+    }
+}
+
+impl Sdf for Polyline {
+    // to the nearest segment, never below 0 without an inside
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        self.points
+            .windows(2)
+            .map(|w| distance(p, closest_on_segment(p, w[0], w[1])))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    fn normal(&self, p: (f64, f64)) -> (f64, f64) {
+        self.points
+            .windows(2)
+            .filter_map(|w| {
+                let normal = Self::segment_normal(w[0], w[1])?;
+                Some((distance(p, closest_on_segment(p, w[0], w[1])), normal))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or((0.0, 0.0), |(_, normal)| normal)
+    }
+}
+
+impl Shape for Polyline {
+    fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(self.points.iter().copied())
+    }
+
+    fn intersect(&self, p: (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
+        self.points
+            .windows(2)
+            .filter_map(|w| {
+                let normal = Self::segment_normal(w[0], w[1])?;
+                let t = ray_segment(p, (dx, dy), w[0], w[1])?;
+                Some(Intersection {
+                    point: (p.0 + dx * t, p.1 + dy * t),
+                    normal,
+                })
+            })
+            .collect()
+    }
+
+    fn is_inside(&self, _: (f64, f64)) -> bool {
+        false
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
 #[allow(dead_code)]
 pub struct Circle {
     pub cx: f64,
@@ -53,7 +496,95 @@ pub struct Circle {
     pub r: f64,
 }
 
+#[allow(dead_code)]
+impl Circle {
+    pub fn new(cx: f64, cy: f64, r: f64) -> Self {
+        Self { cx, cy, r }
+    }
+}
+
+impl Sdf for Circle {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        distance(p, (self.cx, self.cy)) - self.r
+    }
+}
+
 impl Shape for Circle {
+    fn aabb(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.cx, self.cy, self.r, self.r))
+    }
+
+    fn nearest(
+        &self,
+        (px, py): (f64, f64),
+        (dx, dy): (f64, f64),
+        t_max: f64,
+    ) -> Option<(Intersection, f64)> {
+        let a = dx * dx + dy * dy;
+        let ocx = px - self.cx;
+        let ocy = py - self.cy;
+        let b = 2.0 * (ocx * dx + ocy * dy);
+        let c = ocx * ocx + ocy * ocy - self.r * self.r;
+        let delta = b * b - 4.0 * a * c;
+        if delta < 0.0 {
+            return None;
+        }
+        let t1 = (-b - delta.sqrt()) / (2.0 * a);
+        let t2 = (-b + delta.sqrt()) / (2.0 * a);
+        let t = if t1 > EPSILON { t1 } else { t2 };
+        if t <= EPSILON || t >= t_max {
+            return None;
+        }
+        let x = px + dx * t;
+        let y = py + dy * t;
+        let nx = x - self.cx;
+        let ny = y - self.cy;
+        let len = (nx * nx + ny * ny).sqrt();
+        Some((
+            Intersection {
+                point: (x, y),
+                normal: (nx / len, ny / len),
+            },
+            t,
+        ))
+    }
+
+    fn nearest4(&self, p: [(f64, f64); 4], d: [(f64, f64); 4], t_max: [f64; 4]) -> [Hit; 4] {
+        let ((px, py), (dx, dy)) = (lanes(p), lanes(d));
+        let a = dx * dx + dy * dy;
+        let ocx = px - f64x4::splat(self.cx);
+        let ocy = py - f64x4::splat(self.cy);
+        let b = (ocx * dx + ocy * dy) * 2.0;
+        let c = ocx * ocx + ocy * ocy - f64x4::splat(self.r * self.r);
+        let delta = b * b - a * c * 4.0;
+        let sqrt = delta.max(f64x4::ZERO).sqrt();
+        let t1 = (-b - sqrt) / (a * 2.0);
+        let t2 = (-b + sqrt) / (a * 2.0);
+        let epsilon = f64x4::splat(EPSILON);
+        let t = t1.simd_gt(epsilon).select(t1, t2);
+        let valid = delta.simd_ge(f64x4::ZERO)
+            & t.simd_gt(epsilon)
+            & t.simd_lt(f64x4::from(t_max));
+        let (valid, t) = (valid.to_bitmask(), t.to_array());
+        std::array::from_fn(|i| {
+            if valid & (1 << i) == 0 {
+                return None;
+            }
+            let x = p[i].0 + d[i].0 * t[i];
+            let y = p[i].1 + d[i].1 * t[i];
+            let nx = x - self.cx;
+            let ny = y - self.cy;
+            let len = (nx * nx + ny * ny).sqrt();
+            Some((
+                Intersection {
+                    point: (x, y),
+                    normal: (nx / len, ny / len),
+                },
+                t[i],
+            ))
+        })
+    }
+
     fn intersect(&self, (px, py): (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
         let a = dx * dx + dy * dy;
         let ocx = px - self.cx;
@@ -98,176 +629,1261 @@ impl Shape for Circle {
         let y = py - self.cy;
         x * x + y * y < self.r * self.r
     }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
 }
 
-#[allow(dead_code)]
-pub struct Plane {
-    pub px: f64,
-    pub py: f64,
-    pub nx: f64,
-    pub ny: f64,
+// the circular sector of radius r, from the angle start (in degrees, counterclockwise)
+// to start + sweep
+pub struct Sector {
+    cx: f64,
+    cy: f64,
+    r: f64,
+    start: f64,
+    sweep: f64,
 }
 
-impl Shape for Plane {
-    fn intersect(&self, (px, py): (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
-        let mut result: Vec<Intersection> = Vec::new();
-        let a = dx * self.nx + dy * self.ny;
-        if a.abs() < EPSILON {
-            result
+#[allow(dead_code)]
+impl Sector {
+    // a negative sweep goes clockwise from start, which is the sweep of its size from the end
+    pub fn new(cx: f64, cy: f64, r: f64, start: f64, sweep: f64) -> Self {
+        let (start, sweep) = if sweep < 0.0 {
+            (start + sweep, -sweep)
         } else {
-            let b = (self.px - px) * self.nx + (self.py - py) * self.ny;
-            let t = b / a;
-            if t > EPSILON {
-                result.push(Intersection {
-                    point: (px + dx * t, py + dy * t),
-                    normal: (self.nx, self.ny),
-                });
-            }
-            result
+            (start, sweep)
+        };
+        Self {
+            cx,
+            cy,
+            r,
+            start: 2.0 * PI * start / WHOLE_ANGLE,
+            sweep: 2.0 * PI * sweep.clamp(0.0, WHOLE_ANGLE) / WHOLE_ANGLE,
         }
     }
 
-    fn is_inside(&self, (px, py): (f64, f64)) -> bool {
-        (px - self.px) * self.nx + (py - self.py) * self.ny < 0.0
+    fn contains_angle(&self, (px, py): (f64, f64)) -> bool {
+        let theta = (self.cy - py).atan2(px - self.cx);
+        (theta - self.start).rem_euclid(2.0 * PI) <= self.sweep
     }
-}
 
-pub struct Polygon {
-    points: Vec<(f64, f64)>, // counterclockwise
+    // the end of the radius at angle theta
+    fn end(&self, theta: f64) -> (f64, f64) {
+        (
+            self.cx + self.r * theta.cos(),
+            self.cy - self.r * theta.sin(),
+        )
+    }
 }
 
-#[allow(dead_code)]
-impl Polygon {
-    pub fn new(p: Vec<(f64, f64)>) -> Self {
-        if p.len() > 1 {
-            Self {
-                points: p,
-            }
+impl Sdf for Sector {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        let c = (self.cx, self.cy);
+        let edges = [self.start, self.start + self.sweep]
+            .map(|theta| distance(p, closest_on_segment(p, c, self.end(theta))))
+            .iter()
+            .fold(f64::INFINITY, |a, &b| a.min(b));
+        let d = distance(p, c) - self.r;
+        if self.is_inside(p) {
+            -edges.min(-d)
+        } else if self.contains_angle(p) {
+            edges.min(d)
         } else {
-            panic!("Too few points!");
+            edges
         }
     }
+}
+
+impl Shape for Sector {
+    fn aabb(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.cx, self.cy, self.r, self.r))
+    }
 
-    pub fn rectangle(cx: f64, cy: f64, e: f64, sx: f64, sy: f64) -> Self {
-        let mut elevation = e;
-        while elevation < 0.0 {
-            elevation += WHOLE_ANGLE;
-        }
-        while elevation >= WHOLE_ANGLE {
-            elevation -= WHOLE_ANGLE
+    fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
+        let circle = Circle {
+            cx: self.cx,
+            cy: self.cy,
+            r: self.r,
+        };
+        let mut result: Vec<Intersection> = circle
+            .intersect(p, d)
+            .into_iter()
+            .filter(|item| self.contains_angle(item.point))
+            .collect();
+        if self.sweep < 2.0 * PI {
+            let c = (self.cx, self.cy);
+            let end = self.start + self.sweep;
+            for (theta, normal) in [
+                (self.start, (self.start.sin(), self.start.cos())),
+                (end, (-end.sin(), -end.cos())),
+            ] {
+                if let Some(t) = ray_segment(p, d, c, self.end(theta)) {
+                    result.push(Intersection {
+                        point: (p.0 + d.0 * t, p.1 + d.1 * t),
+                        normal,
+                    });
+                }
+            }
         }
-        let theta = -2.0 * PI * elevation / WHOLE_ANGLE;
-        Self::new([(sx, -sy), (-sx, -sy), (-sx, sy), (sx, sy)].iter()
-            .map(|&(x, y)| (x * theta.cos() - y * theta.sin(), x * theta.sin() + y * theta.cos()))
-            .map(|(x, y)| (x + cx, y + cy))
-            .collect())
+        result
     }
 
-    pub fn regular(cx: f64, cy: f64, r: f64, n: u32, e: f64) -> Self {
-        let mut elevation = e;
-        while elevation < 0.0 {
-            elevation += WHOLE_ANGLE;
-        }
-        while elevation >= WHOLE_ANGLE {
-            elevation -= WHOLE_ANGLE
-        }
-        Self::new((0..n).map(|i| i as f64 * 2.0 * PI / n as f64)
-            .map(|theta| (theta + 2.0 * PI * elevation / WHOLE_ANGLE))
-            .map(|theta| (r * theta.cos(), r * theta.sin()))
-            .map(|(x, y)| (cx + x, cy - y))
-            .collect())
+    fn is_inside(&self, p: (f64, f64)) -> bool {
+        distance(p, (self.cx, self.cy)) < self.r && self.contains_angle(p)
     }
 
-    pub fn star(cx: f64, cy: f64, r: f64, n: u32, e: f64) -> Self {
-        assert!(n >= 5);
-        let mut elevation = e;
-        while elevation < 0.0 {
-            elevation += WHOLE_ANGLE;
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
+// the region between the circles of radius r1 (inner) and r2 (outer)
+#[allow(dead_code)]
+pub struct Ring {
+    pub cx: f64,
+    pub cy: f64,
+    pub r1: f64,
+    pub r2: f64,
+}
+
+impl Sdf for Ring {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        let d = distance(p, (self.cx, self.cy));
+        (d - self.r2).max(self.r1 - d)
+    }
+}
+
+impl Shape for Ring {
+    fn aabb(&self) -> Option<Aabb> {
+        let r = self.r1.max(self.r2);
+        Some(Aabb::around(self.cx, self.cy, r, r))
+    }
+
+    fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
+        let outer = Circle {
+            cx: self.cx,
+            cy: self.cy,
+            r: self.r2,
+        };
+        let inner = Circle {
+            cx: self.cx,
+            cy: self.cy,
+            r: self.r1,
+        };
+        let mut result = outer.intersect(p, d);
+        for mut item in inner.intersect(p, d) {
+            item.normal = (-item.normal.0, -item.normal.1);
+            result.push(item);
         }
-        while elevation >= WHOLE_ANGLE {
-            elevation -= WHOLE_ANGLE
+        result
+    }
+
+    fn is_inside(&self, (px, py): (f64, f64)) -> bool {
+        let x = px - self.cx;
+        let y = py - self.cy;
+        let d2 = x * x + y * y;
+        self.r1 * self.r1 < d2 && d2 < self.r2 * self.r2
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
+// rotation by the elevation e (in degrees), counterclockwise on the image
+struct Rotation {
+    cos: f64,
+    sin: f64,
+}
+
+impl Rotation {
+    fn new(e: f64) -> Self {
+        let theta = -2.0 * PI * e / WHOLE_ANGLE;
+        Self {
+            cos: theta.cos(),
+            sin: theta.sin(),
         }
-        let cos = (PI / n as f64).cos();
-        let scaling_ratio = (cos * cos * 2.0 - 1.0) / cos;
-        Self::new((0..2 * n).map(|i| (i, i as f64 * PI / n as f64))
+    }
+
+    // world -> local
+    fn to_local(&self, x: f64, y: f64) -> (f64, f64) {
+        (x * self.cos + y * self.sin, y * self.cos - x * self.sin)
+    }
+
+    // local -> world
+    fn to_world(&self, x: f64, y: f64) -> (f64, f64) {
+        (x * self.cos - y * self.sin, x * self.sin + y * self.cos)
+    }
+
+    // the half size of the box around the local box of the half size (hx, hy)
+    fn extent(&self, hx: f64, hy: f64) -> (f64, f64) {
+        (
+            hx * self.cos.abs() + hy * self.sin.abs(),
+            hx * self.sin.abs() + hy * self.cos.abs(),
+        )
+    }
+}
+
+pub struct Ellipse {
+    cx: f64,
+    cy: f64,
+    rx: f64,
+    ry: f64,
+    // the axes of the ellipse are x and y in local
+    rotation: Rotation,
+}
+
+#[allow(dead_code)]
+impl Ellipse {
+    pub fn new(cx: f64, cy: f64, rx: f64, ry: f64, e: f64) -> Self {
+        Self {
+            cx,
+            cy,
+            rx,
+            ry,
+            rotation: Rotation::new(e),
+        }
+    }
+}
+
+impl Sdf for Ellipse {
+    // an approximation, exact on the boundary
+    fn sdf(&self, (px, py): (f64, f64)) -> f64 {
+        let (x, y) = self.rotation.to_local(px - self.cx, py - self.cy);
+        let (x0, y0) = (x / self.rx, y / self.ry);
+        let (x1, y1) = (x0 / self.rx, y0 / self.ry);
+        let k0 = (x0 * x0 + y0 * y0).sqrt();
+        let k1 = (x1 * x1 + y1 * y1).sqrt();
+        if k1 < EPSILON {
+            return -self.rx.min(self.ry);
+        }
+        k0 * (k0 - 1.0) / k1
+    }
+}
+
+impl Shape for Ellipse {
+    fn aabb(&self) -> Option<Aabb> {
+        let (hx, hy) = self.rotation.extent(self.rx, self.ry);
+        Some(Aabb::around(self.cx, self.cy, hx, hy))
+    }
+
+    fn intersect(&self, (px, py): (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
+        let (ox, oy) = self.rotation.to_local(px - self.cx, py - self.cy);
+        let (lx, ly) = self.rotation.to_local(dx, dy);
+        let rx2 = self.rx * self.rx;
+        let ry2 = self.ry * self.ry;
+        let a = lx * lx / rx2 + ly * ly / ry2;
+        let b = 2.0 * (ox * lx / rx2 + oy * ly / ry2);
+        let c = ox * ox / rx2 + oy * oy / ry2 - 1.0;
+        let delta = b * b - 4.0 * a * c;
+        let mut result: Vec<Intersection> = Vec::new();
+        if delta < 0.0 {
+            return result;
+        }
+        let sqrt = delta.sqrt();
+        for t in [(-b - sqrt) / (2.0 * a), (-b + sqrt) / (2.0 * a)] {
+            if t > EPSILON {
+                let (nx, ny) = self
+                    .rotation
+                    .to_world((ox + lx * t) / rx2, (oy + ly * t) / ry2);
+                let len = (nx * nx + ny * ny).sqrt();
+                result.push(Intersection {
+                    point: (px + dx * t, py + dy * t),
+                    normal: (nx / len, ny / len),
+                });
+            }
+        }
+        result
+    }
+
+    fn is_inside(&self, (px, py): (f64, f64)) -> bool {
+        let (x, y) = self.rotation.to_local(px - self.cx, py - self.cy);
+        x * x / (self.rx * self.rx) + y * y / (self.ry * self.ry) < 1.0
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
+// |x / rx| ^ n + |y / ry| ^ n < 1 in local, a squircle when n is 4
+pub struct Superellipse {
+    cx: f64,
+    cy: f64,
+    rx: f64,
+    ry: f64,
+    n: f64,
+    rotation: Rotation,
+}
+
+const SUPERELLIPSE_SAMPLES: u32 = 64;
+
+#[allow(dead_code)]
+impl Superellipse {
+    pub fn new(cx: f64, cy: f64, rx: f64, ry: f64, n: f64, e: f64) -> Result<Self, Error> {
+        if n <= 0.0 {
+            return Err(Error::Invalid(format!(
+                "the exponent of the superellipse must be positive, is {}",
+                n
+            )));
+        }
+        Ok(Self {
+            cx,
+            cy,
+            rx,
+            ry,
+            n,
+            rotation: Rotation::new(e),
+        })
+    }
+
+    // < 0 inside, > 0 outside, in local
+    fn implicit(&self, (x, y): (f64, f64)) -> f64 {
+        ((x / self.rx).abs().powf(self.n) + (y / self.ry).abs().powf(self.n)).powf(1.0 / self.n)
+            - 1.0
+    }
+}
+
+impl Sdf for Superellipse {
+    // an approximation, exact on the boundary
+    fn sdf(&self, (px, py): (f64, f64)) -> f64 {
+        let p = self.rotation.to_local(px - self.cx, py - self.cy);
+        self.implicit(p) * self.rx.min(self.ry)
+    }
+}
+
+impl Shape for Superellipse {
+    fn aabb(&self) -> Option<Aabb> {
+        let (hx, hy) = self.rotation.extent(self.rx, self.ry);
+        Some(Aabb::around(self.cx, self.cy, hx, hy))
+    }
+
+    fn intersect(&self, (px, py): (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
+        let mut result: Vec<Intersection> = Vec::new();
+        let (ox, oy) = self.rotation.to_local(px - self.cx, py - self.cy);
+        let (lx, ly) = self.rotation.to_local(dx, dy);
+
+        // the part of the ray inside the bounding box
+        let mut t0 = EPSILON;
+        let mut t1 = f64::INFINITY;
+        for (o, d, r) in [(ox, lx, self.rx), (oy, ly, self.ry)] {
+            if d.abs() < EPSILON {
+                if o.abs() > r {
+                    return result;
+                }
+            } else {
+                let a = (-r - o) / d;
+                let b = (r - o) / d;
+                t0 = t0.max(a.min(b));
+                t1 = t1.min(a.max(b));
+            }
+        }
+        if t0 >= t1 {
+            return result;
+        }
+
+        // sign changes of the implicit function, refined by bisection
+        let f = |t: f64| self.implicit((ox + lx * t, oy + ly * t));
+        let step = (t1 - t0) / SUPERELLIPSE_SAMPLES as f64;
+        let mut prev = f(t0);
+        for i in 1..=SUPERELLIPSE_SAMPLES {
+            let mut a = t0 + step * (i - 1) as f64;
+            let mut b = t0 + step * i as f64;
+            let next = f(b);
+            if prev * next <= 0.0 && prev != next {
+                for _ in 0..32 {
+                    let m = (a + b) * 0.5;
+                    if f(m) * prev > 0.0 {
+                        a = m;
+                    } else {
+                        b = m;
+                    }
+                }
+                let t = (a + b) * 0.5;
+                let (x, y) = (ox + lx * t, oy + ly * t);
+                let gx = x.signum() * (x / self.rx).abs().powf(self.n - 1.0) / self.rx;
+                let gy = y.signum() * (y / self.ry).abs().powf(self.n - 1.0) / self.ry;
+                let (nx, ny) = self.rotation.to_world(gx, gy);
+                let len = (nx * nx + ny * ny).sqrt();
+                if t > EPSILON && len > 0.0 {
+                    result.push(Intersection {
+                        point: (px + dx * t, py + dy * t),
+                        normal: (nx / len, ny / len),
+                    });
+                }
+            }
+            prev = next;
+        }
+        result
+    }
+
+    fn is_inside(&self, (px, py): (f64, f64)) -> bool {
+        self.implicit(self.rotation.to_local(px - self.cx, py - self.cy)) < 0.0
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
+// a rectangle like Polygon::rectangle, with corners rounded by radius r
+pub struct RoundedRect {
+    cx: f64,
+    cy: f64,
+    sx: f64,
+    sy: f64,
+    r: f64,
+    rotation: Rotation,
+}
+
+#[allow(dead_code)]
+impl RoundedRect {
+    pub fn new(cx: f64, cy: f64, e: f64, sx: f64, sy: f64, r: f64) -> Self {
+        Self {
+            cx,
+            cy,
+            sx,
+            sy,
+            // not clamp, which panics on a negative or nan size
+            r: r.max(0.0).min(sx.min(sy).max(0.0)),
+            rotation: Rotation::new(e),
+        }
+    }
+}
+
+impl Sdf for RoundedRect {
+    fn sdf(&self, (px, py): (f64, f64)) -> f64 {
+        let (x, y) = self.rotation.to_local(px - self.cx, py - self.cy);
+        let qx = x.abs() - (self.sx - self.r);
+        let qy = y.abs() - (self.sy - self.r);
+        let outside = (qx.max(0.0) * qx.max(0.0) + qy.max(0.0) * qy.max(0.0)).sqrt();
+        outside + qx.max(qy).min(0.0) - self.r
+    }
+}
+
+impl Shape for RoundedRect {
+    fn aabb(&self) -> Option<Aabb> {
+        let (hx, hy) = self.rotation.extent(self.sx, self.sy);
+        Some(Aabb::around(self.cx, self.cy, hx, hy))
+    }
+
+    fn intersect(&self, (px, py): (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
+        let mut result: Vec<Intersection> = Vec::new();
+        let (ox, oy) = self.rotation.to_local(px - self.cx, py - self.cy);
+        let (lx, ly) = self.rotation.to_local(dx, dy);
+        // the centers of the corner circles are (±ix, ±iy)
+        let ix = self.sx - self.r;
+        let iy = self.sy - self.r;
+        let mut push = |t: f64, (nx, ny): (f64, f64)| {
+            if t > EPSILON {
+                result.push(Intersection {
+                    point: (px + dx * t, py + dy * t),
+                    normal: self.rotation.to_world(nx, ny),
+                });
+            }
+        };
+
+        // the four sides
+        if lx.abs() > EPSILON {
+            for x in [self.sx, -self.sx] {
+                let t = (x - ox) / lx;
+                if (oy + ly * t).abs() <= iy {
+                    push(t, (x.signum(), 0.0));
+                }
+            }
+        }
+        if ly.abs() > EPSILON {
+            for y in [self.sy, -self.sy] {
+                let t = (y - oy) / ly;
+                if (ox + lx * t).abs() <= ix {
+                    push(t, (0.0, y.signum()));
+                }
+            }
+        }
+
+        // the four corners
+        if self.r > 0.0 {
+            for (cx, cy) in [(ix, iy), (-ix, iy), (-ix, -iy), (ix, -iy)] {
+                let corner = Circle { cx, cy, r: self.r };
+                for item in corner.intersect((ox, oy), (lx, ly)) {
+                    let (x, y) = item.point;
+                    if (x - cx) * cx.signum() > 0.0 && (y - cy) * cy.signum() > 0.0 {
+                        let t = if lx.abs() > ly.abs() {
+                            (x - ox) / lx
+                        } else {
+                            (y - oy) / ly
+                        };
+                        push(t, item.normal);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn is_inside(&self, p: (f64, f64)) -> bool {
+        self.sdf(p) < 0.0
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
+// the set of points whose distance to the segment (x1, y1)-(x2, y2) is less than r
+#[allow(dead_code)]
+pub struct Capsule {
+    pub x1: f64,
+    pub y1: f64,
+    pub x2: f64,
+    pub y2: f64,
+    pub r: f64,
+}
+
+impl Sdf for Capsule {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        distance(
+            p,
+            closest_on_segment(p, (self.x1, self.y1), (self.x2, self.y2)),
+        ) - self.r
+    }
+}
+
+impl Shape for Capsule {
+    fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points([(self.x1, self.y1), (self.x2, self.y2)]).map(|b| b.expand(self.r))
+    }
+
+    fn intersect(&self, (px, py): (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
+        let abx = self.x2 - self.x1;
+        let aby = self.y2 - self.y1;
+        let len2 = abx * abx + aby * aby;
+        if len2 < EPSILON * EPSILON {
+            let cap = Circle {
+                cx: self.x1,
+                cy: self.y1,
+                r: self.r,
+            };
+            return cap.intersect((px, py), (dx, dy));
+        }
+        // position of the projection on the segment, 0 at (x1, y1) and 1 at (x2, y2)
+        let h = |(x, y): (f64, f64)| ((x - self.x1) * abx + (y - self.y1) * aby) / len2;
+        let mut result: Vec<Intersection> = Vec::new();
+
+        // the two sides
+        let len = len2.sqrt();
+        let nx = -aby / len;
+        let ny = abx / len;
+        let a = dx * nx + dy * ny;
+        if a.abs() > EPSILON {
+            let b = (px - self.x1) * nx + (py - self.y1) * ny;
+            for t in [(self.r - b) / a, (-self.r - b) / a] {
+                let point = (px + dx * t, py + dy * t);
+                if t > EPSILON && (0.0..=1.0).contains(&h(point)) {
+                    let normal = if a * t + b > 0.0 {
+                        (nx, ny)
+                    } else {
+                        (-nx, -ny)
+                    };
+                    result.push(Intersection { point, normal });
+                }
+            }
+        }
+
+        // the two caps
+        for (cx, cy, start) in [(self.x1, self.y1, true), (self.x2, self.y2, false)] {
+            let cap = Circle { cx, cy, r: self.r };
+            for item in cap.intersect((px, py), (dx, dy)) {
+                let h = h(item.point);
+                if (start && h < 0.0) || (!start && h > 1.0) {
+                    result.push(item);
+                }
+            }
+        }
+        result
+    }
+
+    fn is_inside(&self, p: (f64, f64)) -> bool {
+        let c = closest_on_segment(p, (self.x1, self.y1), (self.x2, self.y2));
+        distance(p, c) < self.r
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
+#[allow(dead_code)]
+pub struct Plane {
+    pub px: f64,
+    pub py: f64,
+    pub nx: f64,
+    pub ny: f64,
+}
+
+impl Sdf for Plane {
+    fn sdf(&self, (px, py): (f64, f64)) -> f64 {
+        let len = (self.nx * self.nx + self.ny * self.ny).sqrt();
+        ((px - self.px) * self.nx + (py - self.py) * self.ny) / len
+    }
+}
+
+impl Shape for Plane {
+    fn intersect(&self, (px, py): (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
+        let mut result: Vec<Intersection> = Vec::new();
+        let a = dx * self.nx + dy * self.ny;
+        if a.abs() < EPSILON {
+            result
+        } else {
+            let b = (self.px - px) * self.nx + (self.py - py) * self.ny;
+            let t = b / a;
+            if t > EPSILON {
+                result.push(Intersection {
+                    point: (px + dx * t, py + dy * t),
+                    normal: (self.nx, self.ny),
+                });
+            }
+            result
+        }
+    }
+
+    fn is_inside(&self, (px, py): (f64, f64)) -> bool {
+        (px - self.px) * self.nx + (py - self.py) * self.ny < 0.0
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
+pub struct Polygon {
+    points: Vec<(f64, f64)>, // counterclockwise
+}
+
+#[allow(dead_code)]
+impl Polygon {
+    pub fn new(p: Vec<(f64, f64)>) -> Result<Self, Error> {
+        if p.len() > 1 {
+            Ok(Self {
+                points: p,
+            })
+        } else {
+            Err(Error::Invalid(format!(
+                "too few points for a polygon, {}",
+                p.len()
+            )))
+        }
+    }
+
+    pub fn rectangle(cx: f64, cy: f64, e: f64, sx: f64, sy: f64) -> Self {
+        let mut elevation = e;
+        while elevation < 0.0 {
+            elevation += WHOLE_ANGLE;
+        }
+        while elevation >= WHOLE_ANGLE {
+            elevation -= WHOLE_ANGLE
+        }
+        let theta = -2.0 * PI * elevation / WHOLE_ANGLE;
+        Self { points: [(sx, -sy), (-sx, -sy), (-sx, sy), (sx, sy)].iter()
+            .map(|&(x, y)| (x * theta.cos() - y * theta.sin(), x * theta.sin() + y * theta.cos()))
+            .map(|(x, y)| (x + cx, y + cy))
+            .collect() }
+    }
+
+    pub fn regular(cx: f64, cy: f64, r: f64, n: u32, e: f64) -> Result<Self, Error> {
+        let mut elevation = e;
+        while elevation < 0.0 {
+            elevation += WHOLE_ANGLE;
+        }
+        while elevation >= WHOLE_ANGLE {
+            elevation -= WHOLE_ANGLE
+        }
+        Self::new((0..n).map(|i| i as f64 * 2.0 * PI / n as f64)
+            .map(|theta| theta + 2.0 * PI * elevation / WHOLE_ANGLE)
+            .map(|theta| (r * theta.cos(), r * theta.sin()))
+            .map(|(x, y)| (cx + x, cy - y))
+            .collect())
+    }
+
+    pub fn star(cx: f64, cy: f64, r: f64, n: u32, e: f64) -> Result<Self, Error> {
+        if n < 5 {
+            return Err(Error::Invalid(format!("too few points for a star, {}, at least 5", n)));
+        }
+        let mut elevation = e;
+        while elevation < 0.0 {
+            elevation += WHOLE_ANGLE;
+        }
+        while elevation >= WHOLE_ANGLE {
+            elevation -= WHOLE_ANGLE
+        }
+        let cos = (PI / n as f64).cos();
+        let scaling_ratio = (cos * cos * 2.0 - 1.0) / cos;
+        Self::new((0..2 * n).map(|i| (i, i as f64 * PI / n as f64))
             .map(|(i, theta)| (i, theta + 2.0 * PI * elevation / WHOLE_ANGLE))
             .map(|(i, theta)| {
                 let mut l = r;
                 if i % 2 == 1 {
-                    l = l * scaling_ratio;
+                    l *= scaling_ratio;
                 }
                 (l * theta.cos(), l * theta.sin())
             })
-            .map(|(x, y)| (cx + x, cy - y))
-            .collect())
+            .map(|(x, y)| (cx + x, cy - y))
+            .collect())
+    }
+
+    // the heart curve, its top and bottom are about r away from (cx, cy)
+    pub fn heart(cx: f64, cy: f64, r: f64, e: f64) -> Self {
+        let n = 96;
+        let s = r / 16.0;
+        Self::locate(
+            cx,
+            cy,
+            e,
+            (0..n).map(|i| 2.0 * PI * i as f64 / n as f64).map(|t| {
+                let x = -16.0 * t.sin().powi(3);
+                let y = 13.0 * t.cos()
+                    - 5.0 * (2.0 * t).cos()
+                    - 2.0 * (3.0 * t).cos()
+                    - (4.0 * t).cos();
+                (x * s, (y + 2.0) * s)
+            }),
+        )
+    }
+
+    // the circle of radius r minus the same circle moved d to the right (0 < d < 2r)
+    pub fn crescent(cx: f64, cy: f64, r: f64, d: f64, e: f64) -> Result<Self, Error> {
+        if d <= 0.0 || d >= 2.0 * r {
+            return Err(Error::Invalid(format!(
+                "the offset of the crescent must be in (0, 2r), is {}",
+                d
+            )));
+        }
+        let n = 48;
+        let alpha = (r * r - d * d / 4.0).sqrt().atan2(d / 2.0);
+        let outer = (0..=n)
+            .map(|i| alpha + (2.0 * PI - 2.0 * alpha) * i as f64 / n as f64)
+            .map(|theta| (r * theta.cos(), r * theta.sin()));
+        let inner = (1..n)
+            .map(|i| PI + alpha - 2.0 * alpha * i as f64 / n as f64)
+            .map(|theta| (d + r * theta.cos(), r * theta.sin()));
+        Ok(Self::locate(cx, cy, e, outer.chain(inner)))
+    }
+
+    // n trapezoidal teeth between the radius r1 (root) and r2 (tip)
+    pub fn gear(cx: f64, cy: f64, r1: f64, r2: f64, n: u32, e: f64) -> Result<Self, Error> {
+        if n < 3 {
+            return Err(Error::Invalid(format!("too few teeth for a gear, {}, at least 3", n)));
+        }
+        let pitch = 2.0 * PI / n as f64;
+        Ok(Self::locate(
+            cx,
+            cy,
+            e,
+            (0..n).flat_map(|i| {
+                let theta = pitch * i as f64;
+                [(r1, 0.0), (r2, 0.25), (r2, 0.5), (r1, 0.75)]
+                    .map(|(l, k)| (l * (theta + pitch * k).cos(), l * (theta + pitch * k).sin()))
+            }),
+        ))
+    }
+
+    // an arrow of length l pointing right, with a shaft of width w and a head twice as wide
+    pub fn arrow(cx: f64, cy: f64, l: f64, w: f64, e: f64) -> Self {
+        let tip = l / 2.0;
+        let head = tip - (2.0 * w).min(l / 2.0);
+        Self::locate(
+            cx,
+            cy,
+            e,
+            [
+                (tip, 0.0),
+                (head, w),
+                (head, w / 2.0),
+                (-tip, w / 2.0),
+                (-tip, -w / 2.0),
+                (head, -w / 2.0),
+                (head, -w),
+            ]
+            .into_iter(),
+        )
+    }
+
+    // twice the signed area, negative when the points are counterclockwise on the image
+    fn signed_area(&self) -> f64 {
+        (0..self.points.len())
+            .map(|i| {
+                let (x0, y0) = self.points[i];
+                let (x1, y1) = self.points[(i + 1) % self.points.len()];
+                x0 * y1 - x1 * y0
+            })
+            .sum()
+    }
+
+    // points given counterclockwise around the origin with y axis up, rotated by e
+    // and moved to (cx, cy)
+    fn locate(cx: f64, cy: f64, e: f64, points: impl Iterator<Item = (f64, f64)>) -> Self {
+        let rotation = Rotation::new(e);
+        Self {
+            points: points
+                .map(|(x, y)| rotation.to_world(x, -y))
+                .map(|(x, y)| (cx + x, cy + y))
+                .collect(),
+        }
+    }
+}
+
+impl Sdf for Polygon {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        let mut d = f64::INFINITY;
+        for i in 0..self.points.len() {
+            let a = self.points[i];
+            let b = self.points[(i + 1) % self.points.len()];
+            d = d.min(distance(p, closest_on_segment(p, a, b)));
+        }
+        if self.is_inside(p) {
+            -d
+        } else {
+            d
+        }
+    }
+}
+
+impl Shape for Polygon {
+    fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(self.points.iter().copied())
+    }
+
+    // every edge against the 4 rays, keeping the nearest edge of each
+    fn nearest4(&self, p: [(f64, f64); 4], d: [(f64, f64); 4], t_max: [f64; 4]) -> [Hit; 4] {
+        let ((px, py), (dx, dy)) = (lanes(p), lanes(d));
+        let epsilon = f64x4::splat(EPSILON);
+        let mut best = f64x4::from(t_max);
+        let mut edge = [None; 4];
+        for i in 0..self.points.len() {
+            let a = self.points[i];
+            let b = self.points[(i + 1) % self.points.len()];
+            let ax = f64x4::splat(a.0) - px;
+            let ay = f64x4::splat(a.1) - py;
+            let bx = f64x4::splat(b.0) - px;
+            let by = f64x4::splat(b.1) - py;
+            let product1 = ax * dy - dx * ay;
+            let product2 = bx * dy - dx * by;
+            let nx = a.1 - b.1;
+            let ny = b.0 - a.0;
+            let len = (nx * nx + ny * ny).sqrt();
+            let (nx, ny) = (f64x4::splat(nx / len), f64x4::splat(ny / len));
+            let c1 = dx * nx + dy * ny;
+            let t = (ax * nx + ay * ny) / c1;
+            let valid = (product1 * product2).simd_lt(f64x4::ZERO)
+                & c1.abs().simd_gt(epsilon)
+                & t.simd_gt(epsilon)
+                & t.simd_lt(best);
+            let mask = valid.to_bitmask();
+            if mask != 0 {
+                best = valid.select(t, best);
+                for (j, e) in edge.iter_mut().enumerate() {
+                    if mask & (1 << j) != 0 {
+                        *e = Some(i);
+                    }
+                }
+            }
+        }
+        let t = best.to_array();
+        std::array::from_fn(|j| {
+            let i = edge[j]?;
+            let (a, b) = (self.points[i], self.points[(i + 1) % self.points.len()]);
+            let nx = a.1 - b.1;
+            let ny = b.0 - a.0;
+            let len = (nx * nx + ny * ny).sqrt();
+            Some((
+                Intersection {
+                    point: (p[j].0 + d[j].0 * t[j], p[j].1 + d[j].1 * t[j]),
+                    normal: (nx / len, ny / len),
+                },
+                t[j],
+            ))
+        })
+    }
+
+    fn intersect(&self, (px, py): (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
+        let mut result: Vec<Intersection> = Vec::new();
+        for i in 0..self.points.len() {
+            let a = self.points[i];
+            let b = if i + 1 == self.points.len() {
+                self.points[0]
+            } else {
+                self.points[i + 1]
+            };
+            let ax = a.0 - px;
+            let ay = a.1 - py;
+            let bx = b.0 - px;
+            let by = b.1 - py;
+            let product1 = ax * dy - dx * ay;
+            let product2 = bx * dy - dx * by;
+            if product1 * product2 < 0.0 {
+                let nx = a.1 - b.1;
+                let ny = b.0 - a.0;
+                let len = (nx * nx + ny * ny).sqrt();
+                let nx = nx / len;
+                let ny = ny / len;
+                let c1 = dx * nx + dy * ny;
+                if c1.abs() > EPSILON {
+                    let c2 = (a.0 - px) * nx + (a.1 - py) * ny;
+                    let t = c2 / c1;
+                    if t > EPSILON {
+                        result.push(Intersection {
+                            point: (px + dx * t, py + dy * t),
+                            normal: (nx, ny),
+                        });
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn is_inside(&self, (px, py): (f64, f64)) -> bool {
+        let mut cross_count = 0;
+        for i in 0..self.points.len() {
+            let (x0, y0) = self.points[i];
+            let mut j = i + 1;
+            if j >= self.points.len() {
+                j = 0
+            }
+            let (x1, y1) = self.points[j];
+
+            // a vertical edge is along the vertical ray through p, it never crosses it. the
+            // edges before and after it count the crossing at its ends, each with one end
+            // included, so counting it too made a point of the x of the edge inside when
+            // outside, and the other way, whatever its height
+            if (x1 - x0).abs() < EPSILON {
+                continue;
+            }
+
+            let slope = (y1 - y0) / (x1 - x0);
+            let cond1 = (x0 <= px) && (px < x1);
+            let cond2 = (x1 <= px) && (px < x0);
+            let above = py < slope * (px - x0) + y0;
+            if (cond1 || cond2) && above {
+                cross_count += 1;
+            }
+        }
+        cross_count % 2 != 0
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
+// a region bounded by several polygons with the even-odd rule, inner polygons are holes
+pub struct Contours {
+    c: Vec<Polygon>,
+}
+
+#[allow(dead_code)]
+impl Contours {
+    pub fn new(contours: Vec<Vec<(f64, f64)>>) -> Self {
+        let c: Vec<Polygon> = contours
+            .into_iter()
+            .filter(|points| points.len() > 2)
+            .map(|points| Polygon { points })
+            .collect();
+        // make the normals of every polygon point out of the region
+        let holes: Vec<bool> = (0..c.len())
+            .map(|i| {
+                let depth = (0..c.len())
+                    .filter(|&j| j != i && c[j].is_inside(c[i].points[0]))
+                    .count();
+                depth % 2 == 1
+            })
+            .collect();
+        let c = c
+            .into_iter()
+            .zip(holes)
+            .map(|(mut polygon, hole)| {
+                if (polygon.signed_area() > 0.0) != hole {
+                    polygon.points.reverse();
+                }
+                polygon
+            })
+            .collect();
+        Self { c }
+    }
+}
+
+impl Sdf for Contours {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        let d = self
+            .c
+            .iter()
+            .map(|polygon| polygon.sdf(p).abs())
+            .fold(f64::INFINITY, f64::min);
+        if self.is_inside(p) {
+            -d
+        } else {
+            d
+        }
+    }
+}
+
+impl Shape for Contours {
+    fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(
+            self.c
+                .iter()
+                .flat_map(|polygon| polygon.points.iter().copied()),
+        )
+    }
+
+    fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
+        self.c
+            .iter()
+            .flat_map(|polygon| polygon.intersect(p, d))
+            .collect()
+    }
+
+    fn is_inside(&self, p: (f64, f64)) -> bool {
+        self.c.iter().filter(|polygon| polygon.is_inside(p)).count() % 2 == 1
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
+
+// a closed path of cubic Bézier curves, counterclockwise
+pub struct BezierPath {
+    curves: Vec<[(f64, f64); 4]>,
+    // the flattened path, used to find the rough position of the intersections
+    outline: Polygon,
+}
+
+const BEZIER_PIECES: usize = 32;
+
+fn bezier_point(c: &[(f64, f64); 4], u: f64) -> (f64, f64) {
+    let v = 1.0 - u;
+    let (a, b, cc, d) = (v * v * v, 3.0 * v * v * u, 3.0 * v * u * u, u * u * u);
+    (
+        a * c[0].0 + b * c[1].0 + cc * c[2].0 + d * c[3].0,
+        a * c[0].1 + b * c[1].1 + cc * c[2].1 + d * c[3].1,
+    )
+}
+
+fn bezier_tangent(c: &[(f64, f64); 4], u: f64) -> (f64, f64) {
+    let v = 1.0 - u;
+    let (a, b, d) = (3.0 * v * v, 6.0 * v * u, 3.0 * u * u);
+    (
+        a * (c[1].0 - c[0].0) + b * (c[2].0 - c[1].0) + d * (c[3].0 - c[2].0),
+        a * (c[1].1 - c[0].1) + b * (c[2].1 - c[1].1) + d * (c[3].1 - c[2].1),
+    )
+}
+
+#[allow(dead_code)]
+impl BezierPath {
+    // p0, c1, c2, p1, c1, c2, p2, ..., c1, c2; the last curve goes back to p0
+    pub fn new(p: Vec<(f64, f64)>) -> Result<Self, Error> {
+        if p.len() < 3 || !p.len().is_multiple_of(3) {
+            return Err(Error::Invalid(format!(
+                "the number of control points must be a multiple of 3, is {}",
+                p.len()
+            )));
+        }
+        let curves: Vec<[(f64, f64); 4]> = (0..p.len() / 3)
+            .map(|i| {
+                [
+                    p[3 * i],
+                    p[3 * i + 1],
+                    p[3 * i + 2],
+                    p[(3 * i + 3) % p.len()],
+                ]
+            })
+            .collect();
+        let outline = Polygon {
+            points: curves
+                .iter()
+                .flat_map(|c| {
+                    (0..BEZIER_PIECES)
+                        .map(move |i| bezier_point(c, i as f64 / BEZIER_PIECES as f64))
+                })
+                .collect(),
+        };
+        Ok(Self { curves, outline })
     }
 }
 
-impl Shape for Polygon {
+impl Sdf for BezierPath {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        self.outline.sdf(p)
+    }
+}
+
+impl Shape for BezierPath {
+    fn aabb(&self) -> Option<Aabb> {
+        // the curves are inside the hull of their control points
+        Aabb::from_points(self.curves.iter().flatten().copied())
+    }
+
     fn intersect(&self, (px, py): (f64, f64), (dx, dy): (f64, f64)) -> Vec<Intersection> {
         let mut result: Vec<Intersection> = Vec::new();
-        for i in 0..self.points.len() {
-            let a = self.points[i];
-            let b = if i + 1 == self.points.len() {
-                self.points[0]
-            } else {
-                self.points[i + 1]
-            };
-            let ax = a.0 - px;
-            let ay = a.1 - py;
-            let bx = b.0 - px;
-            let by = b.1 - py;
-            let product1 = ax * dy - dx * ay;
-            let product2 = bx * dy - dx * by;
-            if product1 * product2 < 0.0 {
-                let nx = a.1 - b.1;
-                let ny = b.0 - a.0;
-                let len = (nx * nx + ny * ny).sqrt();
-                let nx = nx / len;
-                let ny = ny / len;
-                let c1 = dx * nx + dy * ny;
-                if c1.abs() > EPSILON {
-                    let c2 = (a.0 - px) * nx + (a.1 - py) * ny;
-                    let t = c2 / c1;
-                    if t > EPSILON {
-                        result.push(Intersection {
-                            point: (px + dx * t, py + dy * t),
-                            normal: (nx, ny),
-                        });
-                    }
+        let points = &self.outline.points;
+        for i in 0..points.len() {
+            let a = points[i];
+            let b = points[(i + 1) % points.len()];
+            let product1 = (a.0 - px) * dy - dx * (a.1 - py);
+            let product2 = (b.0 - px) * dy - dx * (b.1 - py);
+            if product1 * product2 >= 0.0 {
+                continue;
+            }
+            // refine the crossing on the curve with newton's method
+            let c = &self.curves[i / BEZIER_PIECES];
+            let u0 = (i % BEZIER_PIECES) as f64 / BEZIER_PIECES as f64;
+            let u1 = u0 + 1.0 / BEZIER_PIECES as f64;
+            let mut u = u0 + (u1 - u0) * product1 / (product1 - product2);
+            for _ in 0..4 {
+                let (x, y) = bezier_point(c, u);
+                let (tx, ty) = bezier_tangent(c, u);
+                let g = (x - px) * dy - dx * (y - py);
+                let dg = tx * dy - dx * ty;
+                if dg.abs() < EPSILON {
+                    break;
                 }
+                u = (u - g / dg).clamp(u0, u1);
+            }
+            let (x, y) = bezier_point(c, u);
+            let t = ((x - px) * dx + (y - py) * dy) / (dx * dx + dy * dy);
+            if t > EPSILON {
+                let (tx, ty) = bezier_tangent(c, u);
+                let len = (tx * tx + ty * ty).sqrt();
+                result.push(Intersection {
+                    point: (px + dx * t, py + dy * t),
+                    normal: (-ty / len, tx / len),
+                });
             }
         }
         result
     }
 
-    fn is_inside(&self, (px, py): (f64, f64)) -> bool {
-        let mut cross_count = 0;
-        for i in 0..self.points.len() {
-            let (x0, y0) = self.points[i];
-            let mut j = i + 1;
-            if j >= self.points.len() {
-                j = 0
-            }
-            let (x1, y1) = self.points[j];
+    fn is_inside(&self, p: (f64, f64)) -> bool {
+        self.outline.is_inside(p)
+    }
 
-            if (x1 - x0).abs() < EPSILON {
-                if (px - x0) * (px - x1) > 0.0 {
-                    continue;
-                }
-                cross_count += 1;
-                continue;
-            }
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        Some(self)
+    }
+}
 
-            let slope = (y1 - y0) as f64 / (x1 - x0) as f64;
-            let cond1 = (x0 <= px) && (px < x1);
-            let cond2 = (x1 <= px) && (px < x0);
-            let above = py < slope * (px - x0) + y0;
-            if (cond1 || cond2) && above {
-                cross_count += 1;
-            }
+// the shape a scaled by (sx, sy), then rotated by e, then translated by (tx, ty)
+pub struct TransformedShape {
+    a: Box<dyn Shape + Sync>,
+    tx: f64,
+    ty: f64,
+    sx: f64,
+    sy: f64,
+    rotation: Rotation,
+}
+
+#[allow(dead_code)]
+impl TransformedShape {
+    // the points are divided by the scale to go back to the shape
+    pub fn new(
+        a: Box<dyn Shape + Sync>,
+        (tx, ty): (f64, f64),
+        e: f64,
+        (sx, sy): (f64, f64),
+    ) -> Result<Self, Error> {
+        if sx == 0.0 || sy == 0.0 || !sx.is_finite() || !sy.is_finite() {
+            return Err(Error::Invalid(format!(
+                "the scale of a transform must be finite and not 0, is ({}, {})",
+                sx, sy
+            )));
         }
-        cross_count % 2 != 0
+        Ok(Self {
+            a,
+            tx,
+            ty,
+            sx,
+            sy,
+            rotation: Rotation::new(e),
+        })
+    }
+
+    fn to_local(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (x, y) = self.rotation.to_local(x, y);
+        (x / self.sx, y / self.sy)
+    }
+
+    fn to_world_intersection(&self, item: &Intersection) -> Intersection {
+        let (x, y) = self
+            .rotation
+            .to_world(item.point.0 * self.sx, item.point.1 * self.sy);
+        let (nx, ny) = self
+            .rotation
+            .to_world(item.normal.0 / self.sx, item.normal.1 / self.sy);
+        let len = (nx * nx + ny * ny).sqrt();
+        Intersection {
+            point: (x + self.tx, y + self.ty),
+            normal: (nx / len, ny / len),
+        }
+    }
+}
+
+impl Sdf for TransformedShape {
+    // a lower bound when the scale is not uniform
+    fn sdf(&self, (px, py): (f64, f64)) -> f64 {
+        let d = sdf_of(self.a.as_ref(), self.to_local((px - self.tx, py - self.ty)));
+        d * self.sx.abs().min(self.sy.abs())
+    }
+}
+
+impl Shape for TransformedShape {
+    fn aabb(&self) -> Option<Aabb> {
+        let b = self.a.aabb()?;
+        Aabb::from_points(b.corners().map(|(x, y)| {
+            let (x, y) = self.rotation.to_world(x * self.sx, y * self.sy);
+            (x + self.tx, y + self.ty)
+        }))
+    }
+
+    fn intersect(&self, (px, py): (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
+        let p = self.to_local((px - self.tx, py - self.ty));
+        let d = self.to_local(d);
+        self.a
+            .intersect(p, d)
+            .iter()
+            .map(|item| self.to_world_intersection(item))
+            .collect()
+    }
+
+    // the ray parameter is the same in the local space
+    fn nearest(
+        &self,
+        (px, py): (f64, f64),
+        d: (f64, f64),
+        t_max: f64,
+    ) -> Option<(Intersection, f64)> {
+        let p = self.to_local((px - self.tx, py - self.ty));
+        let d = self.to_local(d);
+        let (item, t) = self.a.nearest(p, d, t_max)?;
+        Some((self.to_world_intersection(&item), t))
+    }
+
+    fn nearest4(&self, p: [(f64, f64); 4], d: [(f64, f64); 4], t_max: [f64; 4]) -> [Hit; 4] {
+        let p = p.map(|(px, py)| self.to_local((px - self.tx, py - self.ty)));
+        let d = d.map(|d| self.to_local(d));
+        self.a
+            .nearest4(p, d, t_max)
+            .map(|hit| hit.map(|(item, t)| (self.to_world_intersection(&item), t)))
+    }
+
+    fn is_inside(&self, (px, py): (f64, f64)) -> bool {
+        self.a
+            .is_inside(self.to_local((px - self.tx, py - self.ty)))
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        (self.a.as_sdf().is_some()).then_some(self as &dyn Sdf)
     }
 }
 
@@ -276,7 +1892,20 @@ pub struct UnionShape {
     pub c: Vec<Box<dyn Shape + Sync>>,
 }
 
+impl Sdf for UnionShape {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        self.c
+            .iter()
+            .map(|item| sdf_of(item.as_ref(), p))
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
 impl Shape for UnionShape {
+    fn aabb(&self) -> Option<Aabb> {
+        union_aabb(&self.c)
+    }
+
     fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
         let length = self.c.len();
         let mut result: Vec<Intersection> = Vec::new();
@@ -309,6 +1938,21 @@ impl Shape for UnionShape {
         result
     }
 
+    // t_max shrinks with every child hit, so farther hits are not tested against the others
+    fn nearest(&self, p: (f64, f64), d: (f64, f64), mut t_max: f64) -> Option<(Intersection, f64)> {
+        let mut result = None;
+        for i in 0..self.c.len() {
+            let hit = first_hit(self.c[i].as_ref(), p, d, t_max, |item| {
+                (0..self.c.len()).all(|j| i == j || !self.c[j].is_inside(item.point))
+            });
+            if let Some(hit) = hit {
+                t_max = hit.1;
+                result = Some(hit);
+            }
+        }
+        result
+    }
+
     fn is_inside(&self, p: (f64, f64)) -> bool {
         let mut result = false;
         self.c.iter().for_each(|item| {
@@ -318,6 +1962,10 @@ impl Shape for UnionShape {
         });
         result
     }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        (have_sdf(&self.c)).then_some(self as &dyn Sdf)
+    }
 }
 
 #[allow(dead_code)]
@@ -325,7 +1973,23 @@ pub struct IntersectShape {
     pub c: Vec<Box<dyn Shape + Sync>>,
 }
 
+impl Sdf for IntersectShape {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        self.c
+            .iter()
+            .map(|item| sdf_of(item.as_ref(), p))
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+}
+
 impl Shape for IntersectShape {
+    fn aabb(&self) -> Option<Aabb> {
+        self.c
+            .iter()
+            .filter_map(|item| item.aabb())
+            .reduce(|a, b| a.intersection(&b))
+    }
+
     fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
         let length = self.c.len();
         let mut result: Vec<Intersection> = Vec::new();
@@ -358,6 +2022,20 @@ impl Shape for IntersectShape {
         result
     }
 
+    fn nearest(&self, p: (f64, f64), d: (f64, f64), mut t_max: f64) -> Option<(Intersection, f64)> {
+        let mut result = None;
+        for i in 0..self.c.len() {
+            let hit = first_hit(self.c[i].as_ref(), p, d, t_max, |item| {
+                (0..self.c.len()).all(|j| i == j || self.c[j].is_inside(item.point))
+            });
+            if let Some(hit) = hit {
+                t_max = hit.1;
+                result = Some(hit);
+            }
+        }
+        result
+    }
+
     fn is_inside(&self, p: (f64, f64)) -> bool {
         let mut result = true;
         self.c.iter().for_each(|item| {
@@ -367,6 +2045,178 @@ impl Shape for IntersectShape {
         });
         result
     }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        (have_sdf(&self.c)).then_some(self as &dyn Sdf)
+    }
+}
+
+// a minus every shape of c
+#[allow(dead_code)]
+pub struct DifferenceShape {
+    pub a: Box<dyn Shape + Sync>,
+    pub c: Vec<Box<dyn Shape + Sync>>,
+}
+
+impl Sdf for DifferenceShape {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        self.c
+            .iter()
+            .map(|cutter| -sdf_of(cutter.as_ref(), p))
+            .fold(sdf_of(self.a.as_ref(), p), f64::max)
+    }
+}
+
+impl Shape for DifferenceShape {
+    fn aabb(&self) -> Option<Aabb> {
+        self.a.aabb()
+    }
+
+    fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
+        let mut result: Vec<Intersection> = Vec::new();
+        for item in self.a.intersect(p, d) {
+            if !self.c.iter().any(|cutter| cutter.is_inside(item.point)) {
+                result.push(item);
+            }
+        }
+        for i in 0..self.c.len() {
+            for item in self.c[i].intersect(p, d) {
+                if !self.a.is_inside(item.point) {
+                    continue;
+                }
+                let mut check = true;
+                for j in 0..self.c.len() {
+                    if i != j && self.c[j].is_inside(item.point) {
+                        check = false;
+                        break;
+                    }
+                }
+                if check {
+                    let mut opposite_item = item;
+                    opposite_item.normal.0 = -opposite_item.normal.0;
+                    opposite_item.normal.1 = -opposite_item.normal.1;
+                    result.push(opposite_item);
+                }
+            }
+        }
+        result
+    }
+
+    fn nearest(&self, p: (f64, f64), d: (f64, f64), mut t_max: f64) -> Option<(Intersection, f64)> {
+        let mut result = first_hit(self.a.as_ref(), p, d, t_max, |item| {
+            !self.c.iter().any(|cutter| cutter.is_inside(item.point))
+        });
+        if let Some(hit) = &result {
+            t_max = hit.1;
+        }
+        for i in 0..self.c.len() {
+            let hit = first_hit(self.c[i].as_ref(), p, d, t_max, |item| {
+                self.a.is_inside(item.point)
+                    && (0..self.c.len()).all(|j| i == j || !self.c[j].is_inside(item.point))
+            });
+            if let Some((mut item, t)) = hit {
+                item.normal = (-item.normal.0, -item.normal.1);
+                t_max = t;
+                result = Some((item, t));
+            }
+        }
+        result
+    }
+
+    fn is_inside(&self, p: (f64, f64)) -> bool {
+        self.a.is_inside(p) && !self.c.iter().any(|cutter| cutter.is_inside(p))
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        (self.a.as_sdf().is_some() && have_sdf(&self.c)).then_some(self as &dyn Sdf)
+    }
+}
+
+// the min without a blend for k not above 0, which would divide by it
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+// the union of c, blended where the shapes are closer than k
+#[allow(dead_code)]
+pub struct SmoothUnionShape {
+    pub c: Vec<Box<dyn Shape + Sync>>,
+    pub k: f64,
+}
+
+impl Sdf for SmoothUnionShape {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        // the ones without an sdf are left out, smooth_min of an infinite distance is not a number
+        let mut ds = self
+            .c
+            .iter()
+            .filter_map(|item| item.as_sdf())
+            .map(|item| item.sdf(p));
+        match ds.next() {
+            Some(first) => ds.fold(first, |a, b| smooth_min(a, b, self.k)),
+            None => f64::INFINITY,
+        }
+    }
+}
+
+impl Shape for SmoothUnionShape {
+    fn aabb(&self) -> Option<Aabb> {
+        // the blend only reaches k / 4 out of the shapes, none for k not above 0
+        union_aabb(&self.c).map(|b| b.expand(self.k.max(0.0)))
+    }
+
+    fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
+        march(self, p, d)
+    }
+
+    fn is_inside(&self, p: (f64, f64)) -> bool {
+        self.sdf(p) < 0.0
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        (have_sdf(&self.c)).then_some(self as &dyn Sdf)
+    }
+}
+
+// a minus every shape of c, the cuts are blended within k
+#[allow(dead_code)]
+pub struct SmoothSubtractShape {
+    pub a: Box<dyn Shape + Sync>,
+    pub c: Vec<Box<dyn Shape + Sync>>,
+    pub k: f64,
+}
+
+impl Sdf for SmoothSubtractShape {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        self.c
+            .iter()
+            .filter_map(|cutter| cutter.as_sdf())
+            .fold(sdf_of(self.a.as_ref(), p), |d, cutter| {
+                -smooth_min(-d, cutter.sdf(p), self.k)
+            })
+    }
+}
+
+impl Shape for SmoothSubtractShape {
+    fn aabb(&self) -> Option<Aabb> {
+        self.a.aabb()
+    }
+
+    fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
+        march(self, p, d)
+    }
+
+    fn is_inside(&self, p: (f64, f64)) -> bool {
+        self.sdf(p) < 0.0
+    }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        (self.a.as_sdf().is_some() && have_sdf(&self.c)).then_some(self as &dyn Sdf)
+    }
 }
 
 #[allow(dead_code)]
@@ -374,6 +2224,12 @@ pub struct ComplementShape {
     pub a: Box<dyn Shape + Sync>,
 }
 
+impl Sdf for ComplementShape {
+    fn sdf(&self, p: (f64, f64)) -> f64 {
+        -sdf_of(self.a.as_ref(), p)
+    }
+}
+
 impl Shape for ComplementShape {
     fn intersect(&self, p: (f64, f64), d: (f64, f64)) -> Vec<Intersection> {
         let mut result: Vec<Intersection> = Vec::new();
@@ -386,9 +2242,19 @@ impl Shape for ComplementShape {
         result
     }
 
+    fn nearest(&self, p: (f64, f64), d: (f64, f64), t_max: f64) -> Option<(Intersection, f64)> {
+        let (mut item, t) = self.a.nearest(p, d, t_max)?;
+        item.normal = (-item.normal.0, -item.normal.1);
+        Some((item, t))
+    }
+
     fn is_inside(&self, p: (f64, f64)) -> bool {
         !self.a.is_inside(p)
     }
+
+    fn as_sdf(&self) -> Option<&dyn Sdf> {
+        (self.a.as_sdf().is_some()).then_some(self as &dyn Sdf)
+    }
 }
 
 #[allow(dead_code)]
@@ -398,4 +2264,285 @@ impl ComplementShape {
             a
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn assert_hit(hit: Hit, point: (f64, f64), normal: (f64, f64), t: f64) {
+        let (item, s) = hit.expect("a hit");
+        assert!(
+            close(item.point.0, point.0) && close(item.point.1, point.1),
+            "point {:?}, not {:?}",
+            item.point,
+            point
+        );
+        assert!(
+            close(item.normal.0, normal.0) && close(item.normal.1, normal.1),
+            "normal {:?}, not {:?}",
+            item.normal,
+            normal
+        );
+        assert!(close(s, t), "t {}, not {}", s, t);
+    }
+
+    fn circle(cx: f64, r: f64) -> Box<dyn Shape + Sync> {
+        Box::new(Circle::new(cx, 0.5, r))
+    }
+
+    #[test]
+    fn circle_nearest() {
+        let c = Circle::new(0.5, 0.5, 0.2);
+        assert_hit(
+            c.nearest((0.0, 0.5), (1.0, 0.0), f64::INFINITY),
+            (0.3, 0.5),
+            (-1.0, 0.0),
+            0.3,
+        );
+        // from inside, the normal is still outwards
+        assert_hit(
+            c.nearest((0.5, 0.5), (1.0, 0.0), f64::INFINITY),
+            (0.7, 0.5),
+            (1.0, 0.0),
+            0.2,
+        );
+        // t is of the direction as it is
+        assert_hit(
+            c.nearest((0.0, 0.5), (2.0, 0.0), f64::INFINITY),
+            (0.3, 0.5),
+            (-1.0, 0.0),
+            0.15,
+        );
+        // behind, beside, and beyond t_max
+        assert!(c.nearest((1.0, 0.5), (1.0, 0.0), f64::INFINITY).is_none());
+        assert!(c.nearest((0.0, 0.0), (1.0, 0.0), f64::INFINITY).is_none());
+        assert!(c.nearest((0.0, 0.5), (1.0, 0.0), 0.3).is_none());
+    }
+
+    #[test]
+    fn polygon_nearest() {
+        let p = Polygon::rectangle(0.5, 0.5, 0.0, 0.1, 0.1);
+        assert_hit(
+            p.nearest((0.0, 0.5), (1.0, 0.0), f64::INFINITY),
+            (0.4, 0.5),
+            (-1.0, 0.0),
+            0.4,
+        );
+        assert_hit(
+            p.nearest((0.5, 0.5), (0.0, 1.0), f64::INFINITY),
+            (0.5, 0.6),
+            (0.0, 1.0),
+            0.1,
+        );
+        assert!(p.nearest((0.0, 0.5), (-1.0, 0.0), f64::INFINITY).is_none());
+        assert!(p.nearest((0.0, 0.5), (1.0, 0.0), 0.4).is_none());
+    }
+
+    #[test]
+    fn first_hit_skips_the_ones_not_kept() {
+        let c = Circle::new(0.5, 0.5, 0.2);
+        let hit = first_hit(&c, (0.0, 0.5), (1.0, 0.0), f64::INFINITY, |item| {
+            item.point.0 > 0.5
+        });
+        assert_hit(hit, (0.7, 0.5), (1.0, 0.0), 0.7);
+        assert!(first_hit(&c, (0.0, 0.5), (1.0, 0.0), 0.7, |item| item.point.0 > 0.5).is_none());
+        assert!(first_hit(&c, (0.0, 0.5), (1.0, 0.0), f64::INFINITY, |_| false).is_none());
+    }
+
+    #[test]
+    fn polygon_is_inside_with_vertical_edges() {
+        // an l, the vertical edges of x 1 and 2 end where the horizontal ones start
+        let l = Polygon::new(vec![
+            (0.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (0.0, 2.0),
+        ])
+        .unwrap();
+        // of the x of a vertical edge, above, beside and below it
+        assert!(l.is_inside((1.0, 0.5)));
+        assert!(!l.is_inside((1.0, -0.5)));
+        assert!(!l.is_inside((1.0, 2.5)));
+        assert!(!l.is_inside((2.0, 1.5)));
+        assert!(!l.is_inside((0.0, -0.5)));
+        assert!(!l.is_inside((0.0, 2.5)));
+        assert!(l.is_inside((0.5, 1.5)));
+        assert!(!l.is_inside((1.5, 1.5)));
+        let r = Polygon::rectangle(0.5, 0.5, 0.0, 0.1, 0.1);
+        assert!(r.is_inside((0.5, 0.5)));
+        assert!(!r.is_inside((0.4, 0.3)));
+        assert!(!r.is_inside((0.4, 0.7)));
+        assert!(!r.is_inside((0.6, 0.3)));
+    }
+
+    #[test]
+    fn union_nearest() {
+        // the nearer child after the farther one
+        let u = UnionShape {
+            c: vec![circle(0.8, 0.1), circle(0.3, 0.1)],
+        };
+        assert_hit(
+            u.nearest((0.0, 0.5), (1.0, 0.0), f64::INFINITY),
+            (0.2, 0.5),
+            (-1.0, 0.0),
+            0.2,
+        );
+        // the boundaries inside the other child are not hit
+        let u = UnionShape {
+            c: vec![circle(0.4, 0.1), circle(0.55, 0.1)],
+        };
+        assert_hit(
+            u.nearest((0.47, 0.5), (1.0, 0.0), f64::INFINITY),
+            (0.65, 0.5),
+            (1.0, 0.0),
+            0.18,
+        );
+        assert!(u.nearest((0.0, 0.5), (1.0, 0.0), 0.3).is_none());
+    }
+
+    #[test]
+    fn difference_nearest() {
+        let d = DifferenceShape {
+            a: circle(0.5, 0.2),
+            c: vec![circle(0.35, 0.1)],
+        };
+        // through the cut, onto the cutter turned inside out
+        assert_hit(
+            d.nearest((0.0, 0.5), (1.0, 0.0), f64::INFINITY),
+            (0.45, 0.5),
+            (-1.0, 0.0),
+            0.45,
+        );
+        assert_hit(
+            d.nearest((1.0, 0.5), (-1.0, 0.0), f64::INFINITY),
+            (0.7, 0.5),
+            (1.0, 0.0),
+            0.3,
+        );
+        assert!(d.nearest((0.0, 0.5), (1.0, 0.0), 0.4).is_none());
+    }
+
+    #[test]
+    fn complement_nearest() {
+        let c = ComplementShape::new(circle(0.5, 0.2));
+        assert_hit(
+            c.nearest((0.5, 0.5), (1.0, 0.0), f64::INFINITY),
+            (0.7, 0.5),
+            (-1.0, 0.0),
+            0.2,
+        );
+    }
+
+    #[test]
+    fn sdf_of_the_composites() {
+        let light = || -> Box<dyn Shape + Sync> {
+            Box::new(DirectionalLight {
+                d: 10.0,
+                nx: 1.0,
+                ny: 0.0,
+            })
+        };
+        assert!(light().as_sdf().is_none());
+        let u = UnionShape {
+            c: vec![circle(0.5, 0.2), circle(0.9, 0.1)],
+        };
+        let sdf = u.as_sdf().expect("an sdf");
+        assert!(close(sdf.sdf((0.5, 0.5)), -0.2));
+        assert!(UnionShape {
+            c: vec![circle(0.5, 0.2), light()],
+        }
+        .as_sdf()
+        .is_none());
+        assert!(ComplementShape::new(light()).as_sdf().is_none());
+        // the cuts of a smooth subtract are marched without the light
+        let s = SmoothSubtractShape {
+            a: circle(0.5, 0.2),
+            c: vec![light()],
+            k: 0.1,
+        };
+        assert!(s.as_sdf().is_none());
+        assert!(close(s.sdf((0.5, 0.5)), -0.2));
+    }
+
+    #[test]
+    fn march_to_the_end_of_the_box() {
+        // far from the origin, and from one another
+        let u = SmoothUnionShape {
+            c: vec![
+                Box::new(Circle::new(20.0, 0.5, 1.0)),
+                Box::new(Circle::new(40.0, 0.5, 1.0)),
+            ],
+            k: 0.1,
+        };
+        let hits = u.intersect((0.0, 0.5), (1.0, 0.0));
+        let xs: Vec<f64> = hits.iter().map(|item| item.point.0).collect();
+        assert_eq!(xs.len(), 4, "{:?}", xs);
+        for (x, expected) in xs.iter().zip([19.0, 21.0, 39.0, 41.0]) {
+            assert!((x - expected).abs() < 1e-4, "{:?}", xs);
+        }
+        assert!(u.intersect((0.0, 5.0), (1.0, 0.0)).is_empty());
+        assert!(u.intersect((0.0, 0.5), (-1.0, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn smooth_min_without_a_blend() {
+        assert_eq!(smooth_min(1.0, 2.0, 0.0), 1.0);
+        assert_eq!(smooth_min(2.0, -1.0, 0.0), -1.0);
+        assert_eq!(smooth_min(1.0, 2.0, -0.5), 1.0);
+        // within k the blend is below both
+        assert!(smooth_min(1.0, 1.0, 0.5) < 1.0);
+        assert_eq!(smooth_min(1.0, 2.0, 0.5), 1.0);
+        let u = SmoothUnionShape {
+            c: vec![circle(0.35, 0.1), circle(0.65, 0.1)],
+            k: 0.0,
+        };
+        assert!(close(u.sdf((0.5, 0.5)), 0.05));
+        assert!(u.is_inside((0.3, 0.5)));
+        assert!(!u.is_inside((0.5, 0.5)));
+        assert_eq!(u.intersect((0.0, 0.5), (1.0, 0.0)).len(), 4);
+    }
+
+    #[test]
+    fn sector_with_a_negative_sweep() {
+        let clockwise = Sector::new(0.5, 0.5, 0.3, 90.0, -60.0);
+        let counterclockwise = Sector::new(0.5, 0.5, 0.3, 30.0, 60.0);
+        // at 60, 0 and 120 degrees, y is down
+        for p in [(0.6, 0.33), (0.7, 0.5), (0.4, 0.33)] {
+            assert_eq!(
+                clockwise.is_inside(p),
+                counterclockwise.is_inside(p),
+                "{:?}",
+                p
+            );
+        }
+        assert!(clockwise.is_inside((0.6, 0.33)));
+        assert!(!clockwise.is_inside((0.7, 0.5)));
+    }
+
+    #[test]
+    fn nearest4_is_nearest() {
+        let shapes: [Box<dyn Shape + Sync>; 2] = [
+            Box::new(Circle::new(0.5, 0.5, 0.2)),
+            Box::new(Polygon::regular(0.5, 0.5, 0.2, 5, 10.0).unwrap()),
+        ];
+        let p = [(0.0, 0.5), (0.5, 0.5), (1.0, 0.0), (0.5, 1.0)];
+        let d = [(1.0, 0.0), (0.3, -0.4), (1.0, 0.0), (0.0, -2.0)];
+        let t_max = [f64::INFINITY, f64::INFINITY, f64::INFINITY, 0.1];
+        for shape in &shapes {
+            let hits = shape.nearest4(p, d, t_max);
+            for i in 0..4 {
+                match shape.nearest(p[i], d[i], t_max[i]) {
+                    Some((item, t)) => assert_hit(hits[i], item.point, item.normal, t),
+                    None => assert!(hits[i].is_none(), "{} of {}", i, shape.kind()),
+                }
+            }
+        }
+    }
+}
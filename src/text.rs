@@ -0,0 +1,107 @@
+use std::fs;
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use crate::{error::Error, shapes::Contours};
+
+// segments used for every quadratic or cubic curve of a glyph
+const CURVE_PIECES: usize = 8;
+
+struct Outline {
+    contours: Vec<Vec<(f64, f64)>>,
+    current: Vec<(f64, f64)>,
+    last: (f64, f64),
+    // font units -> world
+    scale: f64,
+    x: f64,
+    y: f64,
+}
+
+impl Outline {
+    fn point(&self, x: f32, y: f32) -> (f64, f64) {
+        (
+            self.x + x as f64 * self.scale,
+            self.y - y as f64 * self.scale,
+        )
+    }
+
+    fn push(&mut self, p: (f64, f64)) {
+        self.current.push(p);
+        self.last = p;
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.close();
+        let p = self.point(x, y);
+        self.push(p);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.push(p);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let p0 = self.last;
+        let p1 = self.point(x1, y1);
+        let p2 = self.point(x, y);
+        for i in 1..=CURVE_PIECES {
+            let u = i as f64 / CURVE_PIECES as f64;
+            let v = 1.0 - u;
+            self.push((
+                v * v * p0.0 + 2.0 * v * u * p1.0 + u * u * p2.0,
+                v * v * p0.1 + 2.0 * v * u * p1.1 + u * u * p2.1,
+            ));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let p0 = self.last;
+        let p1 = self.point(x1, y1);
+        let p2 = self.point(x2, y2);
+        let p3 = self.point(x, y);
+        for i in 1..=CURVE_PIECES {
+            let u = i as f64 / CURVE_PIECES as f64;
+            let v = 1.0 - u;
+            let (a, b, c, d) = (v * v * v, 3.0 * v * v * u, 3.0 * v * u * u, u * u * u);
+            self.push((
+                a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+                a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+            ));
+        }
+    }
+
+    fn close(&mut self) {
+        // the last point usually repeats the first one
+        if self.current.len() > 1 && self.current.first() == self.current.last() {
+            self.current.pop();
+        }
+        if !self.current.is_empty() {
+            self.contours.push(std::mem::take(&mut self.current));
+        }
+    }
+}
+
+// the outlines of a line of text, (x, y) is the start of the baseline and size the height of an em
+pub fn text(font: &str, text: &str, size: f64, x: f64, y: f64) -> Result<Contours, Error> {
+    let data = fs::read(font).map_err(|e| Error::Io(font.to_string(), e))?;
+    let face =
+        Face::from_slice(&data, 0).map_err(|e| Error::Parse(font.to_string(), e.to_string()))?;
+    let mut outline = Outline {
+        contours: Vec::new(),
+        current: Vec::new(),
+        last: (0.0, 0.0),
+        scale: size / face.units_per_em() as f64,
+        x,
+        y,
+    };
+    for c in text.chars() {
+        // the .notdef glyph, usually a box, with its advance for the ones the font doesn't have
+        let id = face.glyph_index(c).unwrap_or(GlyphId(0));
+        face.outline_glyph(id, &mut outline);
+        outline.close();
+        outline.x += face.glyph_hor_advance(id).unwrap_or(0) as f64 * outline.scale;
+    }
+    Ok(Contours::new(outline.contours))
+}
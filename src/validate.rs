@@ -0,0 +1,541 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{
+    config::{
+        shape_error, BackgroundJson, ColorJson, Config, EmissionJson, EntityJson, LightJson,
+        MaterialJson, MediumJson, ShapeJson,
+    },
+    film::{Dither, ToneMap},
+    render::{Aa, Engine, Fresnel, Integrator, Mode, Sampler},
+};
+
+// the fields every config must have
+const REQUIRED: [&str; 7] = [
+    "enable",
+    "out",
+    "width",
+    "height",
+    "stratification",
+    "max_depth",
+    "scenes",
+];
+
+// the fields of the shapes that are lengths, none of them negative. k is the size of the blend
+// of the smooth ones
+const LENGTHS: [&str; 9] = ["r", "r1", "r2", "rx", "ry", "size", "w", "l", "k"];
+
+// the errors of an expanded config, each as the json pointer of the value and the reason.
+// empty when the config can be read
+pub fn validate(value: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    let object = match value.as_object() {
+        Some(object) => object,
+        None => return vec![": should be an object with the fields of a config".to_string()],
+    };
+    for key in REQUIRED {
+        if !object.contains_key(key) {
+            errors.push(format!(": missing field `{}`", key));
+        }
+    }
+    for key in ["width", "height"] {
+        if let Some(v) = object.get(key) {
+            if v.as_u64() == Some(0) {
+                errors.push(format!(
+                    "/{}: should be at least 1, the image has no pixels",
+                    key
+                ));
+            }
+        }
+    }
+    if let Some(v) = object.get("stratification") {
+        if v.as_u64() == Some(0) {
+            errors.push("/stratification: should be at least 1, no rays are traced".to_string());
+        }
+    }
+    if let Some(v) = object.get("max_seconds") {
+        if v.as_f64().is_some_and(|s| s <= 0.0) {
+            errors.push("/max_seconds: should be above 0, no rays are traced".to_string());
+        }
+    }
+    if let Some(Value::Array(levels)) = object.get("pyramid") {
+        for (i, level) in levels.iter().enumerate() {
+            if level.get("width").and_then(Value::as_u64) == Some(0) {
+                errors.push(format!(
+                    "/pyramid/{}/width: should be at least 1, the image has no pixels",
+                    i
+                ));
+            }
+        }
+    }
+    for key in ["world_width", "world_height"] {
+        if let Some(v) = object.get(key) {
+            if v.as_f64().is_some_and(|s| s <= 0.0) {
+                errors.push(format!(
+                    "/{}: should be above 0, the world has no size in the image",
+                    key
+                ));
+            }
+        }
+    }
+    if let Some(v) = object.get("camera").and_then(|c| c.get("zoom")) {
+        if v.as_f64().is_some_and(|z| z <= 0.0) {
+            errors.push(
+                "/camera/zoom: should be above 0, the image has no size in the world".to_string(),
+            );
+        }
+    }
+    if let Some(v) = object.get("fps") {
+        if v.as_f64().is_some_and(|fps| fps <= 0.0 || !fps.is_finite()) {
+            errors.push("/fps: should be above 0, the frames have no duration".to_string());
+        }
+    }
+    if let Some(v) = object.get("bit_depth").and_then(Value::as_u64) {
+        if v != 8 && v != 16 {
+            errors.push(format!("/bit_depth: should be 8 or 16, is {}", v));
+        }
+    }
+    if let Some(v) = object.get("gamma").and_then(Value::as_f64) {
+        if v <= 0.0 || !v.is_finite() {
+            errors.push(format!("/gamma: should be above 0, is {}", v));
+        }
+    }
+    match object.get("scenes") {
+        Some(Value::Array(scenes)) => {
+            for (i, entity) in scenes.iter().enumerate() {
+                let path = format!("/scenes/{}", i);
+                let count = errors.len();
+                match entity.get("shape") {
+                    Some(shape) => shape_errors(shape, &format!("{}/shape", path), &mut errors),
+                    None => errors.push(format!("{}: missing field `shape`", path)),
+                }
+                for key in ["material", "back"] {
+                    if let Some(material) = entity.get(key) {
+                        material_errors(material, &format!("{}/{}", path, key), &mut errors);
+                    }
+                }
+                field::<MaterialJson>(entity, "material", &path, &mut errors);
+                field::<MaterialJson>(entity, "back", &path, &mut errors);
+                field::<EmissionJson>(entity, "emissive", &path, &mut errors);
+                field::<ColorJson>(entity, "diffuse", &path, &mut errors);
+                field::<ColorJson>(entity, "absorption", &path, &mut errors);
+                if errors.len() == count {
+                    check::<EntityJson>(entity, &path, &mut errors);
+                }
+            }
+        }
+        Some(_) => errors.push("/scenes: should be a list of entities".to_string()),
+        None => {}
+    }
+    if let Some(Value::Array(lights)) = object.get("lights") {
+        for (i, light) in lights.iter().enumerate() {
+            check::<LightJson>(light, &format!("/lights/{}", i), &mut errors);
+        }
+    }
+    if let Some(medium) = object.get("medium").filter(|m| !m.is_null()) {
+        check::<MediumJson>(medium, "/medium", &mut errors);
+    }
+    field::<BackgroundJson>(value, "background", "", &mut errors);
+    field::<Engine>(value, "engine", "", &mut errors);
+    field::<Mode>(value, "mode", "", &mut errors);
+    field::<Fresnel>(value, "fresnel", "", &mut errors);
+    field::<Sampler>(value, "sampler", "", &mut errors);
+    field::<Aa>(value, "aa", "", &mut errors);
+    field::<Integrator>(value, "integrator", "", &mut errors);
+    field::<ToneMap>(value, "tone_map", "", &mut errors);
+    field::<Dither>(value, "dither", "", &mut errors);
+    // the rest
+    if errors.is_empty() {
+        check::<Config>(value, "", &mut errors);
+    }
+    errors
+}
+
+fn check<T: DeserializeOwned>(value: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Err(e) = serde_json::from_value::<T>(value.clone()) {
+        errors.push(format!("{}: {}", path, e));
+    }
+}
+
+// the field of the object, when it's there
+fn field<T: DeserializeOwned>(object: &Value, key: &str, path: &str, errors: &mut Vec<String>) {
+    if let Some(value) = object.get(key).filter(|v| !v.is_null()) {
+        check::<T>(value, &format!("{}/{}", path, key), errors);
+    }
+}
+
+// down to the shapes of the composite ones, so that the error is at the innermost shape
+fn shape_errors(shape: &Value, path: &str, errors: &mut Vec<String>) {
+    let (name, fields) = match shape.as_object() {
+        Some(object) if object.len() == 1 => object.iter().next().unwrap(),
+        _ => {
+            errors.push(format!(
+                "{}: should be an object with the type of the shape as its only key",
+                path
+            ));
+            return;
+        }
+    };
+    let path = format!("{}/{}", path, name);
+    let count = errors.len();
+    // of the values given to the constructor
+    let mut constructor = path.clone();
+    let children: Vec<(String, &Value)> = match (name.as_str(), fields) {
+        ("union" | "intersect", Value::Array(list)) => list
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (format!("{}/{}", path, i), s))
+            .collect(),
+        ("complement", s) => vec![(path.clone(), s)],
+        ("transform", _) => fields
+            .get("shape")
+            .map(|s| vec![(format!("{}/shape", path), s)])
+            .unwrap_or_default(),
+        ("difference" | "smooth_subtract" | "smooth_union", _) => {
+            length_errors(fields, &path, errors);
+            let mut children = Vec::new();
+            if let Some(base) = fields.get("base") {
+                children.push((format!("{}/base", path), base));
+            }
+            for key in ["cutters", "shapes"] {
+                if let Some(Value::Array(list)) = fields.get(key) {
+                    for (i, s) in list.iter().enumerate() {
+                        children.push((format!("{}/{}/{}", path, key, i), s));
+                    }
+                }
+            }
+            children
+        }
+        // the variant of the polygon
+        ("polygon", Value::Object(variant)) if variant.len() == 1 => {
+            let (kind, fields) = variant.iter().next().unwrap();
+            constructor = format!("{}/{}", path, kind);
+            length_errors(fields, &constructor, errors);
+            Vec::new()
+        }
+        _ => {
+            length_errors(fields, &path, errors);
+            Vec::new()
+        }
+    };
+    for (path, child) in children {
+        shape_errors(child, &path, errors);
+    }
+    if errors.len() == count {
+        match serde_json::from_value::<ShapeJson>(shape.clone()) {
+            Ok(shape) => {
+                if let Some(e) = shape_error(shape) {
+                    errors.push(format!("{}: {}", constructor, e));
+                }
+            }
+            Err(e) => {
+                // the path of the type, for an unknown one
+                let e = e.to_string();
+                let path = match path.rsplit_once('/') {
+                    Some((parent, _)) if e.starts_with("unknown variant") => parent,
+                    _ => &path,
+                };
+                errors.push(format!("{}: {}", path, e));
+            }
+        }
+    }
+}
+
+// the values of the materials out of their ranges, down to the ones of a mix
+fn material_errors(material: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(Value::Array(list)) = material.get("mix") {
+        for (i, m) in list.iter().enumerate() {
+            material_errors(m, &format!("{}/mix/{}", path, i), errors);
+        }
+    }
+    if let Some(period) = material.pointer("/grating/period").and_then(Value::as_f64) {
+        if period <= 0.0 || !period.is_finite() {
+            errors.push(format!(
+                "{}/grating/period: should be above 0, is {}",
+                path, period
+            ));
+        }
+    }
+}
+
+fn length_errors(fields: &Value, path: &str, errors: &mut Vec<String>) {
+    let object = match fields.as_object() {
+        Some(object) => object,
+        None => return,
+    };
+    for key in LENGTHS {
+        if let Some(v) = object.get(key).and_then(|v| v.as_f64()) {
+            if v < 0.0 {
+                errors.push(format!(
+                    "{}/{}: should not be negative, is {}",
+                    path, key, v
+                ));
+            }
+        }
+    }
+    // the half sizes
+    if path.ends_with("/rounded_rectangle") {
+        for key in ["sx", "sy"] {
+            if let Some(v) = object.get(key).and_then(|v| v.as_f64()) {
+                if v <= 0.0 || !v.is_finite() {
+                    errors.push(format!("{}/{}: should be above 0, is {}", path, key, v));
+                }
+            }
+        }
+    }
+    if let Some(r) = object.get("r").and_then(|v| v.as_f64()) {
+        if r == 0.0 && path.ends_with("/circle") {
+            errors.push(format!(
+                "{}/r: should be positive, the circle is empty",
+                path
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> Value {
+        json!({
+            "enable": true,
+            "out": "a.png",
+            "width": 8,
+            "height": 8,
+            "stratification": 1,
+            "max_depth": 1,
+            "scenes": [{
+                "shape": {"circle": {"cx": 0.5, "cy": 0.5, "r": 0.2}},
+                "emissive": {"grey": 2},
+            }],
+        })
+    }
+
+    // the errors of the config with the value at the pointer
+    fn errors(pointer: &str, v: Value) -> Vec<String> {
+        let mut value = config();
+        *value.pointer_mut(pointer).unwrap() = v;
+        validate(&value)
+    }
+
+    // the errors of the config with the first entity
+    fn entity_errors(entity: Value) -> Vec<String> {
+        errors("/scenes/0", entity)
+    }
+
+    #[test]
+    fn valid() {
+        assert_eq!(validate(&config()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn not_an_object() {
+        assert_eq!(
+            validate(&json!([])),
+            [": should be an object with the fields of a config"]
+        );
+    }
+
+    #[test]
+    fn missing_fields() {
+        let mut value = config();
+        let object = value.as_object_mut().unwrap();
+        object.remove("out");
+        object.remove("scenes");
+        assert_eq!(
+            validate(&value),
+            [": missing field `out`", ": missing field `scenes`"]
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(
+            errors("/width", json!(0)),
+            ["/width: should be at least 1, the image has no pixels"]
+        );
+        assert_eq!(
+            errors("/stratification", json!(0)),
+            ["/stratification: should be at least 1, no rays are traced"]
+        );
+        assert_eq!(
+            errors("/scenes", json!({})),
+            ["/scenes: should be a list of entities"]
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        for fps in [json!(0), json!(-2.5)] {
+            let mut value = config();
+            value["fps"] = fps;
+            assert_eq!(
+                validate(&value),
+                ["/fps: should be above 0, the frames have no duration"]
+            );
+        }
+        let mut value = config();
+        value["bit_depth"] = json!(12);
+        assert_eq!(validate(&value), ["/bit_depth: should be 8 or 16, is 12"]);
+        for gamma in [0, -2] {
+            let mut value = config();
+            value["gamma"] = json!(gamma);
+            assert_eq!(
+                validate(&value),
+                [format!("/gamma: should be above 0, is {}", gamma)]
+            );
+        }
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(
+            errors("/scenes/0/shape/circle/r", json!(-1)),
+            ["/scenes/0/shape/circle/r: should not be negative, is -1"]
+        );
+        assert_eq!(
+            errors("/scenes/0/shape/circle/r", json!(0)),
+            ["/scenes/0/shape/circle/r: should be positive, the circle is empty"]
+        );
+        let rectangle = json!({"rounded_rectangle": {
+            "cx": 0.5, "cy": 0.5, "e": 0, "sx": 0, "sy": 0.1, "r": 0.05,
+        }});
+        assert_eq!(
+            errors("/scenes/0/shape", rectangle),
+            ["/scenes/0/shape/rounded_rectangle/sx: should be above 0, is 0"]
+        );
+    }
+
+    #[test]
+    fn constructors() {
+        let star = json!({"polygon": {"star": {"cx": 0.5, "cy": 0.5, "r": 0.2, "n": 1, "e": 0}}});
+        assert_eq!(
+            errors("/scenes/0/shape", star),
+            ["/scenes/0/shape/polygon/star: too few points for a star, 1, at least 5"]
+        );
+        let crescent =
+            json!({"polygon": {"crescent": {"cx": 0.5, "cy": 0.5, "r": 0.2, "d": 0, "e": 0}}});
+        assert_eq!(
+            errors("/scenes/0/shape", crescent),
+            ["/scenes/0/shape/polygon/crescent: the offset of the crescent must be in (0, 2r), is 0"]
+        );
+        let superellipse = json!({"superellipse": {
+            "cx": 0.5, "cy": 0.5, "rx": 0.2, "ry": 0.1, "n": 0, "e": 0,
+        }});
+        assert_eq!(
+            errors("/scenes/0/shape", superellipse),
+            ["/scenes/0/shape/superellipse: the exponent of the superellipse must be positive, is 0"]
+        );
+        let shape = json!({"union": [
+            {"polyline": []},
+            {"bezier_path": [[0, 0], [1, 0], [1, 1], [0, 1]]},
+        ]});
+        assert_eq!(
+            errors("/scenes/0/shape", shape),
+            [
+                "/scenes/0/shape/union/0/polyline: too few points for a polyline, 0",
+                "/scenes/0/shape/union/1/bezier_path: the number of control points must be a multiple of 3, is 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn scale() {
+        let shape = json!({"transform": {
+            "shape": {"circle": {"cx": 0, "cy": 0, "r": 0.1}},
+            "scale": [0, 0],
+        }});
+        assert_eq!(
+            errors("/scenes/0/shape", shape),
+            ["/scenes/0/shape/transform: the scale of a transform must be finite and not 0, is (0, 0)"]
+        );
+    }
+
+    #[test]
+    fn blend() {
+        let shape = json!({"smooth_union": {
+            "shapes": [{"circle": {"cx": 0.4, "cy": 0.5, "r": 0.1}}],
+            "k": -0.1,
+        }});
+        assert_eq!(
+            errors("/scenes/0/shape", shape),
+            ["/scenes/0/shape/smooth_union/k: should not be negative, is -0.1"]
+        );
+    }
+
+    #[test]
+    fn innermost_shape() {
+        let shape = json!({"union": [
+            {"circle": {"cx": 0, "cy": 0, "r": 1}},
+            {"difference": {
+                "base": {"circle": {"cx": 0, "cy": 0, "r": 1}},
+                "cutters": [{"polygon": {"regular": {"cx": 0, "cy": 0, "r": -1, "n": 3, "e": 0}}}],
+            }},
+        ]});
+        assert_eq!(
+            errors("/scenes/0/shape", shape),
+            ["/scenes/0/shape/union/1/difference/cutters/0/polygon/regular/r: should not be negative, is -1"]
+        );
+        let shape = json!({"union": [{"square": {}}]});
+        let e = errors("/scenes/0/shape", shape);
+        assert_eq!(e.len(), 1);
+        assert!(
+            e[0].starts_with("/scenes/0/shape/union/0: unknown variant `square`"),
+            "{}",
+            e[0]
+        );
+        assert_eq!(
+            errors("/scenes/0/shape", json!({"circle": {}, "ellipse": {}})),
+            ["/scenes/0/shape: should be an object with the type of the shape as its only key"]
+        );
+    }
+
+    #[test]
+    fn grating_period() {
+        let e = entity_errors(json!({
+            "shape": {"circle": {"cx": 0.5, "cy": 0.5, "r": 0.2}},
+            "material": {"grating": {"period": -1}},
+            "back": {"mix": [{"mirror": {"reflectivity": 1}}, {"grating": {"period": 0}}]},
+        }));
+        assert_eq!(
+            e,
+            [
+                "/scenes/0/material/grating/period: should be above 0, is -1",
+                "/scenes/0/back/mix/1/grating/period: should be above 0, is 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn entity_fields() {
+        let e = entity_errors(json!({
+            "shape": {"circle": {"cx": 0.5, "cy": 0.5, "r": 0.2}},
+            "material": {"mirror": {}},
+        }));
+        assert_eq!(e.len(), 1);
+        assert!(
+            e[0].starts_with("/scenes/0/material: missing field `reflectivity`"),
+            "{}",
+            e[0]
+        );
+        assert_eq!(
+            entity_errors(json!({"emissive": {"grey": 1}})),
+            ["/scenes/0: missing field `shape`"]
+        );
+    }
+
+    #[test]
+    fn enums() {
+        let mut value = config();
+        value["tone_map"] = json!("nope");
+        let e = validate(&value);
+        assert_eq!(e.len(), 1);
+        assert!(
+            e[0].starts_with("/tone_map: unknown variant `nope`"),
+            "{}",
+            e[0]
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{config, error::Error, render::Renderer};
+
+// the pixels of the image of the config, 4 bytes of rgba each, row by row. the config is
+// the json of one, or a list of them for the first, without files to include. the first frame
+// of an animation
+#[wasm_bindgen]
+pub fn render_to_rgba(json: &str) -> Result<Vec<u8>, JsValue> {
+    rgba(json).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+fn rgba(json: &str) -> Result<Vec<u8>, Error> {
+    let mut item = config::from_str(json)?;
+    let mut scene = item.scene(None)?;
+    let film = Renderer::new(item.settings())
+        .photons(item.photons, item.photon_radius)
+        .render(&mut scene);
+    let image = film.to_image(item.exposure, item.tone_map, item.gamma, item.dither);
+    Ok(image
+        .pixels()
+        .flat_map(|p| [p[0], p[1], p[2], 255])
+        .collect())
+}
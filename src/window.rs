@@ -0,0 +1,56 @@
+use image::RgbImage;
+use minifb::{Key, KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
+use std::time::Duration;
+
+use crate::{error::Error, film::Film, render::Live};
+
+// shows the film of live as it is rendered, until done. s saves the image so far with save,
+// escape or closing the window stops the render. image gives the colors of the film
+pub fn show<I, S, D>(
+    title: &str,
+    (width, height): (u32, u32),
+    live: &Live,
+    image: I,
+    mut save: S,
+    done: D,
+) -> Result<(), Error>
+where
+    I: Fn(&Film) -> RgbImage,
+    S: FnMut(&Film),
+    D: Fn() -> bool,
+{
+    let error = |e: minifb::Error| Error::Invalid(format!("the window: {}", e));
+    let (w, h) = (width as usize, height as usize);
+    let mut window = Window::new(
+        title,
+        w,
+        h,
+        WindowOptions {
+            resize: true,
+            scale: Scale::FitScreen,
+            scale_mode: ScaleMode::AspectRatioStretch,
+            ..WindowOptions::default()
+        },
+    )
+    .map_err(error)?;
+    window.limit_update_rate(Some(Duration::from_millis(100)));
+    let mut buffer = vec![0u32; w * h];
+    while !done() {
+        if !window.is_open() || window.is_key_down(Key::Escape) {
+            live.stop();
+            return Ok(());
+        }
+        if window.is_key_pressed(Key::S, KeyRepeat::No) {
+            save(&live.film.lock().unwrap());
+        }
+        let shown = image(&live.film.lock().unwrap());
+        // before the render has begun the film is empty
+        if shown.dimensions() == (width, height) {
+            for (pixel, p) in buffer.iter_mut().zip(shown.pixels()) {
+                *pixel = u32::from_be_bytes([0, p[0], p[1], p[2]]);
+            }
+        }
+        window.update_with_buffer(&buffer, w, h).map_err(error)?;
+    }
+    Ok(())
+}
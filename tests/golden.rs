@@ -0,0 +1,34 @@
+use std::{env, path::Path};
+
+use imprint_of_light::golden::{self, Tolerance, Verdict};
+
+// the scenes of test-scenes against their references in test-scenes/golden, saved again by
+// BLESS=1. the renders are left in the temporary directory of the tests
+#[test]
+fn test_scenes() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(golden::DIR);
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join(golden::DIR);
+    let bless = env::var_os("BLESS").is_some();
+    let outcomes = golden::run(&dir, &out, &Tolerance::default(), bless).unwrap();
+    assert!(!outcomes.is_empty(), "no scenes in {}", dir.display());
+    let failed: Vec<String> = outcomes
+        .iter()
+        .filter(|outcome| !outcome.passed())
+        .map(|outcome| match &outcome.verdict {
+            Ok(Verdict::Different(d)) => format!(
+                "{}: mean {:.4}, {:.2}% of the pixels differ",
+                outcome.name,
+                d.mean,
+                d.differing * 100.0
+            ),
+            Ok(Verdict::Missing) => format!(
+                "{}: no reference, saved by BLESS=1, see {}",
+                outcome.name,
+                out.display()
+            ),
+            Err(e) => format!("{}: {}", outcome.name, e),
+            Ok(_) => unreachable!(),
+        })
+        .collect();
+    assert!(failed.is_empty(), "\n{}", failed.join("\n"));
+}